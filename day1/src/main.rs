@@ -68,7 +68,7 @@ pub struct FuelCalculator<N: Borrow<u32>, T: Iterator<Item = N>> {
     iter: T,
 }
 
-impl<'a> FuelCalculator<u32, Once<u32>> {
+impl FuelCalculator<u32, Once<u32>> {
     /// Creates fuel calculator for a single module with a given `mass`
     pub fn for_module(mass: u32) -> FuelCalculator<u32, Once<u32>> {
         FuelCalculator { iter: once(mass) }
@@ -128,7 +128,7 @@ mod test {
 
     #[test]
     fn check_calculate_required_fuel() {
-        vec![(14, 2), (12, 2), (1969, 654), (100756, 33583)]
+        [(14, 2), (12, 2), (1969, 654), (100756, 33583)]
             .iter()
             .for_each(|(mass, expected): &(u32, u32)| {
                 let calc = FuelCalculator::for_module(*mass);
@@ -138,7 +138,7 @@ mod test {
 
     #[test]
     fn check_calculate_total_fuel() {
-        vec![(14, 2), (12, 2), (1969, 966), (100756, 50346)]
+        [(14, 2), (12, 2), (1969, 966), (100756, 50346)]
             .iter()
             .for_each(|(mass, expected): &(u32, u32)| {
                 let calc = FuelCalculator::for_module(*mass);
@@ -1,37 +1,31 @@
 use std::clone::Clone;
 use std::fs::read_to_string;
 
+use intcode::batch::{Batch, Job};
 use intcode::Program;
 
 static INPUT_PATH: &str = "day2/data/input.txt";
 
-fn read_input() -> Vec<i32> {
+fn read_input() -> Vec<i64> {
     read_to_string(INPUT_PATH)
         .unwrap_or_else(|e| panic!("Error reading file {}: {}", INPUT_PATH, e))
         .split(",")
         .map(|s| {
-            s.parse::<i32>()
+            s.parse::<i64>()
                 .unwrap_or_else(|e| panic!("Unable to convert {} to u32: {}", s, e))
         })
         .collect()
 }
 
-fn find_noun_verb(program: &Vec<i32>, expected: i32) -> Option<(i32, i32)> {
-    for i in 0..99 {
-        for j in 0..99 {
-            let mut p2_in = program.clone();
-            p2_in[1] = i;
-            p2_in[2] = j;
+fn find_noun_verb(program: &[i64], expected: i64) -> Option<(i64, i64)> {
+    let jobs =
+        (0..99).flat_map(|i| (0..99).map(move |j| Job::new().with_patch(1, i).with_patch(2, j)));
+    let found = Batch::new(program)
+        .run_until(jobs, |outcome| outcome.memory[0] == expected)
+        .pop()
+        .filter(|outcome| outcome.memory[0] == expected)?;
 
-            Program::new(&mut p2_in).run();
-
-            if *p2_in.get(0).expect("get(0)") == expected {
-                return Some((i, j));
-            }
-        }
-    }
-
-    None
+    Some((found.job.patches[0].1, found.job.patches[1].1))
 }
 
 fn main() {
@@ -41,7 +35,7 @@ fn main() {
     p1_in[2] = 2;
 
     Program::new(&mut p1_in).run();
-    println!("Program output is: {}", p1_in.get(0).expect("get(0)"));
+    println!("Program output is: {}", p1_in.first().expect("get(0)"));
 
     let pair = find_noun_verb(&input, 19_690_720).unwrap();
     println!("Noun and verb result: {}", 100 * pair.0 + pair.1);
@@ -50,10 +44,11 @@ fn main() {
 #[cfg(test)]
 mod test {
     use super::*;
+    use intcode::dump;
 
     #[test]
     fn check_day2_examples() {
-        vec![
+        [
             (vec![1, 0, 0, 0, 99], vec![2, 0, 0, 0, 99]),
             (vec![2, 3, 0, 3, 99], vec![2, 3, 0, 6, 99]),
             (vec![2, 4, 4, 5, 99, 0], vec![2, 4, 4, 5, 99, 9801]),
@@ -63,9 +58,9 @@ mod test {
             ),
         ]
         .iter_mut()
-        .for_each(|(input, out): &mut (Vec<i32>, Vec<i32>)| {
+        .for_each(|(input, out): &mut (Vec<i64>, Vec<i64>)| {
             Program::new(input).run();
-            assert_eq!(input, out);
+            dump::assert_same(out, input);
         });
     }
 }
@@ -95,7 +95,7 @@ fn into_absolute_coords(w: &Wire) -> Vec<Section> {
     w.iter()
         .scan(State::empty(), |state, p| {
             let dest = Position::from((state.pos.x + p.0, state.pos.y + p.1));
-            let res = Section::new(state.pos, Position::from(dest), state.dist);
+            let res = Section::new(state.pos, dest, state.dist);
             state.pos = dest;
             state.dist += p.0.abs() + p.1.abs();
             Some(res)
@@ -113,12 +113,12 @@ impl Intersection {
     fn empty() -> Intersection {
         Intersection {
             pos: Position::from((0, 0)),
-            dist: std::i32::MAX,
+            dist: i32::MAX,
         }
     }
 }
 
-fn find_intesections(wire1: &Vec<Section>, wire2: &Vec<Section>) -> Vec<Intersection> {
+fn find_intesections(wire1: &[Section], wire2: &[Section]) -> Vec<Intersection> {
     wire1
         .iter()
         .skip(1)
@@ -228,7 +228,7 @@ mod test {
 
     #[test]
     fn test_closest_examples() {
-        vec![
+        [
             (
                 "R75,D30,R83,U83,L12,D49,R71,U7,L72\nU62,R66,U55,R34,D71,R55,D58,R83",
                 159,
@@ -244,7 +244,7 @@ mod test {
 
     #[test]
     fn test_shortest_examples() {
-        vec![
+        [
             ("U3,L5\nL2,U1,R1,U1,L1,U3", 12),
             ("U10,L15\nL10,U15", 40),
             ("D10,L15\nL10,D15", 40),
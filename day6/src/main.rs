@@ -8,6 +8,7 @@ use std::rc::Rc;
 
 const INPUT_PATH: &str = "day6/data/input.txt";
 
+#[allow(clippy::derive_ord_xor_partial_ord)]
 #[derive(Eq, Ord)]
 struct TreeNode<T> {
     value: T,
@@ -170,7 +171,7 @@ impl<T: Eq + Clone + Ord> Tree<T> {
     }
 }
 
-fn read_input<'a>() -> Result<Tree<String>, std::io::Error> {
+fn read_input() -> Result<Tree<String>, std::io::Error> {
     let mut tree = Tree::new();
 
     let file = File::open(INPUT_PATH)?;
@@ -181,7 +182,7 @@ fn read_input<'a>() -> Result<Tree<String>, std::io::Error> {
         .map(|l| l.expect("BufRead::lines()::[item]::unwrap()"))
         .map(|l| l.split(')').map(String::from).collect::<Vec<String>>())
         .for_each(|data| {
-            let left = data.get(0).unwrap();
+            let left = data.first().unwrap();
             let right = data.get(1).unwrap();
 
             tree.insert(right, left);
@@ -3,42 +3,44 @@ extern crate permutations;
 
 use std::fs::read_to_string;
 
+use intcode::batch::Batch;
 use intcode::Program;
 use permutations::*;
 
 const INPUT_PATH: &str = "day7/data/input.txt";
 
-fn read_input() -> Result<Vec<i32>, std::io::Error> {
+fn read_input() -> Result<Vec<i64>, std::io::Error> {
     let program = read_to_string(INPUT_PATH)?
         .split(',')
-        .map(|v| v.parse::<i32>().expect("i32::parse"))
-        .collect::<Vec<i32>>();
+        .map(|v| v.parse::<i64>().expect("i64::parse"))
+        .collect::<Vec<i64>>();
 
     Ok(program)
 }
 
-fn run_amplifiers(mem: &[i32], phase_settings: &[i32]) -> i32 {
-    phase_settings.iter().fold(0i32, |acc, s| {
-        let mut mem: Box<[i32]> = Box::from(mem);
-        let p = Program::new(mem.as_mut())
+fn run_amplifiers(mem: &[i64], phase_settings: &[i64]) -> i64 {
+    phase_settings.iter().fold(0i64, |acc, s| {
+        let mut mem: Box<[i64]> = Box::from(mem);
+        let mut p = Program::new(mem.as_mut())
             .add_input_value(*s)
-            .add_input_value(acc)
-            .run();
-        let out = p.output().first().expect("No output produced");
-        *out
+            .add_input_value(acc);
+        let out = p.outputs().next().expect("No output produced");
+        out.unwrap_or_else(|e| panic!("Error while running program: {}", e))
     })
 }
 
 fn main() {
     let prog = read_input().unwrap();
-    let max_out = (0..5)
-        .collect::<Vec<i32>>()
+    let phase_settings = (0..5)
+        .collect::<Vec<i64>>()
         .unique_permutations()
-        .map(|permutation| {
-            let phase_settings = permutation.iter().cloned().copied().collect::<Vec<i32>>();
-
-            run_amplifiers(&prog, &phase_settings)
+        .map(|permutation| permutation.iter().cloned().copied().collect::<Vec<i64>>())
+        .collect::<Vec<_>>();
+    let max_out = Batch::new(&prog)
+        .map(phase_settings, |prog, phase_settings| {
+            run_amplifiers(prog, &phase_settings)
         })
+        .into_iter()
         .max()
         .expect("max()");
 
@@ -0,0 +1,495 @@
+//! Compares the decoded instruction cache against the interpreter as it was
+//! before the cache, kept below as `baseline`, which is what the speed-up is
+//! measured against.
+//!
+//! Two more runs are given for context: `Program` with the cache off, which
+//! still goes through the compact decoded form that came with the cache, and
+//! the reference interpreter.
+//!
+//! Run with `cargo bench -p intcode`.
+
+use std::fs::read_to_string;
+use std::time::{Duration, Instant};
+
+use intcode::{lang, reference, Program};
+
+fn read_program(path: &str) -> Vec<i64> {
+    read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading file {}: {}", path, e))
+        .trim()
+        .split(',')
+        .map(|v| v.parse::<i64>().expect("i64::parse"))
+        .collect()
+}
+
+fn time<F: FnMut() -> i64>(name: &str, runs: u32, mut f: F) -> Duration {
+    // Warm up, and keep the result observable so nothing gets optimised away
+    let check = f();
+    let start = Instant::now();
+    for _ in 0..runs {
+        assert_eq!(f(), check);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<32} {:>10.2?} per run, {:?} total",
+        name,
+        elapsed / runs,
+        elapsed
+    );
+    elapsed
+}
+
+/// Day 2 part 2: every noun and verb pair, as in `find_noun_verb`.
+fn day2_sweep(program: &[i64], cache: bool) -> i64 {
+    let mut sum = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut mem = program.to_vec();
+            mem[1] = noun;
+            mem[2] = verb;
+            Program::new(&mut mem).with_decode_cache(cache).run();
+            sum += mem[0];
+        }
+    }
+    sum
+}
+
+fn day2_sweep_baseline(program: &[i64]) -> i64 {
+    let mut sum = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut mem = program.to_vec();
+            mem[1] = noun;
+            mem[2] = verb;
+            baseline::Program::new(&mut mem).run();
+            sum += mem[0];
+        }
+    }
+    sum
+}
+
+fn day2_sweep_reference(program: &[i64]) -> i64 {
+    let mut sum = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut mem = program.to_vec();
+            mem[1] = noun;
+            mem[2] = verb;
+            reference::run(&mut mem, &[], usize::MAX);
+            sum += mem[0];
+        }
+    }
+    sum
+}
+
+/// Day 5 diagnostics, which loop over the same instructions many times.
+fn day5_diagnostics(program: &[i64], cache: bool) -> i64 {
+    [1, 5]
+        .iter()
+        .map(|input| {
+            let mut mem = program.to_vec();
+            let p = Program::new(&mut mem)
+                .with_decode_cache(cache)
+                .add_input_value(*input)
+                .run();
+            *p.output().last().unwrap()
+        })
+        .sum()
+}
+
+fn day5_diagnostics_baseline(program: &[i64]) -> i64 {
+    [1, 5]
+        .iter()
+        .map(|input| {
+            let mut mem = program.to_vec();
+            let p = baseline::Program::new(&mut mem)
+                .add_input_value(*input)
+                .run();
+            *p.output().last().unwrap()
+        })
+        .sum()
+}
+
+fn day5_diagnostics_reference(program: &[i64]) -> i64 {
+    [1, 5]
+        .iter()
+        .map(|input| {
+            let mut mem = program.to_vec();
+            let out = reference::run(&mut mem, &[*input], usize::MAX);
+            *out.output.last().unwrap()
+        })
+        .sum()
+}
+
+/// A tight loop summing the numbers from `n` down to 1.
+fn countdown(n: i64) -> Vec<i64> {
+    let mut program = vec![
+        1101, 0, 0, 100, 1101, 0, n, 101, 1, 100, 101, 100, 1001, 101, -1, 101, 1005, 101, 8, 4,
+        100, 99,
+    ];
+    program.resize(102, 0);
+    program
+}
+
+fn run_to_first_output(program: &[i64], cache: bool) -> i64 {
+    let mut mem = program.to_vec();
+    let p = Program::new(&mut mem).with_decode_cache(cache).run();
+    p.output()[0]
+}
+
+fn run_to_first_output_baseline(program: &[i64]) -> i64 {
+    let mut mem = program.to_vec();
+    let p = baseline::Program::new(&mut mem).run();
+    p.output()[0]
+}
+
+fn run_to_first_output_reference(program: &[i64]) -> i64 {
+    let mut mem = program.to_vec();
+    reference::run(&mut mem, &[], usize::MAX).output[0]
+}
+
+/// Naive recursive Fibonacci, compiled from the `lang` module, which exercises
+/// calls through the relative base.
+const FIB: &str = "
+    fn fib(n) {
+        if (n < 2) { return n; }
+        return fib(n - 1) + fib(n - 2);
+    }
+    print(fib(20));
+";
+
+fn main() {
+    let day2 = read_program("../day2/data/input.txt");
+    let day5 = read_program("../day5/data/input.txt");
+
+    println!("day2 noun/verb sweep (10000 programs)");
+    let cached = time("  Program, decode cache", 5, || day2_sweep(&day2, true));
+    time("  Program, no decode cache", 5, || day2_sweep(&day2, false));
+    let before = time("  before the cache", 5, || day2_sweep_baseline(&day2));
+    time("  reference", 5, || day2_sweep_reference(&day2));
+    speedup(before, cached);
+
+    println!("day5 diagnostics");
+    let cached = time("  Program, decode cache", 2000, || {
+        day5_diagnostics(&day5, true)
+    });
+    time("  Program, no decode cache", 2000, || {
+        day5_diagnostics(&day5, false)
+    });
+    let before = time("  before the cache", 2000, || {
+        day5_diagnostics_baseline(&day5)
+    });
+    time("  reference", 2000, || day5_diagnostics_reference(&day5));
+    speedup(before, cached);
+
+    let loop_program = countdown(100_000);
+    println!("countdown loop (300000 instructions)");
+    let cached = time("  Program, decode cache", 20, || {
+        run_to_first_output(&loop_program, true)
+    });
+    time("  Program, no decode cache", 20, || {
+        run_to_first_output(&loop_program, false)
+    });
+    let before = time("  before the cache", 20, || {
+        run_to_first_output_baseline(&loop_program)
+    });
+    time("  reference", 20, || {
+        run_to_first_output_reference(&loop_program)
+    });
+    speedup(before, cached);
+
+    let fib = lang::compile(FIB).unwrap().memory;
+    println!("compiled recursive fib(20)");
+    let cached = time("  Program, decode cache", 20, || {
+        run_to_first_output(&fib, true)
+    });
+    time("  Program, no decode cache", 20, || {
+        run_to_first_output(&fib, false)
+    });
+    let before = time("  before the cache", 20, || {
+        run_to_first_output_baseline(&fib)
+    });
+    time("  reference", 20, || run_to_first_output_reference(&fib));
+    speedup(before, cached);
+}
+
+fn speedup(before: Duration, cached: Duration) {
+    println!(
+        "  speedup over before the cache: {:.2}x",
+        before.as_secs_f64() / cached.as_secs_f64()
+    );
+}
+
+/// `Program`'s interpreter as it was before the decode cache: every step
+/// reads the opcode and parameter words from memory and decodes them again.
+/// Only devices are left out, as none are attached here, which if anything
+/// makes the baseline faster than it was.
+mod baseline {
+    use std::collections::VecDeque;
+    use std::convert::TryFrom;
+
+    use intcode::{Error, Op, OpCode, OpError, Param, ParamMode, Profile};
+
+    pub struct Program<'a> {
+        memory: &'a mut [i64],
+        profile: Profile,
+        next_op: usize,
+        relative_base: i64,
+        input: VecDeque<i64>,
+        output: Vec<i64>,
+    }
+
+    enum Instruction {
+        Increase(usize),
+        GoTo(usize),
+        Stop,
+    }
+
+    impl<'a> Program<'a> {
+        pub fn new(memory: &mut [i64]) -> Program<'_> {
+            Program {
+                memory,
+                profile: Profile::default(),
+                next_op: 0,
+                relative_base: 0,
+                input: VecDeque::new(),
+                output: Vec::new(),
+            }
+        }
+
+        pub fn add_input_value(mut self, input: i64) -> Self {
+            self.input.push_back(input);
+            self
+        }
+
+        pub fn output(&self) -> &[i64] {
+            &self.output
+        }
+
+        fn read_at(&mut self, pos: usize) -> Result<i64, Error> {
+            self.memory
+                .get(pos)
+                .copied()
+                .ok_or(Error::ReadOutOfBounds(pos))
+        }
+
+        fn write_at(&mut self, pos: usize, value: i64) {
+            self.memory[pos] = value;
+        }
+
+        fn parse_op(&mut self) -> Result<Op, Error> {
+            use OpError::*;
+
+            let address = self.next_op;
+            let invalid = move |e| Error::InvalidOp(address, e);
+
+            let opcode_val = self.read_at(self.next_op)?;
+            let opcode = OpCode::try_from(opcode_val)
+                .map_err(InvalidOpCode)
+                .map_err(invalid)?;
+
+            if !self.profile.allows_op(opcode.op) {
+                return Err(invalid(OpCodeNotInProfile(opcode.op, self.profile)));
+            }
+            if let Some((n, mode)) = [&opcode.arg0, &opcode.arg1, &opcode.arg2]
+                .iter()
+                .enumerate()
+                .find(|(_, mode)| !self.profile.allows_mode(mode))
+            {
+                return Err(invalid(ParamModeNotInProfile(
+                    n + 1,
+                    mode.as_int(),
+                    self.profile,
+                )));
+            }
+
+            match opcode.op {
+                99 => Ok(Op::Terminate),
+                1 => {
+                    let left = self.parse_param(1, opcode.arg0)?;
+                    let right = self.parse_param(2, opcode.arg1)?;
+                    match self.parse_param(3, opcode.arg2)? {
+                        Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
+                        Param::Position(target) => Ok(Op::Add((left, right, target))),
+                    }
+                }
+                2 => {
+                    let left = self.parse_param(1, opcode.arg0)?;
+                    let right = self.parse_param(2, opcode.arg1)?;
+                    match self.parse_param(3, opcode.arg2)? {
+                        Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
+                        Param::Position(target) => Ok(Op::Mul((left, right, target))),
+                    }
+                }
+                3 => match self.parse_param(1, opcode.arg0)? {
+                    Param::Immediate(_) => Err(invalid(ImmediateTargetParam(1))),
+                    Param::Position(target) => Ok(Op::Input(target)),
+                },
+                4 => {
+                    let value = self.parse_param(1, opcode.arg0)?;
+                    Ok(Op::Output(value))
+                }
+                5 => {
+                    let condition = self.parse_param(1, opcode.arg0)?;
+                    let value = self.parse_param(2, opcode.arg1)?;
+                    Ok(Op::JumpIfTrue((condition, value)))
+                }
+                6 => {
+                    let condition = self.parse_param(1, opcode.arg0)?;
+                    let value = self.parse_param(2, opcode.arg1)?;
+                    Ok(Op::JumpIfFalse((condition, value)))
+                }
+                7 => {
+                    let left = self.parse_param(1, opcode.arg0)?;
+                    let right = self.parse_param(2, opcode.arg1)?;
+                    match self.parse_param(3, opcode.arg2)? {
+                        Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
+                        Param::Position(target) => Ok(Op::LessThan((left, right, target))),
+                    }
+                }
+                8 => {
+                    let left = self.parse_param(1, opcode.arg0)?;
+                    let right = self.parse_param(2, opcode.arg1)?;
+                    match self.parse_param(3, opcode.arg2)? {
+                        Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
+                        Param::Position(target) => Ok(Op::Equals((left, right, target))),
+                    }
+                }
+                9 => {
+                    let value = self.parse_param(1, opcode.arg0)?;
+                    Ok(Op::AdjustRelativeBase(value))
+                }
+                _ => Err(invalid(UnrecognisedOpCode(opcode.op))),
+            }
+        }
+
+        fn parse_param(&mut self, offset: usize, mode: ParamMode) -> Result<Param, Error> {
+            let address = self.next_op + offset;
+            let value = self.read_at(address)?;
+
+            let position = match mode {
+                ParamMode::Immediate => return Ok(Param::Immediate(value)),
+                ParamMode::Position => value,
+                ParamMode::Relative => self.relative_base.wrapping_add(value),
+            };
+
+            if position < 0 || position as usize >= self.memory.len() {
+                Err(Error::InvalidOp(
+                    self.next_op,
+                    OpError::PositionParamOutOfBounds(offset, position),
+                ))
+            } else {
+                Ok(Param::Position(position as usize))
+            }
+        }
+
+        fn read_param(&mut self, param: &Param) -> Result<i64, Error> {
+            match param {
+                Param::Immediate(value) => Ok(*value),
+                Param::Position(pos) => self.read_at(*pos),
+            }
+        }
+
+        fn jump_target(&mut self, param: &Param) -> Result<usize, Error> {
+            let target = self.read_param(param)?;
+            if target < 0 {
+                Err(Error::NegativeJumpTarget(self.next_op, target))
+            } else {
+                Ok(target as usize)
+            }
+        }
+
+        fn step(&mut self) -> Result<Instruction, Error> {
+            let op = self.parse_op()?;
+
+            let instruction = match &op {
+                Op::Add((p0, p1, dest)) => {
+                    let left = self.read_param(p0)?;
+                    let right = self.read_param(p1)?;
+                    self.write_at(*dest, left.wrapping_add(right));
+                    Instruction::Increase(op.size().unwrap())
+                }
+                Op::Mul((p0, p1, dest)) => {
+                    let left = self.read_param(p0)?;
+                    let right = self.read_param(p1)?;
+                    self.write_at(*dest, left.wrapping_mul(right));
+                    Instruction::Increase(op.size().unwrap())
+                }
+                Op::Input(dest) => match self.input.pop_front() {
+                    Some(input) => {
+                        self.write_at(*dest, input);
+                        Instruction::Increase(op.size().unwrap())
+                    }
+                    _ => return Err(Error::MissingInput(self.next_op)),
+                },
+                Op::Output(src) => {
+                    let value = self.read_param(src)?;
+                    self.output.push(value);
+                    Instruction::Increase(op.size().unwrap())
+                }
+                Op::JumpIfTrue((p0, target)) => {
+                    if self.read_param(p0)? != 0 {
+                        Instruction::GoTo(self.jump_target(target)?)
+                    } else {
+                        Instruction::Increase(op.size().unwrap())
+                    }
+                }
+                Op::JumpIfFalse((p0, target)) => {
+                    if self.read_param(p0)? == 0 {
+                        Instruction::GoTo(self.jump_target(target)?)
+                    } else {
+                        Instruction::Increase(op.size().unwrap())
+                    }
+                }
+                Op::LessThan((p0, p1, target)) => {
+                    let left = self.read_param(p0)?;
+                    let right = self.read_param(p1)?;
+
+                    self.write_at(*target, if left < right { 1 } else { 0 });
+
+                    Instruction::Increase(op.size().unwrap())
+                }
+                Op::Equals((p0, p1, target)) => {
+                    let left = self.read_param(p0)?;
+                    let right = self.read_param(p1)?;
+
+                    self.write_at(*target, if left == right { 1 } else { 0 });
+
+                    Instruction::Increase(op.size().unwrap())
+                }
+                Op::AdjustRelativeBase(p0) => {
+                    let value = self.read_param(p0)?;
+                    self.relative_base = self.relative_base.wrapping_add(value);
+                    Instruction::Increase(op.size().unwrap())
+                }
+                Op::Terminate => Instruction::Stop,
+            };
+
+            Ok(instruction)
+        }
+
+        fn execute(&mut self, step_limit: Option<usize>) -> Result<(), Error> {
+            let mut steps = 0;
+            loop {
+                if step_limit.is_some_and(|limit| steps >= limit) {
+                    return Err(Error::StepLimitReached(steps));
+                }
+                steps += 1;
+
+                match self.step()? {
+                    Instruction::Increase(val) => self.next_op += val,
+                    Instruction::GoTo(instr) => self.next_op = instr,
+                    Instruction::Stop => return Ok(()),
+                }
+            }
+        }
+
+        pub fn run(mut self) -> Program<'a> {
+            if let Err(e) = self.execute(None) {
+                panic!("Error while running program: {}", e);
+            }
+
+            self
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Translates an image into a Rust function with `intcode::transpile`.
+//!
+//! Usage: `cargo run -p intcode --example transpile -- <image file> <function name>`
+//!
+//! The image file holds comma separated words, like the puzzle inputs. The
+//! source is written to standard output.
+
+use std::env;
+use std::fs::read_to_string;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <image file> <function name>", args[0]);
+        process::exit(2);
+    }
+
+    let path = &args[1];
+    let memory: Vec<i64> = read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading file {}: {}", path, e))
+        .trim()
+        .split(',')
+        .map(|v| v.parse::<i64>().expect("i64::parse"))
+        .collect();
+
+    print!("{}", intcode::transpile::transpile(&memory, &args[2]));
+}
@@ -0,0 +1,56 @@
+//! Runs an image and renders its memory heatmap and timeline with
+//! `intcode::trace`.
+//!
+//! Usage: `cargo run -p intcode --example visualize -- <image file> [input...]`
+//!
+//! The image file holds comma separated words, like the puzzle inputs. The
+//! images are written to `heatmap.ppm` and `timeline.ppm`.
+
+use std::env;
+use std::fs::read_to_string;
+use std::process;
+
+use intcode::Program;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <image file> [input...]", args[0]);
+        process::exit(2);
+    }
+
+    let path = &args[1];
+    let mut memory: Vec<i64> = read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading file {}: {}", path, e))
+        .trim()
+        .split(',')
+        .map(|v| v.parse::<i64>().expect("i64::parse"))
+        .collect();
+    let input: Vec<i64> = args[2..]
+        .iter()
+        .map(|v| v.parse::<i64>().expect("i64::parse"))
+        .collect();
+
+    let size = memory.len();
+    let mut program = Program::new(&mut memory).with_trace().add_input(&input);
+    if let Err(e) = program.run_with_limit(10_000_000) {
+        eprintln!("Stopped early: {}", e);
+    }
+
+    let trace = program.trace().unwrap();
+    let width = 32;
+    trace
+        .heatmap(size, width)
+        .scaled(8)
+        .save_ppm("heatmap.ppm")
+        .expect("writing heatmap.ppm");
+    trace
+        .timeline(1024, 512)
+        .save_ppm("timeline.ppm")
+        .expect("writing timeline.ppm");
+    println!(
+        "{} steps over {} addresses, written to heatmap.ppm and timeline.ppm",
+        trace.instruction_pointers().len(),
+        trace.len()
+    );
+}
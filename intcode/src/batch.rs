@@ -0,0 +1,269 @@
+//! Runs many variations of a program on a pool of threads.
+//!
+//! A [`Batch`] holds a program image and runs [`Job`]s against copies of it,
+//! each with its own memory patches and input, handing out jobs to worker
+//! threads as they become free. Results always come back in job order.
+//!
+//! [`run_until`](Batch::run_until) stops handing out jobs once a result
+//! matches a predicate, which suits searches like day 2's noun and verb.
+//! Jobs that need more than a single run, such as day 7's chains of
+//! amplifiers, can go through [`map`](Batch::map) with a function of their
+//! own.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::memo::Memo;
+use crate::{Error, Program};
+
+/// Changes to the image and the input for a single run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Job {
+    /// `(address, value)` pairs written to memory before the run.
+    pub patches: Vec<(usize, i64)>,
+    pub input: Vec<i64>,
+}
+
+impl Job {
+    pub fn new() -> Job {
+        Job::default()
+    }
+
+    pub fn with_patch(mut self, address: usize, value: i64) -> Job {
+        self.patches.push((address, value));
+        self
+    }
+
+    pub fn add_input(mut self, values: &[i64]) -> Job {
+        self.input.extend(values);
+        self
+    }
+}
+
+/// The result of running a [`Job`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub job: Job,
+    pub output: Vec<i64>,
+    /// Memory once the run stopped.
+    pub memory: Vec<i64>,
+    /// Whether the program halted, or why it stopped.
+    pub result: Result<(), Error>,
+}
+
+/// Runs `job` on a copy of `image`, with at most `step_limit` instructions.
+pub fn run_job(image: &[i64], job: Job, step_limit: Option<usize>) -> Outcome {
+    run_with(image, job, |program| program.execute(step_limit, false))
+}
+
+/// Sets up a program for `job` on a copy of `image` and has `run` run it.
+pub(crate) fn run_with<F>(image: &[i64], job: Job, run: F) -> Outcome
+where
+    F: FnOnce(&mut Program) -> Result<(), Error>,
+{
+    let mut memory = image.to_vec();
+    for (address, value) in &job.patches {
+        if *address >= memory.len() {
+            memory.resize(address + 1, 0);
+        }
+        memory[*address] = *value;
+    }
+
+    let mut program = Program::new(&mut memory).add_input(&job.input);
+    let result = run(&mut program);
+    let output = std::mem::take(&mut program.output);
+    drop(program);
+    Outcome {
+        job,
+        output,
+        memory,
+        result,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Batch<'a> {
+    image: &'a [i64],
+    threads: usize,
+    step_limit: Option<usize>,
+    memo: Option<&'a Memo>,
+}
+
+impl<'a> Batch<'a> {
+    /// A batch running on as many threads as the machine runs in parallel.
+    pub fn new(image: &'a [i64]) -> Batch<'a> {
+        Batch {
+            image,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            step_limit: None,
+            memo: None,
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Batch<'a> {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stops each run after `step_limit` instructions, with
+    /// [`Error::StepLimitReached`] as its result.
+    pub fn with_step_limit(mut self, step_limit: usize) -> Batch<'a> {
+        self.step_limit = Some(step_limit);
+        self
+    }
+
+    /// Runs jobs through `memo`, so that those run before, by this batch or
+    /// another, aren't run again. Functions passed to [`map`](Batch::map)
+    /// are called as usual.
+    pub fn with_memo(mut self, memo: &'a Memo) -> Batch<'a> {
+        self.memo = Some(memo);
+        self
+    }
+
+    /// Runs every job.
+    pub fn run<I>(&self, jobs: I) -> Vec<Outcome>
+    where
+        I: IntoIterator<Item = Job>,
+        I::IntoIter: Send,
+    {
+        self.run_until(jobs, |_| false)
+    }
+
+    /// Runs jobs until one's outcome matches `stop`, which is then the last
+    /// of the outcomes returned. Jobs after it may have been started, but
+    /// their outcomes are dropped.
+    pub fn run_until<I, P>(&self, jobs: I, stop: P) -> Vec<Outcome>
+    where
+        I: IntoIterator<Item = Job>,
+        I::IntoIter: Send,
+        P: Fn(&Outcome) -> bool + Sync,
+    {
+        let (step_limit, memo) = (self.step_limit, self.memo);
+        let run = |image: &[i64], job| match memo {
+            Some(memo) => memo.run_job(image, job, step_limit),
+            None => run_job(image, job, step_limit),
+        };
+        self.map_until(jobs, run, stop)
+    }
+
+    /// Calls `f` with the image and each job, in parallel.
+    pub fn map<T, R, I, F>(&self, jobs: I, f: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send,
+        F: Fn(&[i64], T) -> R + Sync,
+    {
+        self.map_until(jobs, f, |_| false)
+    }
+
+    /// Like [`map`](Batch::map), but stops at the first result that matches
+    /// `stop`, as [`run_until`](Batch::run_until) does.
+    pub fn map_until<T, R, I, F, P>(&self, jobs: I, f: F, stop: P) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send,
+        F: Fn(&[i64], T) -> R + Sync,
+        P: Fn(&R) -> bool + Sync,
+    {
+        let jobs = Mutex::new(jobs.into_iter().enumerate());
+        // Index of the first job known to match; no job after it is started
+        let first_match = AtomicUsize::new(usize::MAX);
+        let results = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| loop {
+                    let (index, job) = {
+                        let mut jobs = jobs.lock().unwrap();
+                        match jobs.next() {
+                            Some((index, _)) if index > first_match.load(Ordering::SeqCst) => {
+                                return
+                            }
+                            Some(next) => next,
+                            None => return,
+                        }
+                    };
+                    let result = f(self.image, job);
+                    if stop(&result) {
+                        first_match.fetch_min(index, Ordering::SeqCst);
+                    }
+                    results.lock().unwrap().push((index, result));
+                });
+            }
+        });
+
+        // Jobs are handed out in order, so every job before the first match
+        // has run
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(index, _)| *index);
+        let first_match = first_match.into_inner();
+        results.truncate(first_match.saturating_add(1));
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds the words at 5 and 6 into 0
+    const SUM: [i64; 7] = [1, 5, 6, 0, 99, 0, 0];
+
+    fn sums(threads: usize) -> impl Iterator<Item = Job> {
+        (0..50).map(move |n| Job::new().with_patch(5, n).with_patch(6, threads as i64))
+    }
+
+    #[test]
+    fn results_come_back_in_job_order() {
+        for threads in [1, 4] {
+            let outcomes = Batch::new(&SUM).with_threads(threads).run(sums(threads));
+            assert_eq!(outcomes.len(), 50);
+            for (n, outcome) in outcomes.iter().enumerate() {
+                assert_eq!(outcome.memory[0], (n + threads) as i64);
+                assert_eq!(outcome.job.patches[0], (5, n as i64));
+                assert_eq!(outcome.result, Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_the_first_match() {
+        let outcomes = Batch::new(&SUM)
+            .with_threads(8)
+            .run_until(sums(0), |o| o.memory[0] % 7 == 6);
+        assert_eq!(outcomes.len(), 7);
+        assert_eq!(outcomes.last().unwrap().memory[0], 6);
+
+        let outcomes = Batch::new(&SUM).run_until(sums(0), |_| false);
+        assert_eq!(outcomes.len(), 50);
+    }
+
+    #[test]
+    fn runs_report_their_errors() {
+        // Echoes input forever
+        let echo = [3, 7, 4, 7, 1105, 1, 0, 0];
+        let jobs = vec![Job::new().add_input(&[1, 2]), Job::new()];
+        let outcomes = Batch::new(&echo).with_step_limit(100).run(jobs);
+        assert_eq!(outcomes[0].output, [1, 2]);
+        assert_eq!(outcomes[0].result, Err(Error::MissingInput(0)));
+        assert_eq!(outcomes[1].output, []);
+
+        let outcomes = Batch::new(&[1105, 1, 0])
+            .with_step_limit(100)
+            .run(vec![Job::new()]);
+        assert_eq!(outcomes[0].result, Err(Error::StepLimitReached(100)));
+    }
+
+    #[test]
+    fn maps_custom_jobs() {
+        let lengths = Batch::new(&SUM)
+            .with_threads(3)
+            .map(1..=10, |image, n| image.len() * n);
+        assert_eq!(lengths, (1..=10).map(|n| n * 7).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,44 @@
+//! Debug adapter for Intcode programs, see `intcode::dap`.
+//!
+//! Usage: `intcode-dap [--port <port>]`
+//!
+//! Without a port the adapter talks over standard input and output. With one
+//! it listens on 127.0.0.1, prints `listening on <address>` to standard
+//! error and serves a single client; port 0 picks a free port.
+
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::process;
+
+use intcode::dap;
+
+fn serve_tcp(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    dap::serve(stream.try_clone()?, stream)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let result = match &args[1..] {
+        [] => dap::serve(io::stdin(), io::stdout()),
+        [flag, port] if flag == "--port" => match port.parse() {
+            Ok(port) => serve_tcp(port),
+            Err(_) => {
+                eprintln!("invalid port {}", port);
+                process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("usage: {} [--port <port>]", args[0]);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
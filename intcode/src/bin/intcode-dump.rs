@@ -0,0 +1,39 @@
+//! Prints Intcode images, see `intcode::dump`.
+//!
+//! Usage: `intcode-dump <image> [after image]`
+//!
+//! With one image, prints it as a dump. With two, lists the cells that
+//! differ between them and exits with status 1 if there are any.
+
+use std::env;
+use std::fs::read_to_string;
+use std::process;
+
+use intcode::dump;
+
+fn read_image(path: &str) -> Vec<i64> {
+    read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading file {}: {}", path, e))
+        .trim()
+        .split(',')
+        .map(|v| v.trim().parse::<i64>().expect("i64::parse"))
+        .collect()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.len() {
+        2 => print!("{}", dump::dump(&read_image(&args[1]))),
+        3 => {
+            let diff = dump::format_diff(&read_image(&args[1]), &read_image(&args[2]));
+            print!("{}", diff);
+            if !diff.is_empty() {
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("usage: {} <image> [after image]", args[0]);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Checks two Intcode programs against each other, see `intcode::equiv`.
+//!
+//! Usage: `intcode-equiv <left image> <right image> [step limit]`
+//!
+//! Prints the smallest input found that tells them apart and exits with
+//! status 1, or how many inputs they agreed on.
+
+use std::env;
+use std::fs::read_to_string;
+use std::process;
+
+use intcode::equiv::Checker;
+
+fn read_image(path: &str) -> Vec<i64> {
+    read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading file {}: {}", path, e))
+        .trim()
+        .split(',')
+        .map(|v| v.trim().parse::<i64>().expect("i64::parse"))
+        .collect()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("usage: {} <left image> <right image> [step limit]", args[0]);
+        process::exit(2);
+    }
+
+    let mut checker = Checker::new();
+    if let Some(limit) = args.get(3) {
+        checker = checker.with_step_limit(limit.parse().expect("usize::parse"));
+    }
+    match checker.check(&read_image(&args[1]), &read_image(&args[2])) {
+        Ok(inputs) => println!("programs agree on {} inputs", inputs),
+        Err(difference) => {
+            println!("{}", difference);
+            process::exit(1);
+        }
+    }
+}
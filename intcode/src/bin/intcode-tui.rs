@@ -0,0 +1,198 @@
+//! Terminal UI for stepping through Intcode programs, see `intcode::tui`.
+//!
+//! Usage: `intcode-tui <image file> [input...]`
+//!
+//! With a terminal on standard input and output the UI takes over the
+//! screen. Keys:
+//!
+//! * `s`, `n` or space steps one instruction, `c` continues,
+//! * `r` runs to the instruction under the cursor, `b` sets or clears a
+//!   breakpoint on it, and the arrow keys or `j` and `k` move it,
+//! * `[` and `]` or page up and down scroll the memory pane,
+//! * `i` queues input typed on the bottom line, ended with enter,
+//! * `q` quits.
+//!
+//! Otherwise it reads the commands of `intcode::tui::run_lines`, one per
+//! line. The terminal is switched to raw mode with `stty`.
+
+use std::env;
+use std::fs::read_to_string;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{self, Command as Shell, Stdio};
+
+use intcode::tui::{cells_per_row, run_lines, Command, Session};
+
+/// Runs `stty` on the terminal with `args`, returning what it prints.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Shell::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The terminal size as (columns, rows).
+fn size() -> (usize, usize) {
+    let size = stty(&["size"]).unwrap_or_default();
+    match size.split_whitespace().collect::<Vec<_>>()[..] {
+        [rows, columns] => (columns.parse().unwrap_or(80), rows.parse().unwrap_or(24)),
+        _ => (80, 24),
+    }
+}
+
+/// Raw mode and the alternate screen, restored when dropped.
+struct Screen {
+    saved: String,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Screen { saved })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+enum Key {
+    Char(u8),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Other,
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let mut byte = [0];
+    if input.read(&mut byte)? == 0 {
+        return Ok(None);
+    }
+    if byte[0] != 0x1b {
+        return Ok(Some(Key::Char(byte[0])));
+    }
+
+    // An escape sequence ends with a byte from `@` to `~`
+    let mut sequence = Vec::new();
+    loop {
+        if input.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        sequence.push(byte[0]);
+        if sequence.len() > 1 && (0x40..=0x7e).contains(&byte[0]) {
+            break;
+        }
+    }
+    Ok(Some(match &sequence[..] {
+        b"[A" | b"OA" => Key::Up,
+        b"[B" | b"OB" => Key::Down,
+        b"[5~" => Key::PageUp,
+        b"[6~" => Key::PageDown,
+        _ => Key::Other,
+    }))
+}
+
+fn run_screen(session: &mut Session) -> io::Result<()> {
+    let _screen = Screen::enter()?;
+    let mut input = io::stdin();
+    let mut stdout = io::stdout();
+    // Input being typed, if any
+    let mut prompt: Option<String> = None;
+
+    loop {
+        let (width, height) = size();
+        write!(
+            stdout,
+            "{}",
+            session.render(width, height, prompt.as_deref())
+        )?;
+        stdout.flush()?;
+
+        let key = match read_key(&mut input)? {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        if let Some(text) = &mut prompt {
+            match key {
+                Key::Char(b'\r') | Key::Char(b'\n') => {
+                    match format!("i {}", text).parse::<Command>() {
+                        Ok(command) => session.execute(&command),
+                        Err(e) => session.set_status(e),
+                    }
+                    prompt = None;
+                }
+                Key::Char(0x7f) | Key::Char(0x08) => {
+                    text.pop();
+                }
+                Key::Char(c @ (b'0'..=b'9' | b'-' | b' ' | b',')) => text.push(c as char),
+                _ => {}
+            }
+            continue;
+        }
+
+        let page = (height.saturating_sub(5) * cells_per_row(width)) as isize;
+        match key {
+            Key::Char(b'q') | Key::Char(0x03) => return Ok(()),
+            Key::Char(b's') | Key::Char(b'n') | Key::Char(b' ') => {
+                session.execute(&Command::Step(1))
+            }
+            Key::Char(b'c') => session.execute(&Command::Continue),
+            Key::Char(b'r') => session.execute(&Command::RunTo(session.cursor())),
+            Key::Char(b'b') => session.execute(&Command::ToggleBreakpoint(session.cursor())),
+            Key::Char(b'd') => session.execute(&Command::Diff),
+            Key::Char(b'k') | Key::Up => session.move_cursor(-1),
+            Key::Char(b'j') | Key::Down => session.move_cursor(1),
+            Key::Char(b'[') | Key::PageUp => session.scroll_memory(-page),
+            Key::Char(b']') | Key::PageDown => session.scroll_memory(page),
+            Key::Char(b'i') => prompt = Some(String::new()),
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <image file> [input...]", args[0]);
+        process::exit(2);
+    }
+
+    let path = &args[1];
+    let image: Vec<i64> = read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading file {}: {}", path, e))
+        .trim()
+        .split(',')
+        .map(|v| v.trim().parse::<i64>().expect("i64::parse"))
+        .collect();
+    let input: Vec<i64> = args[2..]
+        .iter()
+        .map(|v| v.parse::<i64>().expect("i64::parse"))
+        .collect();
+    let name = Path::new(path)
+        .file_name()
+        .map_or(path.into(), |n| n.to_string_lossy());
+    let mut session = Session::new(&name, &image, &input);
+
+    let result = if io::stdin().is_terminal() && io::stdout().is_terminal() {
+        run_screen(&mut session)
+    } else {
+        run_lines(&mut session, io::stdin().lock(), io::stdout().lock())
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
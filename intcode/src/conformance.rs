@@ -0,0 +1,205 @@
+//! Published puzzle examples, run against every profile that supports them.
+//!
+//! Each profile must run its own examples and those of the earlier puzzles,
+//! and reject the examples that rely on instructions it does not have.
+
+use crate::{Error, OpError, Profile, Program};
+
+const ALL: [Profile; 3] = [Profile::Day2, Profile::Day5, Profile::Day9];
+
+fn run(profile: Profile, memory: &mut [i64], input: &[i64]) -> Result<Vec<i64>, Error> {
+    let mut p = Program::new(memory).with_profile(profile).add_input(input);
+    p.run_with_limit(10_000)?;
+    Ok(p.output().to_vec())
+}
+
+fn padded(program: &[i64], len: usize) -> Vec<i64> {
+    let mut memory = program.to_vec();
+    memory.resize(len, 0);
+    memory
+}
+
+fn assert_rejected(profile: Profile, memory: &[i64], input: &[i64]) {
+    let result = run(profile, &mut memory.to_vec(), input);
+    match result {
+        Err(Error::InvalidOp(_, OpError::OpCodeNotInProfile(_, p)))
+        | Err(Error::InvalidOp(_, OpError::ParamModeNotInProfile(_, _, p))) => {
+            assert_eq!(p, profile)
+        }
+        other => panic!("{:?} accepted by {}: {:?}", memory, profile, other),
+    }
+}
+
+/// Day 2: programs and the memory they leave behind.
+fn day2_examples() -> Vec<(Vec<i64>, Vec<i64>)> {
+    vec![
+        (
+            vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+            vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50],
+        ),
+        (vec![1, 0, 0, 0, 99], vec![2, 0, 0, 0, 99]),
+        (vec![2, 3, 0, 3, 99], vec![2, 3, 0, 6, 99]),
+        (vec![2, 4, 4, 5, 99, 0], vec![2, 4, 4, 5, 99, 9801]),
+        (
+            vec![1, 1, 1, 4, 99, 5, 6, 0, 99],
+            vec![30, 1, 1, 4, 2, 5, 6, 0, 99],
+        ),
+    ]
+}
+
+/// A program with pairs of input and expected output.
+type IoExample = (Vec<i64>, Vec<(i64, i64)>);
+
+/// Day 5: programs with pairs of input and expected output.
+fn day5_examples() -> Vec<IoExample> {
+    let compare = vec![(7, 0), (8, 1), (9, 0)];
+    let less = vec![(7, 1), (8, 0), (9, 0)];
+    let nonzero = vec![(0, 0), (1, 1), (-3, 1)];
+
+    vec![
+        (vec![3, 0, 4, 0, 99], vec![(5, 5), (-17, -17)]),
+        (vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], compare.clone()),
+        (vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], less.clone()),
+        (vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], compare),
+        (vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], less),
+        (
+            vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            nonzero.clone(),
+        ),
+        (
+            vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+            nonzero,
+        ),
+        (
+            vec![
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ],
+            vec![(7, 999), (8, 1000), (9, 1001)],
+        ),
+    ]
+}
+
+/// Day 5: programs without input and the memory they leave behind.
+fn day5_memory_examples() -> Vec<(Vec<i64>, Vec<i64>)> {
+    vec![
+        (vec![1002, 4, 3, 4, 33], vec![1002, 4, 3, 4, 99]),
+        (vec![1101, 100, -1, 4, 0], vec![1101, 100, -1, 4, 99]),
+    ]
+}
+
+const QUINE: [i64; 16] = [
+    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+];
+
+/// Day 9: programs, the memory they need and their expected output.
+fn day9_examples() -> Vec<(Vec<i64>, Vec<i64>)> {
+    let mut relative = padded(&[109, 2000, 109, 19, 204, -34, 99], 2020);
+    relative[1985] = 42;
+
+    vec![
+        (padded(&QUINE, 102), QUINE.to_vec()),
+        (
+            vec![1102, 34_915_192, 34_915_192, 7, 4, 7, 99, 0],
+            vec![1_219_070_632_396_864],
+        ),
+        (
+            vec![104, 1_125_899_906_842_624, 99],
+            vec![1_125_899_906_842_624],
+        ),
+        (relative, vec![42]),
+    ]
+}
+
+#[test]
+fn day2_examples_run_on_every_profile() {
+    for profile in ALL.iter() {
+        for (program, expected) in day2_examples() {
+            let mut memory = program.clone();
+            run(*profile, &mut memory, &[]).unwrap();
+            assert_eq!(memory, expected, "{:?} on {}", program, profile);
+        }
+    }
+}
+
+#[test]
+fn day5_examples_run_on_day5_and_later() {
+    for profile in [Profile::Day5, Profile::Day9].iter() {
+        for (program, cases) in day5_examples() {
+            for (input, output) in cases {
+                let out = run(*profile, &mut program.clone(), &[input]).unwrap();
+                assert_eq!(out, &[output], "{:?} on {}", program, profile);
+            }
+        }
+        for (program, expected) in day5_memory_examples() {
+            let mut memory = program.clone();
+            run(*profile, &mut memory, &[]).unwrap();
+            assert_eq!(memory, expected, "{:?} on {}", program, profile);
+        }
+    }
+}
+
+#[test]
+fn day5_examples_are_rejected_by_day2() {
+    for (program, cases) in day5_examples() {
+        assert_rejected(Profile::Day2, &program, &[cases[0].0]);
+    }
+    for (program, _) in day5_memory_examples() {
+        assert_rejected(Profile::Day2, &program, &[]);
+    }
+}
+
+#[test]
+fn day9_examples_run_on_day9() {
+    for (program, expected) in day9_examples() {
+        let out = run(Profile::Day9, &mut program.clone(), &[]).unwrap();
+        assert_eq!(out, expected, "{:?}", &program[..program.len().min(16)]);
+    }
+}
+
+#[test]
+fn relative_mode_examples_are_rejected_before_day9() {
+    let relative = &day9_examples()[3].0;
+    for profile in [Profile::Day2, Profile::Day5].iter() {
+        assert_rejected(*profile, &padded(&QUINE, 102), &[]);
+        assert_rejected(*profile, relative, &[]);
+    }
+}
+
+#[test]
+fn profiles_ignore_the_modes_of_missing_parameters() {
+    // Halt with immediate modes, output with a relative third mode
+    let mut memory = vec![1199];
+    assert_eq!(run(Profile::Day2, &mut memory, &[]), Ok(vec![]));
+    let mut memory = vec![21104, 7, 99];
+    assert_eq!(run(Profile::Day5, &mut memory, &[]), Ok(vec![7]));
+}
+
+#[test]
+fn profile_errors_name_the_offending_opcode_and_mode() {
+    let mut memory = vec![1101, 1, 1, 0, 99];
+    assert_eq!(
+        run(Profile::Day2, &mut memory, &[]),
+        Err(Error::InvalidOp(
+            0,
+            OpError::ParamModeNotInProfile(1, 1, Profile::Day2)
+        ))
+    );
+
+    let mut memory = vec![4, 0, 204, 0, 99];
+    assert_eq!(
+        run(Profile::Day5, &mut memory, &[]),
+        Err(Error::InvalidOp(
+            2,
+            OpError::ParamModeNotInProfile(1, 2, Profile::Day5)
+        ))
+    );
+
+    let mut memory = vec![9, 0, 99];
+    let err = run(Profile::Day5, &mut memory, &[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid op at 0: opcode 9 is not part of the day 5 instruction set"
+    );
+}
@@ -0,0 +1,276 @@
+//! Execution coverage of programs.
+//!
+//! A [`Program`](crate::Program) built with
+//! [`with_coverage`](crate::Program::with_coverage) counts how often each
+//! instruction completes and which way each conditional jump goes. Coverage
+//! from several runs of the same image can be merged and then printed as an
+//! annotated listing:
+//!
+//! ```text
+//!        1 |      0  3,14                        IN [14]
+//!        3 |      2  4,14                        OUT [14]
+//!        3 |      4  1001,14,-1,14               ADD [14], -1, [14]
+//!        3 |      8  1005,14,2                   JNZ [14], 2  (jumped 2, fell through 1)
+//!        1 |     11  99                          HALT
+//!        - |     12  0                           DATA 0
+//!
+//! instructions: 5/5 (100.0%), branch directions: 2/2 (100.0%)
+//! ```
+//!
+//! Instructions that never ran are marked `#####`, words that aren't known
+//! to be code `-`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+
+use crate::disasm::{self, Line};
+use crate::transpile::analyse;
+
+/// How often a conditional jump jumped and how often it fell through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub jumped: u64,
+    pub fell_through: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    executed: BTreeMap<usize, u64>,
+    branches: BTreeMap<usize, Branch>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub(crate) fn record(&mut self, address: usize) {
+        *self.executed.entry(address).or_default() += 1;
+    }
+
+    pub(crate) fn record_branch(&mut self, address: usize, jumped: bool) {
+        let branch = self.branches.entry(address).or_default();
+        if jumped {
+            branch.jumped += 1;
+        } else {
+            branch.fell_through += 1;
+        }
+    }
+
+    /// Adds the counts of `other`, which should come from runs of the same
+    /// image.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.executed {
+            *self.executed.entry(*address).or_default() += count;
+        }
+        for (address, branch) in &other.branches {
+            let b = self.branches.entry(*address).or_default();
+            b.jumped += branch.jumped;
+            b.fell_through += branch.fell_through;
+        }
+    }
+
+    /// Number of times the instruction at `address` completed.
+    pub fn hits(&self, address: usize) -> u64 {
+        self.executed.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn branch(&self, address: usize) -> Option<Branch> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Addresses of the instructions to report on: everything reachable
+    /// from address 0 and everything that ran.
+    fn instructions(&self, memory: &[i64]) -> BTreeSet<usize> {
+        let flow = analyse(memory);
+        let mut instructions: BTreeSet<usize> = flow
+            .instrs
+            .iter()
+            .filter(|(_, instr)| instr.is_some())
+            .map(|(address, _)| *address)
+            .collect();
+        instructions.extend(self.executed.keys());
+        instructions
+    }
+
+    pub fn summary(&self, memory: &[i64]) -> Summary {
+        let mut summary = Summary::default();
+        for address in self.instructions(memory) {
+            summary.instructions += 1;
+            if self.hits(address) > 0 {
+                summary.executed += 1;
+            }
+
+            let conditional = disasm::decode(memory, address).is_some_and(|_| {
+                let word = memory[address];
+                (word % 100 == 5 || word % 100 == 6) && (word / 100) % 10 != 1
+            });
+            if conditional {
+                let branch = self.branch(address).unwrap_or_default();
+                summary.branch_directions += 2;
+                summary.directions_taken +=
+                    usize::from(branch.jumped > 0) + usize::from(branch.fell_through > 0);
+            }
+        }
+        summary
+    }
+
+    /// The listing of `memory` annotated with hit counts and branch
+    /// directions, followed by the summary.
+    pub fn report(&self, memory: &[i64]) -> String {
+        let instructions = self.instructions(memory);
+        let mut out = String::new();
+
+        let mut address = 0;
+        while address < memory.len() {
+            let line = instructions
+                .contains(&address)
+                .then(|| disasm::decode(memory, address))
+                .flatten();
+            let (count, line) = match line {
+                Some(line) if self.hits(address) > 0 => (self.hits(address).to_string(), line),
+                Some(line) => ("#####".to_string(), line),
+                None => (
+                    "-".to_string(),
+                    Line {
+                        address,
+                        len: 1,
+                        text: format!("DATA {}", memory[address]),
+                    },
+                ),
+            };
+
+            out += &format!("{:>8} | {}", count, disasm::format_line(memory, &line));
+            if let Some(branch) = self.branch(address) {
+                out += &format!(
+                    "  (jumped {}, fell through {})",
+                    branch.jumped, branch.fell_through
+                );
+            }
+            out.push('\n');
+            address += line.len;
+        }
+
+        out += &format!("\n{}\n", self.summary(memory));
+        out
+    }
+}
+
+/// Coverage totals for an image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub instructions: usize,
+    pub executed: usize,
+    /// Two for every conditional jump whose condition isn't an immediate.
+    pub branch_directions: usize,
+    pub directions_taken: usize,
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * part as f64 / total as f64
+    }
+}
+
+impl Summary {
+    pub fn instruction_percent(&self) -> f64 {
+        percent(self.executed, self.instructions)
+    }
+
+    pub fn branch_percent(&self) -> f64 {
+        percent(self.directions_taken, self.branch_directions)
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instructions: {}/{} ({:.1}%), branch directions: {}/{} ({:.1}%)",
+            self.executed,
+            self.instructions,
+            self.instruction_percent(),
+            self.directions_taken,
+            self.branch_directions,
+            self.branch_percent()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    // Counts down from the input, printing each value
+    const COUNTDOWN: [i64; 15] = [3, 14, 4, 14, 1001, 14, -1, 14, 1005, 14, 2, 99, 0, 0, 0];
+
+    fn coverage(input: i64) -> Coverage {
+        let mut memory = COUNTDOWN.to_vec();
+        let program = Program::new(&mut memory)
+            .with_coverage()
+            .add_input_value(input)
+            .run();
+        program.coverage().unwrap().clone()
+    }
+
+    #[test]
+    fn counts_instructions_and_branches() {
+        let c = coverage(3);
+        assert_eq!(c.hits(0), 1);
+        assert_eq!(c.hits(2), 3);
+        assert_eq!(c.hits(11), 1);
+        assert_eq!(c.hits(12), 0);
+        assert_eq!(
+            c.branch(8),
+            Some(Branch {
+                jumped: 2,
+                fell_through: 1
+            })
+        );
+
+        let summary = c.summary(&COUNTDOWN);
+        assert_eq!(summary.instructions, 5);
+        assert_eq!(summary.executed, 5);
+        assert_eq!(summary.branch_directions, 2);
+        assert_eq!(summary.directions_taken, 2);
+    }
+
+    #[test]
+    fn runs_merge() {
+        // With 1 the loop never goes round
+        let mut c = coverage(1);
+        assert_eq!(c.summary(&COUNTDOWN).directions_taken, 1);
+
+        c.merge(&coverage(2));
+        assert_eq!(c.hits(2), 3);
+        assert_eq!(c.summary(&COUNTDOWN).directions_taken, 2);
+    }
+
+    #[test]
+    fn report_marks_unexecuted_code() {
+        // Jumps over the first output when the input is non-zero
+        let memory = [3, 10, 1005, 10, 7, 104, 1, 104, 2, 99, 0];
+        let mut m = memory.to_vec();
+        let program = Program::new(&mut m)
+            .with_coverage()
+            .add_input_value(1)
+            .run();
+        let report = program.coverage().unwrap().report(&memory);
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert_eq!(
+            lines[1],
+            "       1 |      2  1005,10,7                   JNZ [10], 7  (jumped 1, fell through 0)"
+        );
+        assert!(lines[2].starts_with("   ##### |      5  104,1 "));
+        assert!(lines[3].starts_with("       1 |      7  104,2 "));
+        assert!(lines[5].starts_with("       - |     10  0 "));
+        assert_eq!(
+            lines.last().unwrap(),
+            &"instructions: 4/5 (80.0%), branch directions: 1/2 (50.0%)"
+        );
+    }
+}
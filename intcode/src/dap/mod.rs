@@ -0,0 +1,758 @@
+//! A [Debug Adapter Protocol] server for Intcode programs.
+//!
+//! [`serve`] speaks the protocol over any pair of streams; the `intcode-dap`
+//! binary runs it on standard input and output or on a local TCP port. A
+//! `launch` request takes either a `program` path to a file of comma
+//! separated words or the words themselves as `image`, plus optional `input`
+//! values and `stopOnEntry`.
+//!
+//! The program is shown as a listing of its image as launched, served as a
+//! virtual source, so breakpoints can be set on its lines. Instruction
+//! breakpoints and the disassembly view work on addresses, and follow the
+//! current memory. Variables show the registers and the memory in windows of
+//! [`WINDOW`] cells. The debug console accepts:
+//!
+//! * `input 1 2 3` to queue input values, which resumes a program that
+//!   stopped waiting for input once it is continued,
+//! * `ip`, `rb` and `[12]` to read registers and memory,
+//! * `dump` and `diff` to print memory as a [dump](crate::dump::dump) or the
+//!   cells changed since the program started.
+//!
+//! Every step is a single instruction. Values the program outputs are sent
+//! as `output` events.
+//!
+//! [Debug Adapter Protocol]: https://microsoft.github.io/debug-adapter-protocol/
+
+pub mod json;
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fs::read_to_string;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use crate::debugger::{self, Debugger};
+use crate::disasm::{self, Line};
+use crate::Error;
+use json::{object, Value};
+
+/// Number of memory cells in each window of the variables view.
+pub const WINDOW: usize = 64;
+
+/// Instructions run between checks for new requests while the program runs.
+const SLICE: usize = 10_000;
+
+const THREAD_ID: i64 = 1;
+const SOURCE_REFERENCE: i64 = 1;
+const REGISTERS: i64 = 1;
+const MEMORY: i64 = 2;
+/// Variable references from here on are memory windows.
+const FIRST_WINDOW: i64 = 1000;
+
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Reads one message, or `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().map_err(invalid_data)?);
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(invalid_data)?;
+    json::parse(&body).map(Some).map_err(invalid_data)
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Serves a single debug session, until the client disconnects or closes
+/// `input`.
+///
+/// Requests are read on a separate thread, so that a running program can be
+/// paused.
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        loop {
+            let message = read_message(&mut reader).transpose();
+            let last = !matches!(message, Some(Ok(_)));
+            if let Some(message) = message {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+            if last {
+                return;
+            }
+        }
+    });
+
+    let mut server = Server::new(output);
+    loop {
+        let request = if server.running {
+            match requests.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        match request {
+            Some(request) => {
+                if !server.handle(&request?)? {
+                    return Ok(());
+                }
+            }
+            None => server.run_slice()?,
+        }
+    }
+}
+
+/// Why the program stopped.
+enum Stop {
+    Entry,
+    Step,
+    Breakpoint,
+    Pause,
+    Failed(Error),
+    Halted,
+}
+
+impl From<debugger::Stop> for Stop {
+    fn from(stop: debugger::Stop) -> Stop {
+        match stop {
+            debugger::Stop::Breakpoint | debugger::Stop::Reached => Stop::Breakpoint,
+            debugger::Stop::Failed(e) => Stop::Failed(e),
+            debugger::Stop::Halted => Stop::Halted,
+        }
+    }
+}
+
+/// A launched program.
+struct Debuggee {
+    debugger: Debugger,
+    name: String,
+    /// Listing of the image as launched, one source line per entry.
+    listing: Vec<Line>,
+    image: Vec<i64>,
+    /// Addresses of source and instruction breakpoints.
+    line_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    /// How many of the values output were sent to the client.
+    sent: usize,
+}
+
+impl Debuggee {
+    /// Index of the source line covering `address`.
+    fn line_of(&self, address: usize) -> Option<usize> {
+        let i = self
+            .listing
+            .partition_point(|l| l.address + l.len <= address);
+        self.listing
+            .get(i)
+            .filter(|l| l.address <= address)
+            .map(|_| i)
+    }
+
+    /// Hands the source and instruction breakpoints to the debugger.
+    fn update_breakpoints(&mut self) {
+        let addresses = self.line_breakpoints.union(&self.instruction_breakpoints);
+        self.debugger.set_breakpoints(addresses.copied());
+    }
+
+    /// Runs a single instruction.
+    fn step(&mut self) -> Option<Stop> {
+        self.debugger.step().map(Stop::from)
+    }
+
+    fn source(&self) -> Value {
+        object(vec![
+            ("name", self.name.clone().into()),
+            ("sourceReference", SOURCE_REFERENCE.into()),
+        ])
+    }
+}
+
+struct Server<W: Write> {
+    output: W,
+    seq: i64,
+    debuggee: Option<Debuggee>,
+    stop_on_entry: bool,
+    running: bool,
+}
+
+fn address_of(reference: &Value) -> Option<usize> {
+    reference.as_str()?.trim().parse().ok()
+}
+
+fn parse_image(text: &str) -> Result<Vec<i64>, String> {
+    text.trim()
+        .split(',')
+        .map(|word| {
+            word.trim()
+                .parse()
+                .map_err(|_| format!("invalid word {:?}", word))
+        })
+        .collect()
+}
+
+fn values(array: &Value, name: &str) -> Result<Vec<i64>, String> {
+    match array {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(values) => values
+            .iter()
+            .map(|v| v.as_i64())
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("`{}` must only hold integers", name)),
+        _ => Err(format!("`{}` must be an array of integers", name)),
+    }
+}
+
+impl<W: Write> Server<W> {
+    fn new(output: W) -> Server<W> {
+        Server {
+            output,
+            seq: 0,
+            debuggee: None,
+            stop_on_entry: false,
+            running: false,
+        }
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Value)>) -> io::Result<()> {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        write_message(&mut self.output, &object(fields))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(vec![
+            ("type", "event".into()),
+            ("event", event.into()),
+            ("body", body),
+        ])
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("success", result.is_ok().into()),
+            ("command", request.get("command").clone()),
+        ];
+        match result {
+            Ok(body) => fields.push(("body", body)),
+            Err(message) => fields.push(("message", message.into())),
+        }
+        self.send(fields)
+    }
+
+    /// Handles a request, returning `false` once the session is over.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request.get("command").as_str().unwrap_or("");
+        let args = request.get("arguments");
+        let result = match command {
+            "initialize" => Ok(object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsDisassembleRequest", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsSteppingGranularity", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ])),
+            "launch" => self.launch(args),
+            "disconnect" => {
+                self.respond(request, Ok(object(vec![])))?;
+                return Ok(false);
+            }
+            _ if self.debuggee.is_none() => Err(format!("`{}` needs a launched program", command)),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(object(vec![])),
+            "configurationDone" => Ok(object(vec![])),
+            "threads" => Ok(object(vec![(
+                "threads",
+                vec![object(vec![
+                    ("id", THREAD_ID.into()),
+                    ("name", "intcode".into()),
+                ])]
+                .into(),
+            )])),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(object(vec![(
+                "scopes",
+                vec![
+                    object(vec![
+                        ("name", "Registers".into()),
+                        ("presentationHint", "registers".into()),
+                        ("variablesReference", REGISTERS.into()),
+                        ("expensive", false.into()),
+                    ]),
+                    object(vec![
+                        ("name", "Memory".into()),
+                        ("variablesReference", MEMORY.into()),
+                        ("expensive", false.into()),
+                    ]),
+                ]
+                .into(),
+            )])),
+            "variables" => self.variables(args),
+            "source" => Ok(object(vec![
+                ("content", disasm::listing(&self.debuggee().image).into()),
+                ("mimeType", "text/x-intcode".into()),
+            ])),
+            "disassemble" => self.disassemble(args),
+            "evaluate" => self.evaluate(args),
+            "continue" => Ok(object(vec![("allThreadsContinued", true.into())])),
+            "next" | "stepIn" | "pause" | "terminate" => Ok(object(vec![])),
+            _ => Err(format!("unsupported request `{}`", command)),
+        };
+
+        let succeeded = result.is_ok();
+        self.respond(request, result)?;
+        if !succeeded {
+            return Ok(true);
+        }
+
+        match command {
+            "launch" => self.event("initialized", object(vec![]))?,
+            "configurationDone" if self.stop_on_entry => self.stopped(Stop::Entry)?,
+            "configurationDone" => {
+                self.running = true;
+                self.check_breakpoint()?;
+            }
+            "continue" => self.resume()?,
+            "next" | "stepIn" => {
+                let stop = self.debuggee_mut().step().unwrap_or(Stop::Step);
+                self.stopped(stop)?;
+            }
+            "pause" if self.running => {
+                self.running = false;
+                self.stopped(Stop::Pause)?;
+            }
+            "terminate" => {
+                self.running = false;
+                self.event("terminated", object(vec![]))?;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn debuggee(&self) -> &Debuggee {
+        self.debuggee.as_ref().unwrap()
+    }
+
+    fn debuggee_mut(&mut self) -> &mut Debuggee {
+        self.debuggee.as_mut().unwrap()
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let (name, image) = match (args.get("program").as_str(), args.get("image")) {
+            (Some(path), _) => {
+                let text = read_to_string(path)
+                    .map_err(|e| format!("Error reading file {}: {}", path, e))?;
+                let name = Path::new(path)
+                    .file_name()
+                    .map_or(path.into(), |n| n.to_string_lossy());
+                (name.into_owned(), parse_image(&text)?)
+            }
+            (None, Value::Null) => return Err("launch needs a `program` or an `image`".into()),
+            (None, image) => ("image".to_string(), values(image, "image")?),
+        };
+        let input = values(args.get("input"), "input")?;
+
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+        let mut debugger = Debugger::new(&image);
+        debugger.add_input(&input);
+        self.debuggee = Some(Debuggee {
+            debugger,
+            name: format!("{}.intcode", name),
+            listing: disasm::disassemble(&image),
+            image,
+            line_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            sent: 0,
+        });
+        Ok(object(vec![]))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let lines: Vec<i64> = match args.get("breakpoints").as_array() {
+            Some(breakpoints) => breakpoints
+                .iter()
+                .map(|b| b.get("line").as_i64().unwrap_or(0))
+                .collect(),
+            None => values(args.get("lines"), "lines")?,
+        };
+
+        let debuggee = self.debuggee_mut();
+        let source = debuggee.source();
+        debuggee.line_breakpoints.clear();
+        let breakpoints = lines
+            .iter()
+            .map(|line| {
+                let address = usize::try_from(line - 1)
+                    .ok()
+                    .and_then(|i| debuggee.listing.get(i))
+                    .map(|l| l.address);
+                match address {
+                    Some(address) => {
+                        debuggee.line_breakpoints.insert(address);
+                        object(vec![
+                            ("verified", true.into()),
+                            ("line", (*line).into()),
+                            ("source", source.clone()),
+                            ("instructionReference", address.to_string().into()),
+                        ])
+                    }
+                    None => object(vec![
+                        ("verified", false.into()),
+                        ("line", (*line).into()),
+                        ("message", "no instruction on this line".into()),
+                    ]),
+                }
+            })
+            .collect::<Vec<_>>();
+        debuggee.update_breakpoints();
+        Ok(object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let debuggee = self.debuggee_mut();
+        let size = debuggee.debugger.program().memory().len();
+        debuggee.instruction_breakpoints.clear();
+        let breakpoints = args
+            .get("breakpoints")
+            .as_array()
+            .unwrap_or(&[])
+            .iter()
+            .map(|b| {
+                let reference = b.get("instructionReference");
+                let offset = b.get("offset").as_i64().unwrap_or(0);
+                let address = address_of(reference)
+                    .and_then(|a| usize::try_from(a as i64 + offset).ok())
+                    .filter(|a| *a < size);
+                match address {
+                    Some(address) => {
+                        debuggee.instruction_breakpoints.insert(address);
+                        object(vec![
+                            ("verified", true.into()),
+                            ("instructionReference", address.to_string().into()),
+                        ])
+                    }
+                    None => object(vec![
+                        ("verified", false.into()),
+                        ("instructionReference", reference.clone()),
+                        ("message", "not an address in memory".into()),
+                    ]),
+                }
+            })
+            .collect::<Vec<_>>();
+        debuggee.update_breakpoints();
+        Ok(object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn stack_trace(&self) -> Value {
+        let debuggee = self.debuggee();
+        let ip = debuggee.debugger.program().instruction_pointer();
+        let name = disasm::decode(&debuggee.debugger.memory(), ip)
+            .map_or_else(|| format!("{}: ???", ip), |l| format!("{}: {}", ip, l.text));
+
+        let mut frame = vec![
+            ("id", 1.into()),
+            ("name", name.into()),
+            ("column", 1.into()),
+            ("instructionPointerReference", ip.to_string().into()),
+        ];
+        match debuggee.line_of(ip) {
+            Some(i) => {
+                frame.push(("line", (i + 1).into()));
+                frame.push(("source", debuggee.source()));
+            }
+            None => frame.push(("line", 0.into())),
+        }
+        object(vec![
+            ("stackFrames", vec![object(frame)].into()),
+            ("totalFrames", 1.into()),
+        ])
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let debuggee = self.debuggee();
+        let program = debuggee.debugger.program();
+        let variable = |name: String, value: String, reference: i64| {
+            object(vec![
+                ("name", name.into()),
+                ("value", value.into()),
+                ("variablesReference", reference.into()),
+            ])
+        };
+        let list = |values: &mut dyn Iterator<Item = i64>| {
+            let values: Vec<_> = values.map(|v| v.to_string()).collect();
+            format!("[{}]", values.join(", "))
+        };
+
+        let variables = match args.get("variablesReference").as_i64() {
+            Some(REGISTERS) => vec![
+                variable("ip".into(), program.instruction_pointer().to_string(), 0),
+                variable("rb".into(), program.relative_base().to_string(), 0),
+                variable("steps".into(), program.steps().to_string(), 0),
+                variable(
+                    "input".into(),
+                    list(&mut program.pending_input().iter().copied()),
+                    0,
+                ),
+                variable(
+                    "output".into(),
+                    list(&mut debuggee.debugger.output().iter().copied()),
+                    0,
+                ),
+                variable("halted".into(), program.is_halted().to_string(), 0),
+            ],
+            Some(MEMORY) => {
+                let size = program.memory().len();
+                (0..size)
+                    .step_by(WINDOW)
+                    .enumerate()
+                    .map(|(n, start)| {
+                        let end = (start + WINDOW).min(size) - 1;
+                        variable(
+                            format!("{}..{}", start, end),
+                            String::new(),
+                            FIRST_WINDOW + n as i64,
+                        )
+                    })
+                    .collect()
+            }
+            Some(window) if window >= FIRST_WINDOW => {
+                let memory = program.memory();
+                let start = (window - FIRST_WINDOW) as usize * WINDOW;
+                (start..(start + WINDOW).min(memory.len()))
+                    .map(|address| {
+                        let value = memory.read(address).unwrap_or(0).to_string();
+                        variable(format!("[{}]", address), value, 0)
+                    })
+                    .collect()
+            }
+            _ => return Err("unknown variables reference".into()),
+        };
+        Ok(object(vec![("variables", variables.into())]))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let base = address_of(args.get("memoryReference"))
+            .ok_or("`memoryReference` must be an address")? as i64
+            + args.get("offset").as_i64().unwrap_or(0);
+        let offset = args.get("instructionOffset").as_i64().unwrap_or(0);
+        let count = args.get("instructionCount").as_i64().unwrap_or(0).max(0);
+
+        let debuggee = self.debuggee();
+        let memory = debuggee.debugger.memory();
+        let lines = disasm::disassemble(&memory);
+        let base = usize::try_from(base).unwrap_or(0);
+        let index = lines.partition_point(|l| l.address + l.len <= base) as i64;
+
+        let instructions = (index + offset..index + offset + count)
+            .map(
+                |i| match usize::try_from(i).ok().and_then(|i| lines.get(i)) {
+                    Some(line) => {
+                        let words: Vec<_> = memory[line.address..line.address + line.len]
+                            .iter()
+                            .map(|w| w.to_string())
+                            .collect();
+                        let mut fields = vec![
+                            ("address", line.address.to_string().into()),
+                            ("instructionBytes", words.join(",").into()),
+                            ("instruction", line.text.clone().into()),
+                        ];
+                        if let Some(n) = debuggee.line_of(line.address) {
+                            fields.push(("line", (n + 1).into()));
+                            fields.push(("location", debuggee.source()));
+                        }
+                        object(fields)
+                    }
+                    // Outside memory, one word per instruction
+                    None => {
+                        let address = if i < 0 {
+                            i
+                        } else {
+                            memory.len() as i64 + i - lines.len() as i64
+                        };
+                        object(vec![
+                            ("address", address.to_string().into()),
+                            ("instruction", "".into()),
+                            ("presentationHint", "invalid".into()),
+                        ])
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        Ok(object(vec![("instructions", instructions.into())]))
+    }
+
+    fn evaluate(&mut self, args: &Value) -> Result<Value, String> {
+        let expression = args.get("expression").as_str().unwrap_or("").trim();
+        let debugger = &mut self.debuggee_mut().debugger;
+
+        let result = if let Some(values) = expression.strip_prefix("input") {
+            let values = values
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse::<i64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid input in `{}`", expression))?;
+            debugger.add_input(&values);
+            format!("queued {} input values", values.len())
+        } else if expression == "ip" {
+            debugger.program().instruction_pointer().to_string()
+        } else if expression == "rb" {
+            debugger.program().relative_base().to_string()
+        } else if expression == "dump" {
+            debugger.dump()
+        } else if expression == "diff" {
+            debugger.diff()
+        } else if let Some(address) = expression
+            .strip_prefix('[')
+            .and_then(|e| e.strip_suffix(']'))
+        {
+            let address = address
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid address in `{}`", expression))?;
+            debugger
+                .program()
+                .memory()
+                .read(address)
+                .ok_or_else(|| format!("address {} is outside memory", address))?
+                .to_string()
+        } else {
+            return Err(format!("can't evaluate `{}`", expression));
+        };
+        Ok(object(vec![
+            ("result", result.into()),
+            ("variablesReference", 0.into()),
+        ]))
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        self.running = true;
+        // Leave the current breakpoint before checking for the next
+        match self.debuggee_mut().step() {
+            Some(stop) => self.stopped(stop),
+            None => self.check_breakpoint(),
+        }
+    }
+
+    fn check_breakpoint(&mut self) -> io::Result<()> {
+        self.flush_output()?;
+        if self.debuggee().debugger.at_breakpoint() {
+            self.stopped(Stop::Breakpoint)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn run_slice(&mut self) -> io::Result<()> {
+        match self.debuggee_mut().debugger.run(SLICE) {
+            Some(stop) => self.stopped(stop.into()),
+            None => self.flush_output(),
+        }
+    }
+
+    /// Sends the values output since the last call as `output` events.
+    fn flush_output(&mut self) -> io::Result<()> {
+        let debuggee = self.debuggee_mut();
+        let output = debuggee.debugger.output();
+        let values = output[debuggee.sent..].to_vec();
+        debuggee.sent = output.len();
+        for value in values {
+            self.event(
+                "output",
+                object(vec![
+                    ("category", "stdout".into()),
+                    ("output", format!("{}\n", value).into()),
+                ]),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, stop: Stop) -> io::Result<()> {
+        self.running = false;
+        self.flush_output()?;
+        let (reason, text) = match stop {
+            Stop::Entry => ("entry", None),
+            Stop::Step => ("step", None),
+            Stop::Breakpoint => ("breakpoint", None),
+            Stop::Pause => ("pause", None),
+            Stop::Failed(e) => ("exception", Some(e.to_string())),
+            Stop::Halted => {
+                self.event("exited", object(vec![("exitCode", 0.into())]))?;
+                return self.event("terminated", object(vec![]));
+            }
+        };
+
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(text) = text {
+            body.push(("description", text.clone().into()));
+            body.push(("text", text.into()));
+        }
+        self.event("stopped", object(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_framed_with_their_length() {
+        let message = object(vec![("seq", 1.into()), ("text", "é".into())]);
+        let mut framed = Vec::new();
+        write_message(&mut framed, &message).unwrap();
+        assert_eq!(
+            String::from_utf8(framed.clone()).unwrap(),
+            "Content-Length: 21\r\n\r\n{\"seq\":1,\"text\":\"é\"}"
+        );
+
+        let mut reader = &framed[..];
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+}
@@ -0,0 +1,197 @@
+//! Single stepping and breakpoints, shared by the debug front ends.
+//!
+//! A [`Debugger`] owns a copy of a program image and runs it one instruction
+//! at a time, collecting what it outputs. Both the [debug adapter](crate::dap)
+//! and the [terminal UI](crate::tui) drive their programs through one.
+
+use std::collections::BTreeSet;
+
+use crate::{dump, Error, Program};
+
+/// Why the debugger gave control back.
+#[derive(Debug)]
+pub enum Stop {
+    /// The instruction pointer reached a breakpoint.
+    Breakpoint,
+    /// The instruction pointer reached the address run to.
+    Reached,
+    /// The next instruction failed with the error; the program can carry on
+    /// once the cause is fixed, for example by queueing input.
+    Failed(Error),
+    Halted,
+}
+
+pub struct Debugger {
+    program: Program<'static>,
+    /// The image the program started from.
+    image: Vec<i64>,
+    breakpoints: BTreeSet<usize>,
+    /// Everything the program has output so far.
+    output: Vec<i64>,
+}
+
+impl Debugger {
+    pub fn new(image: &[i64]) -> Debugger {
+        Debugger {
+            program: Program::with_storage(image.to_vec().into_boxed_slice()),
+            image: image.to_vec(),
+            breakpoints: BTreeSet::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn program(&self) -> &Program<'static> {
+        &self.program
+    }
+
+    /// A copy of the program's memory.
+    pub fn memory(&self) -> Vec<i64> {
+        let memory = self.program.memory();
+        (0..memory.len())
+            .map(|address| memory.read(address).unwrap_or(0))
+            .collect()
+    }
+
+    /// The image the program started from.
+    pub fn image(&self) -> &[i64] {
+        &self.image
+    }
+
+    /// The memory as a [`dump`](dump::dump), with the words of each
+    /// instruction on one line.
+    pub fn dump(&self) -> String {
+        dump::dump(&self.memory())
+    }
+
+    /// The cells the program has changed since it started, as a
+    /// [`format_diff`](dump::format_diff) against the image.
+    pub fn diff(&self) -> String {
+        dump::format_diff(&self.image, &self.memory())
+    }
+
+    /// Everything the program has output so far.
+    pub fn output(&self) -> &[i64] {
+        &self.output
+    }
+
+    pub fn add_input(&mut self, values: &[i64]) {
+        self.program.input.extend(values);
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn set_breakpoints<I: IntoIterator<Item = usize>>(&mut self, addresses: I) {
+        self.breakpoints = addresses.into_iter().collect();
+    }
+
+    /// Sets a breakpoint at `address`, or clears the one there. Returns
+    /// whether there is one now.
+    pub fn toggle_breakpoint(&mut self, address: usize) -> bool {
+        if self.breakpoints.remove(&address) {
+            return false;
+        }
+        self.breakpoints.insert(address);
+        true
+    }
+
+    pub fn at_breakpoint(&self) -> bool {
+        self.breakpoints
+            .contains(&self.program.instruction_pointer())
+    }
+
+    /// Runs a single instruction, returning `None` if the program can go on.
+    pub fn step(&mut self) -> Option<Stop> {
+        let result = self.program.run_with_limit(1);
+        self.output.append(&mut self.program.output);
+        match result {
+            Err(Error::StepLimitReached(_)) => None,
+            Ok(()) => Some(Stop::Halted),
+            Err(e) => Some(Stop::Failed(e)),
+        }
+    }
+
+    /// Runs until the program stops or reaches a breakpoint, or for at most
+    /// `limit` instructions, in which case it returns `None`. The first
+    /// instruction always runs, so that a program stopped at a breakpoint
+    /// leaves it.
+    pub fn run(&mut self, limit: usize) -> Option<Stop> {
+        self.run_until(None, limit)
+    }
+
+    /// Like [`run`](Debugger::run), but also stops once the instruction
+    /// pointer reaches `address`.
+    pub fn run_to(&mut self, address: usize, limit: usize) -> Option<Stop> {
+        self.run_until(Some(address), limit)
+    }
+
+    fn run_until(&mut self, target: Option<usize>, limit: usize) -> Option<Stop> {
+        for _ in 0..limit {
+            if let Some(stop) = self.step() {
+                return Some(stop);
+            }
+            let ip = self.program.instruction_pointer();
+            if target == Some(ip) {
+                return Some(Stop::Reached);
+            }
+            if self.breakpoints.contains(&ip) {
+                return Some(Stop::Breakpoint);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts down from the input, printing each value
+    const COUNTDOWN: [i64; 15] = [3, 14, 4, 14, 1001, 14, -1, 14, 1005, 14, 2, 99, 0, 0, 0];
+
+    #[test]
+    fn stops_at_breakpoints_and_targets() {
+        let mut debugger = Debugger::new(&COUNTDOWN);
+        debugger.add_input(&[3]);
+        assert!(debugger.toggle_breakpoint(8));
+        assert!(matches!(debugger.run(100), Some(Stop::Breakpoint)));
+        assert_eq!(debugger.output(), [3]);
+        assert!(debugger.at_breakpoint());
+
+        // Leaves the breakpoint it's stopped at
+        assert!(matches!(debugger.run_to(4, 100), Some(Stop::Reached)));
+        assert_eq!(debugger.program().instruction_pointer(), 4);
+        assert!(!debugger.toggle_breakpoint(8));
+        assert!(debugger.run(2).is_none());
+        assert!(matches!(debugger.run(100), Some(Stop::Halted)));
+        assert_eq!(debugger.output(), [3, 2, 1]);
+        assert_eq!(debugger.memory()[14], 0);
+    }
+
+    #[test]
+    fn failures_can_be_fixed() {
+        let mut debugger = Debugger::new(&COUNTDOWN);
+        assert!(matches!(debugger.step(), Some(Stop::Failed(_))));
+        assert_eq!(debugger.program().instruction_pointer(), 0);
+        debugger.add_input(&[1]);
+        assert!(debugger.step().is_none());
+        assert!(matches!(debugger.run(100), Some(Stop::Halted)));
+        assert_eq!(debugger.output(), [1]);
+    }
+
+    #[test]
+    fn diffs_memory_against_the_image() {
+        let mut debugger = Debugger::new(&COUNTDOWN);
+        assert_eq!(debugger.diff(), "");
+        debugger.add_input(&[2]);
+        debugger.step();
+        assert_eq!(
+            debugger.diff(),
+            "    14:            0 -> 2            DATA 0\n"
+        );
+        assert!(debugger
+            .dump()
+            .starts_with("     0 |    3   14           | IN [14]\n"));
+    }
+}
@@ -0,0 +1,629 @@
+//! Decompiler from program images to structured pseudocode.
+//!
+//! The reachable code is split into basic blocks as for [`transpile`], and
+//! the jumps between blocks laid out in address order are matched against the
+//! shapes compilers produce for `if`, `if`/`else`, `while` and `do`/`while`.
+//! Jumps that don't fit, jumps to computed targets and blocks that the
+//! program overwrites are printed as labelled `goto`s instead.
+//!
+//! Memory cells read or written in position mode are named `v<address>`, or
+//! `mem[<address>]` when they hold code, and relative mode parameters are
+//! printed as in the [`disasm`] listing, such as `[rb+3]`.
+//!
+//! [`disasm`]: crate::disasm
+//! [`transpile`]: crate::transpile
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+
+use crate::disasm;
+use crate::transpile::{analyse, Flow, Instr};
+use crate::ParamMode;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cond {
+    value: String,
+    /// Whether the condition holds when `value` is non-zero.
+    nonzero: bool,
+}
+
+impl Cond {
+    fn negate(&self) -> Cond {
+        Cond {
+            value: self.value.clone(),
+            nonzero: !self.nonzero,
+        }
+    }
+
+    fn render(&self) -> String {
+        let test = if self.nonzero { "!=" } else { "==" };
+        format!("{} {} 0", self.value, test)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Static(usize),
+    Dynamic(String),
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    /// Runs on into the block starting at the address.
+    Next(usize),
+    Halt,
+    /// The instruction at the address can't be decoded.
+    Invalid(usize),
+    Goto(Target),
+    Branch(Cond, Target),
+}
+
+#[derive(Debug)]
+struct Block {
+    end: usize,
+    stmts: Vec<String>,
+    term: Term,
+    /// Whether any instruction writes into this block.
+    modified: bool,
+    /// Whether the block ends moving the relative base and jumping, the way
+    /// calls do, rather than going round a loop.
+    calls: bool,
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Label(usize),
+    Simple(String),
+    If(Cond, Vec<Stmt>, Vec<Stmt>),
+    While(Cond, Vec<Stmt>),
+    DoWhile(Vec<Stmt>, Cond),
+    Goto(Target),
+    IfGoto(Cond, Target),
+    Break,
+}
+
+struct Decompiler<'a> {
+    memory: &'a [i64],
+    flow: Flow,
+    blocks: BTreeMap<usize, Block>,
+    /// Blocks whose jump has been turned into structure.
+    consumed: HashSet<usize>,
+    /// Cells named as variables.
+    variables: BTreeSet<usize>,
+}
+
+impl Decompiler<'_> {
+    fn operand(&mut self, instr: &Instr, n: usize) -> String {
+        let value = instr.params[n];
+        match instr.opcode.modes()[n] {
+            ParamMode::Immediate => value.to_string(),
+            ParamMode::Position => {
+                let address = value as usize;
+                let is_code =
+                    self.flow.code.contains(&address) || self.flow.instrs.contains_key(&address);
+                if is_code {
+                    format!("mem[{}]", address)
+                } else {
+                    self.variables.insert(address);
+                    format!("v{}", address)
+                }
+            }
+            ParamMode::Relative => disasm::operand(ParamMode::Relative, value),
+        }
+    }
+
+    fn statement(&mut self, instr: &Instr) -> String {
+        let imm = |n: usize| {
+            if instr.opcode.modes()[n] == ParamMode::Immediate {
+                Some(instr.params[n])
+            } else {
+                None
+            }
+        };
+        let operands: Vec<String> = (0..instr.len - 1).map(|n| self.operand(instr, n)).collect();
+
+        if let (Some(a), Some(b)) = (imm(0), imm(1)) {
+            let folded = match instr.opcode.op {
+                1 => Some(a.wrapping_add(b)),
+                2 => Some(a.wrapping_mul(b)),
+                7 => Some((a < b) as i64),
+                8 => Some((a == b) as i64),
+                _ => None,
+            };
+            if let Some(value) = folded {
+                return format!("{} = {};", operands[2], value);
+            }
+        }
+
+        match instr.opcode.op {
+            1 => match (imm(0), imm(1)) {
+                (_, Some(0)) => format!("{} = {};", operands[2], operands[0]),
+                (Some(0), _) => format!("{} = {};", operands[2], operands[1]),
+                (_, Some(n)) if n < 0 => {
+                    format!("{} = {} - {};", operands[2], operands[0], -i128::from(n))
+                }
+                _ => format!("{} = {} + {};", operands[2], operands[0], operands[1]),
+            },
+            2 => match (imm(0), imm(1)) {
+                (_, Some(1)) => format!("{} = {};", operands[2], operands[0]),
+                (Some(1), _) => format!("{} = {};", operands[2], operands[1]),
+                (_, Some(-1)) => format!("{} = -{};", operands[2], operands[0]),
+                _ => format!("{} = {} * {};", operands[2], operands[0], operands[1]),
+            },
+            3 => format!("{} = read();", operands[0]),
+            4 => format!("print({});", operands[0]),
+            7 => format!("{} = {} < {};", operands[2], operands[0], operands[1]),
+            8 => format!("{} = {} == {};", operands[2], operands[0], operands[1]),
+            9 => match imm(0) {
+                Some(n) if n < 0 => format!("rb -= {};", -i128::from(n)),
+                _ => format!("rb += {};", operands[0]),
+            },
+            _ => unreachable!("not a straight-line instruction"),
+        }
+    }
+
+    fn jump(&mut self, instr: &Instr, next: usize) -> Term {
+        let nonzero = instr.opcode.op == 5;
+        let target = match instr.static_target() {
+            Some(target) => Target::Static(target),
+            None => Target::Dynamic(self.operand(instr, 1)),
+        };
+        if instr.opcode.arg0 == ParamMode::Immediate {
+            return if (instr.params[0] != 0) == nonzero {
+                Term::Goto(target)
+            } else {
+                Term::Next(next)
+            };
+        }
+        let value = self.operand(instr, 0);
+        Term::Branch(Cond { value, nonzero }, target)
+    }
+
+    fn build_blocks(&mut self) {
+        let starts: Vec<usize> = self.flow.block_starts.iter().copied().collect();
+        for start in starts {
+            let mut stmts = Vec::new();
+            let mut address = start;
+            let mut last_op = None;
+            let (term, end) = loop {
+                let instr = match self.flow.instrs.get(&address) {
+                    Some(Some(instr)) => *instr,
+                    _ => break (Term::Invalid(address), address + 1),
+                };
+                let next = address + instr.len;
+                match instr.opcode.op {
+                    99 => break (Term::Halt, next),
+                    5 | 6 => break (self.jump(&instr, next), next),
+                    _ => stmts.push(self.statement(&instr)),
+                }
+                last_op = Some(instr.opcode.op);
+                address = next;
+                if self.flow.block_starts.contains(&address) {
+                    break (Term::Next(address), address);
+                }
+            };
+            let calls = matches!(term, Term::Goto(_)) && last_op == Some(9);
+            let block = Block {
+                end,
+                stmts,
+                term,
+                modified: false,
+                calls,
+            };
+            self.blocks.insert(start, block);
+        }
+
+        // Data that follows a jump is only decoded in case something jumps
+        // to it, so leave it out unless something does.
+        let mut reached = HashSet::new();
+        let mut stack = vec![0];
+        while let Some(start) = stack.pop() {
+            if !reached.insert(start) {
+                continue;
+            }
+            match &self.blocks[&start].term {
+                Term::Next(next) => stack.push(*next),
+                Term::Goto(Target::Static(t)) => stack.push(*t),
+                Term::Branch(_, target) => {
+                    stack.push(self.blocks[&start].end);
+                    if let Target::Static(t) = target {
+                        stack.push(*t);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.blocks.retain(|start, b| {
+            reached.contains(start) || !matches!(b.term, Term::Invalid(_)) || !b.stmts.is_empty()
+        });
+
+        // Blocks that the program writes into can't be trusted to stay as
+        // they are, so they are left unstructured.
+        let written: Vec<usize> = self
+            .flow
+            .instrs
+            .values()
+            .flatten()
+            .filter(|i| i.writes())
+            .filter(|i| i.opcode.modes()[i.len - 2] == ParamMode::Position)
+            .map(|i| i.params[i.len - 2] as usize)
+            .collect();
+        for (start, block) in self.blocks.iter_mut() {
+            block.modified = written.iter().any(|a| (*start..block.end).contains(a));
+        }
+    }
+
+    fn next_block(&self, address: usize) -> Option<usize> {
+        self.blocks.range(address..).next().map(|(start, _)| *start)
+    }
+
+    /// The last block in `from..end`, if its jump is an unused goto to a
+    /// target accepted by `target`.
+    fn goto_ending_at(
+        &self,
+        end: usize,
+        from: usize,
+        target: &dyn Fn(usize) -> bool,
+    ) -> Option<usize> {
+        self.blocks
+            .range(from..end)
+            .next_back()
+            .filter(|(start, b)| {
+                b.end <= end
+                    && !b.modified
+                    && !b.calls
+                    && !self.consumed.contains(start)
+                    && matches!(b.term, Term::Goto(Target::Static(t)) if target(t))
+            })
+            .map(|(start, _)| *start)
+    }
+
+    /// The last unused jump in `at..until` back to `at`, for a loop headed
+    /// there.
+    fn back_edge(&self, at: usize, until: usize) -> Option<usize> {
+        self.blocks
+            .range(at..until)
+            .rev()
+            .find(|(start, b)| {
+                let to_at = match &b.term {
+                    Term::Goto(Target::Static(t)) | Term::Branch(_, Target::Static(t)) => *t == at,
+                    _ => false,
+                };
+                to_at && b.end <= until && !b.modified && !b.calls && !self.consumed.contains(start)
+            })
+            .map(|(start, _)| *start)
+    }
+
+    /// Structures the blocks starting in `from..until`.
+    fn sequence(&mut self, from: usize, until: usize) -> Vec<Stmt> {
+        let mut out = Vec::new();
+        let mut at = match self.next_block(from) {
+            Some(at) => at,
+            None => return out,
+        };
+
+        while at < until {
+            if let Some(end) = self.while_loop(at, until, &mut out) {
+                at = end;
+            } else if let Some(end) = self.bottom_tested_loop(at, until, &mut out) {
+                at = end;
+            } else {
+                at = self.block(at, until, &mut out);
+            }
+            at = match self.next_block(at) {
+                Some(at) => at,
+                None => break,
+            };
+        }
+
+        // Drop gotos to the very next statement
+        let mut i = 0;
+        while i + 1 < out.len() {
+            match (&out[i], &out[i + 1]) {
+                (Stmt::Goto(Target::Static(t)), Stmt::Label(l)) if t == l => {
+                    out.remove(i);
+                }
+                _ => i += 1,
+            }
+        }
+        out
+    }
+
+    /// A test at the top that leaves the loop, and a goto back to it at the
+    /// bottom.
+    fn while_loop(&mut self, at: usize, until: usize, out: &mut Vec<Stmt>) -> Option<usize> {
+        let header = &self.blocks[&at];
+        let exit = match &header.term {
+            Term::Branch(cond, Target::Static(exit))
+                if *exit > header.end && *exit <= until && !header.modified =>
+            {
+                Some((cond.clone(), *exit))
+            }
+            _ => None,
+        };
+        let (cond, exit) = exit?;
+        let bottom = self.goto_ending_at(exit, at, &|t| t == at)?;
+
+        self.consumed.insert(at);
+        self.consumed.insert(bottom);
+        let header = &self.blocks[&at];
+        let (header_end, stmts) = (header.end, header.stmts.clone());
+
+        out.push(Stmt::Label(at));
+        let mut body = self.sequence(header_end, exit);
+        if stmts.is_empty() {
+            out.push(Stmt::While(cond.negate(), body));
+        } else {
+            let mut inner: Vec<Stmt> = stmts.into_iter().map(Stmt::Simple).collect();
+            inner.push(Stmt::If(cond, vec![Stmt::Break], Vec::new()));
+            inner.append(&mut body);
+            let always = Cond {
+                value: "1".to_string(),
+                nonzero: true,
+            };
+            out.push(Stmt::While(always, inner));
+        }
+        Some(exit)
+    }
+
+    /// A jump back to the top at the bottom of the loop, taken always or
+    /// while a condition holds.
+    fn bottom_tested_loop(
+        &mut self,
+        at: usize,
+        until: usize,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let bottom = self.back_edge(at, until)?;
+        self.consumed.insert(bottom);
+        let block = &self.blocks[&bottom];
+        let (end, term) = (block.end, block.term.clone());
+
+        let body = self.sequence(at, end);
+        match term {
+            Term::Branch(cond, _) => out.push(Stmt::DoWhile(body, cond)),
+            _ => {
+                let always = Cond {
+                    value: "1".to_string(),
+                    nonzero: true,
+                };
+                out.push(Stmt::While(always, body));
+            }
+        }
+        Some(end)
+    }
+
+    /// Emits the block at `at` and returns where to carry on.
+    fn block(&mut self, at: usize, until: usize, out: &mut Vec<Stmt>) -> usize {
+        let block = &self.blocks[&at];
+        let (end, modified) = (block.end, block.modified);
+        let term = if self.consumed.contains(&at) {
+            Term::Next(end)
+        } else {
+            block.term.clone()
+        };
+
+        out.push(Stmt::Label(at));
+        if modified {
+            out.push(Stmt::Simple("// overwritten while running".to_string()));
+        }
+        out.extend(block.stmts.iter().cloned().map(Stmt::Simple));
+
+        match term {
+            Term::Next(next) => return next,
+            Term::Halt => out.push(Stmt::Simple("halt;".to_string())),
+            Term::Invalid(address) => out.push(Stmt::Simple(format!(
+                "invalid({});",
+                self.memory.get(address).copied().unwrap_or_default()
+            ))),
+            Term::Goto(target) => out.push(Stmt::Goto(target)),
+            Term::Branch(cond, Target::Static(target))
+                if !modified && target > end && target <= until =>
+            {
+                // A jump over an `else` branch at the end of the `then` branch
+                let over_else = self.goto_ending_at(target, end, &|t| t > target && t <= until);
+                if let Some(jump) = over_else {
+                    if let Term::Goto(Target::Static(join)) = self.blocks[&jump].term {
+                        self.consumed.insert(jump);
+                        let then = self.sequence(end, target);
+                        let otherwise = self.sequence(target, join);
+                        out.push(Stmt::If(cond.negate(), then, otherwise));
+                        return join;
+                    }
+                }
+                let then = self.sequence(end, target);
+                out.push(Stmt::If(cond.negate(), then, Vec::new()));
+                return target;
+            }
+            Term::Branch(cond, target) => out.push(Stmt::IfGoto(cond, target)),
+        }
+        end
+    }
+}
+
+fn goto_targets(stmts: &[Stmt], targets: &mut HashSet<usize>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Goto(Target::Static(t)) | Stmt::IfGoto(_, Target::Static(t)) => {
+                targets.insert(*t);
+            }
+            Stmt::If(_, then, otherwise) => {
+                goto_targets(then, targets);
+                goto_targets(otherwise, targets);
+            }
+            Stmt::While(_, body) | Stmt::DoWhile(body, _) => goto_targets(body, targets),
+            _ => {}
+        }
+    }
+}
+
+fn target(target: &Target) -> String {
+    match target {
+        Target::Static(t) => format!("L{}", t),
+        Target::Dynamic(value) => format!("*{}", value),
+    }
+}
+
+fn print(stmts: &[Stmt], depth: usize, labels: &HashSet<usize>, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Label(address) if labels.contains(address) => {
+                writeln!(out, "L{}:", address).unwrap();
+            }
+            Stmt::Label(_) => {}
+            Stmt::Simple(text) => writeln!(out, "{}{}", indent, text).unwrap(),
+            Stmt::If(cond, then, otherwise) => {
+                writeln!(out, "{}if ({}) {{", indent, cond.render()).unwrap();
+                print(then, depth + 1, labels, out);
+                if !otherwise.is_empty() {
+                    writeln!(out, "{}}} else {{", indent).unwrap();
+                    print(otherwise, depth + 1, labels, out);
+                }
+                writeln!(out, "{}}}", indent).unwrap();
+            }
+            Stmt::While(cond, body) => {
+                if cond.value == "1" {
+                    writeln!(out, "{}while (true) {{", indent).unwrap();
+                } else {
+                    writeln!(out, "{}while ({}) {{", indent, cond.render()).unwrap();
+                }
+                print(body, depth + 1, labels, out);
+                writeln!(out, "{}}}", indent).unwrap();
+            }
+            Stmt::DoWhile(body, cond) => {
+                writeln!(out, "{}do {{", indent).unwrap();
+                print(body, depth + 1, labels, out);
+                writeln!(out, "{}}} while ({});", indent, cond.render()).unwrap();
+            }
+            Stmt::Goto(t) => writeln!(out, "{}goto {};", indent, target(t)).unwrap(),
+            Stmt::IfGoto(cond, t) => {
+                writeln!(out, "{}if ({}) goto {};", indent, cond.render(), target(t)).unwrap()
+            }
+            Stmt::Break => writeln!(out, "{}break;", indent).unwrap(),
+        }
+    }
+}
+
+/// Decompiles the code reachable from address 0 of `memory` into
+/// pseudocode, starting with the initial values of the variables it uses.
+pub fn decompile(memory: &[i64]) -> String {
+    let mut decompiler = Decompiler {
+        memory,
+        flow: analyse(memory),
+        blocks: BTreeMap::new(),
+        consumed: HashSet::new(),
+        variables: BTreeSet::new(),
+    };
+    decompiler.build_blocks();
+    let stmts = decompiler.sequence(0, usize::MAX);
+
+    let mut labels = HashSet::new();
+    goto_targets(&stmts, &mut labels);
+
+    let mut out = String::new();
+    for address in &decompiler.variables {
+        writeln!(out, "var v{} = {};", address, memory[*address]).unwrap();
+    }
+    if !decompiler.variables.is_empty() {
+        out.push('\n');
+    }
+    print(&stmts, 0, &labels, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang;
+
+    #[test]
+    fn while_loop() {
+        // Counts down from 3, printing each value
+        let memory = [1101, 0, 3, 14, 4, 14, 1001, 14, -1, 14, 1005, 14, 4, 99, 0];
+        assert_eq!(
+            decompile(&memory),
+            "var v14 = 0;\n\
+             \n\
+             v14 = 3;\n\
+             do {\n    \
+                 print(v14);\n    \
+                 v14 = v14 - 1;\n\
+             } while (v14 != 0);\n\
+             halt;\n"
+        );
+    }
+
+    #[test]
+    fn structured_control_flow() {
+        let source = "
+            var n = read();
+            while (n > 0) {
+                if (n == 2) {
+                    print(1);
+                } else {
+                    print(0);
+                }
+                n = n - 1;
+            }
+        ";
+        let memory = lang::compile_with_stack(source, 8).unwrap().memory;
+        let text = decompile(&memory);
+        assert!(!text.contains("goto"), "{}", text);
+        assert!(text.contains("while (true) {\n"), "{}", text);
+        assert!(text.contains("} else {\n        print(0);\n"), "{}", text);
+    }
+
+    #[test]
+    fn day5_comparison() {
+        // Prints 999, 1000 or 1001 as the input is below, equal to or above 8
+        let memory = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        assert_eq!(
+            decompile(&memory),
+            "var v20 = 0;\n\
+             var v21 = 0;\n\
+             \n\
+             v21 = read();\n\
+             v20 = v21 == 8;\n\
+             if (v20 == 0) {\n    \
+                 v20 = 8 < v21;\n    \
+                 if (v20 == 0) goto L31;\n\
+             } else {\n    \
+                 v20 = v21 * 125;\n    \
+                 print(v20);\n    \
+                 goto L46;\n\
+             L31:\n    \
+                 print(999);\n    \
+                 goto L46;\n\
+             }\n\
+             v20 = 1001;\n\
+             print(v20);\n\
+             L46:\n\
+             halt;\n"
+        );
+    }
+
+    #[test]
+    fn computed_and_modified_code_uses_gotos() {
+        // Jumps through the cell at 7, then overwrites the cell printed at 9
+        let memory = [1105, 1, 3, 1101, 5, 0, 10, 1105, 1, 9, 4, 0, 99];
+        let text = decompile(&memory);
+        assert!(text.contains("mem[10] = 5;"), "{}", text);
+        assert!(text.contains("// overwritten while running"), "{}", text);
+
+        let memory = [1106, 0, 4, 99, 2105, 1, 0];
+        assert!(decompile(&memory).contains("goto *[rb];"));
+    }
+
+    #[test]
+    fn relative_parameters_read_as_in_the_listing() {
+        let memory = [109, 4, 22201, -2, 2, 3, 204, 3, 99];
+        let text = decompile(&memory);
+        assert!(text.contains("[rb+3] = [rb-2] + [rb+2];"), "{}", text);
+        assert!(text.contains("print([rb+3]);"), "{}", text);
+    }
+}
@@ -0,0 +1,235 @@
+//! Peripherals that can be mapped into a [`Program`]'s address space.
+//!
+//! A device attached with [`Program::attach`] answers every read and write
+//! to the addresses it occupies, including instruction fetches, and is told
+//! when an instruction finishes through [`Device::tick`].
+//!
+//! Devices can be attached by value or by mutable reference; the latter lets
+//! the caller inspect the device once the program is done with it.
+//!
+//! [`Program`]: ../struct.Program.html
+//! [`Program::attach`]: ../struct.Program.html#method.attach
+//! [`Device::tick`]: trait.Device.html#method.tick
+
+use crate::xorshift::Xorshift;
+
+pub trait Device {
+    /// Number of consecutive addresses the device occupies.
+    fn size(&self) -> usize;
+
+    /// Reads the cell at `offset` from the address the device is mapped at.
+    fn read(&mut self, offset: usize) -> i64;
+
+    /// Writes `value` to the cell at `offset`.
+    fn write(&mut self, offset: usize, value: i64);
+
+    /// Called after every executed instruction.
+    fn tick(&mut self) {}
+}
+
+impl<D: Device + ?Sized> Device for &mut D {
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+
+    fn read(&mut self, offset: usize) -> i64 {
+        (**self).read(offset)
+    }
+
+    fn write(&mut self, offset: usize, value: i64) {
+        (**self).write(offset, value)
+    }
+
+    fn tick(&mut self) {
+        (**self).tick()
+    }
+}
+
+/// A single cell holding the number of instructions executed so far.
+///
+/// Writing to the cell sets the counter.
+#[derive(Debug, Default)]
+pub struct CycleCounter {
+    cycles: i64,
+}
+
+impl CycleCounter {
+    pub fn new() -> CycleCounter {
+        CycleCounter::default()
+    }
+
+    pub fn cycles(&self) -> i64 {
+        self.cycles
+    }
+}
+
+impl Device for CycleCounter {
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize) -> i64 {
+        self.cycles
+    }
+
+    fn write(&mut self, _offset: usize, value: i64) {
+        self.cycles = value;
+    }
+
+    fn tick(&mut self) {
+        self.cycles = self.cycles.wrapping_add(1);
+    }
+}
+
+/// A single cell producing a non-negative pseudo-random number on each read.
+///
+/// The sequence is fully determined by the seed, so runs are repeatable.
+/// Writing to the cell reseeds the generator.
+#[derive(Debug)]
+pub struct Random {
+    rng: Xorshift,
+}
+
+impl Random {
+    pub fn with_seed(seed: u64) -> Random {
+        Random {
+            rng: Xorshift::new(seed),
+        }
+    }
+}
+
+impl Device for Random {
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize) -> i64 {
+        (self.rng.next_u64() >> 33) as i64
+    }
+
+    fn write(&mut self, _offset: usize, value: i64) {
+        self.rng = Xorshift::new(value as u64);
+    }
+}
+
+/// A `width` by `height` grid of cells laid out row by row.
+#[derive(Debug)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<i64>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> i64 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[i64] {
+        &self.pixels
+    }
+
+    /// Renders the grid as text, with `#` for non-zero cells.
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            row.iter()
+                .for_each(|p| out.push(if *p == 0 { ' ' } else { '#' }));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Device for Framebuffer {
+    fn size(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn read(&mut self, offset: usize) -> i64 {
+        self.pixels[offset]
+    }
+
+    fn write(&mut self, offset: usize, value: i64) {
+        self.pixels[offset] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    #[test]
+    fn cycle_counter_counts_executed_instructions() {
+        let mut mem = vec![1101, 0, 0, 11, 1101, 0, 0, 11, 4, 100, 99, 0];
+        let p = Program::new(&mut mem)
+            .attach(100, CycleCounter::new())
+            .run();
+        assert_eq!(p.output(), &[2]);
+    }
+
+    #[test]
+    fn random_is_repeatable_for_a_seed() {
+        let run = |seed| {
+            let mut mem = vec![4, 50, 4, 50, 99];
+            let p = Program::new(&mut mem)
+                .attach(50, Random::with_seed(seed))
+                .run();
+            p.output().to_vec()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        assert!(run(7).iter().all(|v| *v >= 0));
+
+        // The seed that cancels out the mixing constant
+        let stuck = run(0x9e37_79b9_7f4a_7c15);
+        assert_ne!(stuck[0], stuck[1]);
+    }
+
+    #[test]
+    fn framebuffer_receives_writes_and_is_inspectable() {
+        let mut fb = Framebuffer::new(2, 2);
+        let mut mem = vec![1101, 1, 0, 1000, 1101, 0, 1, 1003, 99];
+        Program::new(&mut mem).attach(1000, &mut fb).run();
+
+        assert_eq!(fb.pixels(), &[1, 0, 0, 1]);
+        assert_eq!(fb.render(), "# \n #\n");
+        assert_eq!(mem, &[1101, 1, 0, 1000, 1101, 0, 1, 1003, 99]);
+    }
+
+    #[test]
+    fn device_shadows_memory() {
+        let mut fb = Framebuffer::new(1, 1);
+        let mut mem = vec![1101, 5, 5, 5, 99, 0];
+        Program::new(&mut mem).attach(5, &mut fb).run();
+
+        assert_eq!(fb.pixel(0, 0), 10);
+        assert_eq!(mem[5], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps")]
+    fn overlapping_devices_are_rejected() {
+        let mut mem = vec![99];
+        Program::new(&mut mem)
+            .attach(10, Framebuffer::new(4, 1))
+            .attach(12, CycleCounter::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps the end of the address space")]
+    fn devices_past_the_last_address_are_rejected() {
+        let mut mem = vec![99];
+        Program::new(&mut mem).attach(usize::MAX - 2, Framebuffer::new(4, 1));
+    }
+}
@@ -0,0 +1,170 @@
+//! Disassembler producing a mnemonic listing of a memory image.
+//!
+//! Parameters are written as `12` in immediate mode, `[12]` in position mode
+//! and `[rb+12]` in relative mode. Words that don't decode as an instruction
+//! are listed one at a time as `DATA`.
+
+use std::convert::TryFrom;
+
+use crate::{OpCode, ParamMode};
+
+/// A single instruction or data word of a listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: usize,
+    /// Number of words the line covers.
+    pub len: usize,
+    pub text: String,
+}
+
+pub fn mnemonic(op: u8) -> Option<&'static str> {
+    match op {
+        1 => Some("ADD"),
+        2 => Some("MUL"),
+        3 => Some("IN"),
+        4 => Some("OUT"),
+        5 => Some("JNZ"),
+        6 => Some("JZ"),
+        7 => Some("LT"),
+        8 => Some("EQ"),
+        9 => Some("ARB"),
+        99 => Some("HALT"),
+        _ => None,
+    }
+}
+
+pub(crate) fn operand(mode: ParamMode, value: i64) -> String {
+    match mode {
+        ParamMode::Immediate => format!("{}", value),
+        ParamMode::Position => format!("[{}]", value),
+        ParamMode::Relative if value < 0 => format!("[rb{}]", value),
+        ParamMode::Relative if value > 0 => format!("[rb+{}]", value),
+        ParamMode::Relative => "[rb]".to_string(),
+    }
+}
+
+/// Decodes the instruction at `address`, if there is a complete one.
+pub fn decode(memory: &[i64], address: usize) -> Option<Line> {
+    let opcode = OpCode::try_from(*memory.get(address)?).ok()?;
+    let name = mnemonic(opcode.op)?;
+    let count = opcode.param_count()?;
+    let params = memory.get(address + 1..address + 1 + count)?;
+
+    let operands: Vec<_> = params
+        .iter()
+        .zip(opcode.modes().iter())
+        .map(|(value, mode)| operand(*mode, *value))
+        .collect();
+    let text = if operands.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} {}", name, operands.join(", "))
+    };
+
+    Some(Line {
+        address,
+        len: count + 1,
+        text,
+    })
+}
+
+/// Decodes `memory` from start to end, one instruction after another.
+pub fn disassemble(memory: &[i64]) -> Vec<Line> {
+    disassemble_from(memory, 0, Vec::new())
+}
+
+/// Like [`disassemble`], but with a line starting at `address`, so that the
+/// instruction there is listed even if it doesn't line up with those before
+/// it. Instructions that would run past `address` are listed as data.
+pub fn disassemble_at(memory: &[i64], address: usize) -> Vec<Line> {
+    let address = address.min(memory.len());
+    disassemble_from(memory, address, disassemble(&memory[..address]))
+}
+
+fn disassemble_from(memory: &[i64], mut address: usize, mut lines: Vec<Line>) -> Vec<Line> {
+    while address < memory.len() {
+        let line = decode(memory, address).unwrap_or_else(|| Line {
+            address,
+            len: 1,
+            text: format!("DATA {}", memory[address]),
+        });
+        address += line.len;
+        lines.push(line);
+    }
+    lines
+}
+
+/// Formats a line with its address and raw words.
+pub fn format_line(memory: &[i64], line: &Line) -> String {
+    let words: Vec<_> = memory[line.address..line.address + line.len]
+        .iter()
+        .map(|w| w.to_string())
+        .collect();
+    format!("{:>6}  {:<28}{}", line.address, words.join(","), line.text)
+}
+
+pub fn listing(memory: &[i64]) -> String {
+    disassemble(memory)
+        .iter()
+        .map(|line| format_line(memory, line) + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_all_parameter_modes() {
+        let memory = vec![21101, 5, -2, 3, 204, 0, 1006, 7, 0, 99];
+        let text: Vec<_> = disassemble(&memory).into_iter().map(|l| l.text).collect();
+        assert_eq!(
+            text,
+            vec!["ADD 5, -2, [rb+3]", "OUT [rb]", "JZ [7], 0", "HALT"]
+        );
+    }
+
+    #[test]
+    fn undecodable_words_are_data() {
+        let memory = vec![1, 0, 0, 0, 0, 12345, 2, 0];
+        let lines = disassemble(&memory);
+        let text: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "ADD [0], [0], [0]",
+                "DATA 0",
+                "DATA 12345",
+                "DATA 2",
+                "DATA 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_up_with_an_address() {
+        // Jumps into the middle of what reads as an addition from the start
+        let memory = vec![1, 1105, 1, 0, 99];
+        let text: Vec<_> = disassemble_at(&memory, 1)
+            .into_iter()
+            .map(|l| (l.address, l.text))
+            .collect();
+        assert_eq!(
+            text,
+            vec![
+                (0, "DATA 1".to_string()),
+                (1, "JNZ 1, 0".to_string()),
+                (4, "HALT".to_string())
+            ]
+        );
+        assert_eq!(disassemble_at(&memory, 0), disassemble(&memory));
+    }
+
+    #[test]
+    fn listing_shows_addresses_and_words() {
+        assert_eq!(
+            listing(&[1101, 1, 2, 0, 99]),
+            "     0  1101,1,2,0                  ADD 1, 2, [0]\n     4  99                          HALT\n"
+        );
+    }
+}
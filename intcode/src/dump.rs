@@ -0,0 +1,174 @@
+//! Diffs and dumps of memory images.
+//!
+//! [`diff`] lists the cells that differ between two images, each with the
+//! instruction it belongs to, and [`dump`] prints an image with its words
+//! grouped into decoded instructions and lined up in columns:
+//!
+//! ```text
+//!      0 |  1  9 10  3 | ADD [9], [10], [3]
+//!      4 |  2  3 11  0 | MUL [3], [11], [0]
+//!      8 | 99          | HALT
+//!      9 | 30          | DATA 30
+//! ```
+//!
+//! Instructions are decoded with the disassembler from the first address
+//! on, so data between instructions may throw the grouping off.
+
+use std::fmt::Write;
+
+use crate::disasm::{self, Line};
+
+/// A cell that differs between two images; `None` where the image is too
+/// short to have it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub address: usize,
+    pub before: Option<i64>,
+    pub after: Option<i64>,
+}
+
+pub fn diff(before: &[i64], after: &[i64]) -> Vec<Change> {
+    (0..before.len().max(after.len()))
+        .map(|address| Change {
+            address,
+            before: before.get(address).copied(),
+            after: after.get(address).copied(),
+        })
+        .filter(|c| c.before != c.after)
+        .collect()
+}
+
+fn value(v: Option<i64>) -> String {
+    v.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+/// The line of `lines` that covers `address`.
+fn covering(lines: &[Line], address: usize) -> Option<&Line> {
+    let i = lines.partition_point(|l| l.address + l.len <= address);
+    lines.get(i).filter(|l| l.address <= address)
+}
+
+/// One line per changed cell: its address, both values and the instruction
+/// it is part of in `before`.
+pub fn format_diff(before: &[i64], after: &[i64]) -> String {
+    let lines = disasm::disassemble(before);
+    let mut out = String::new();
+    for change in diff(before, after) {
+        let context = match covering(&lines, change.address) {
+            Some(line) if line.len == 1 => line.text.clone(),
+            Some(line) if line.address == change.address => format!("opcode of {}", line.text),
+            Some(line) => format!(
+                "word {} of {} at {}",
+                change.address - line.address,
+                line.text,
+                line.address
+            ),
+            None => "past the end".to_string(),
+        };
+        writeln!(
+            out,
+            "{:>6}: {:>12} -> {:<12} {}",
+            change.address,
+            value(change.before),
+            value(change.after),
+            context
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Panics with the [`format_diff`] of the two images if they differ.
+#[track_caller]
+pub fn assert_same(expected: &[i64], actual: &[i64]) {
+    if expected != actual {
+        panic!(
+            "memory differs from the expected image:\n{}",
+            format_diff(expected, actual)
+        );
+    }
+}
+
+/// The image with its words grouped into decoded instructions, one per line.
+pub fn dump(memory: &[i64]) -> String {
+    let width = memory
+        .iter()
+        .map(|v| v.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let mut out = String::new();
+    for line in disasm::disassemble(memory) {
+        let words: Vec<String> = (0..4)
+            .map(|n| match memory.get(line.address + n) {
+                Some(v) if n < line.len => format!("{:>1$}", v, width),
+                _ => " ".repeat(width),
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:>6} | {} | {}",
+            line.address,
+            words.join(" "),
+            line.text
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_shows_changed_cells_in_context() {
+        let before = [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let after = [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50, 7];
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change {
+                    address: 0,
+                    before: Some(1),
+                    after: Some(3500)
+                },
+                Change {
+                    address: 3,
+                    before: Some(3),
+                    after: Some(70)
+                },
+                Change {
+                    address: 12,
+                    before: None,
+                    after: Some(7)
+                },
+            ]
+        );
+        assert_eq!(
+            format_diff(&before, &after),
+            "     0:            1 -> 3500         opcode of ADD [9], [10], [3]\n\
+             \x20    3:            3 -> 70           word 3 of ADD [9], [10], [3] at 0\n\
+             \x20   12:            - -> 7            past the end\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "word 3 of ADD [0], [0], [0] at 0")]
+    fn assert_same_panics_with_the_diff() {
+        assert_same(&[1, 0, 0, 0, 99], &[1, 0, 0, 2, 99]);
+    }
+
+    #[test]
+    fn dump_lines_up_instructions() {
+        let memory = [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        assert_eq!(
+            dump(&memory),
+            "     0 |  1  9 10  3 | ADD [9], [10], [3]\n\
+             \x20    4 |  2  3 11  0 | MUL [3], [11], [0]\n\
+             \x20    8 | 99          | HALT\n\
+             \x20    9 | 30          | DATA 30\n\
+             \x20   10 | 40          | DATA 40\n\
+             \x20   11 | 50          | DATA 50\n"
+        );
+    }
+}
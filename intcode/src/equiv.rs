@@ -0,0 +1,350 @@
+//! Checks that two programs behave the same, such as a program and its
+//! optimized or transpiled form.
+//!
+//! A [`Checker`] runs both programs on a corpus of inputs: boundary values
+//! first, then random sequences generated from a seed, so checks are
+//! repeatable. For each input it compares the output streams and how the
+//! runs ended: halting, waiting for more input or failing, and for failures
+//! the kind of error. Runs that use up
+//! the step limit are inconclusive, so they only have to agree on the output
+//! they got to.
+//!
+//! The first input that tells the programs apart is shrunk, dropping values
+//! and moving them towards zero while the difference remains, and reported
+//! as a [`Difference`].
+
+use std::fmt::{self, Display};
+use std::mem::discriminant;
+
+use crate::batch::{run_job, Batch, Job};
+use crate::xorshift::Xorshift;
+use crate::Error;
+
+pub const STEP_LIMIT: usize = 100_000;
+
+/// Values tried on their own and repeated before any random input.
+const BOUNDARY: [i64; 15] = [
+    0,
+    1,
+    -1,
+    2,
+    -2,
+    7,
+    8,
+    9,
+    10,
+    100,
+    -100,
+    i32::MAX as i64,
+    i32::MIN as i64,
+    i64::MAX,
+    i64::MIN,
+];
+
+/// Most runs made while shrinking a difference.
+const SHRINK_RUNS: usize = 10_000;
+
+/// How a run ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    Halted,
+    NeedsInput,
+    OutOfSteps,
+    Failed(Error),
+}
+
+/// The output of a run and how it ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub output: Vec<i64>,
+    pub stop: Stop,
+}
+
+impl Run {
+    /// Whether the runs can't be told apart. Failures have to fail with the
+    /// same kind of error, but not at the same address, as addresses can
+    /// differ between the programs.
+    fn agrees_with(&self, other: &Run) -> bool {
+        use Stop::*;
+        match (&self.stop, &other.stop) {
+            (OutOfSteps, _) | (_, OutOfSteps) => {
+                let n = self.output.len().min(other.output.len());
+                self.output[..n] == other.output[..n]
+            }
+            (Halted, Halted) | (NeedsInput, NeedsInput) => self.output == other.output,
+            (Failed(a), Failed(b)) => self.output == other.output && same_kind(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Whether the errors are the same variant, down to the kind of invalid op,
+/// whatever their addresses and values.
+fn same_kind(a: &Error, b: &Error) -> bool {
+    match (a, b) {
+        (Error::InvalidOp(_, a), Error::InvalidOp(_, b)) => discriminant(a) == discriminant(b),
+        _ => discriminant(a) == discriminant(b),
+    }
+}
+
+impl Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output {:?}, ", self.output)?;
+        match &self.stop {
+            Stop::Halted => write!(f, "halted"),
+            Stop::NeedsInput => write!(f, "needed more input"),
+            Stop::OutOfSteps => write!(f, "ran out of steps"),
+            Stop::Failed(e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+/// An input the programs behave differently on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub input: Vec<i64>,
+    pub left: Run,
+    pub right: Run,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "programs differ on input {:?}", self.input)?;
+        writeln!(f, "  left:  {}", self.left)?;
+        write!(f, "  right: {}", self.right)
+    }
+}
+
+/// Values closer to zero than `value`, simplest first.
+fn simpler(value: i64) -> impl Iterator<Item = i64> {
+    let key = |v: i64| (v.unsigned_abs(), v < 0);
+    IntoIterator::into_iter([0, 1, value / 2, value - value.signum(), -value])
+        .filter(move |v| key(*v) < key(value))
+}
+
+#[derive(Debug, Clone)]
+pub struct Checker {
+    step_limit: usize,
+    inputs: usize,
+    max_length: usize,
+    seed: u64,
+    threads: Option<usize>,
+}
+
+impl Default for Checker {
+    fn default() -> Checker {
+        Checker::new()
+    }
+}
+
+impl Checker {
+    /// A checker trying 500 random inputs of up to 8 values each, after the
+    /// boundary values.
+    pub fn new() -> Checker {
+        Checker {
+            step_limit: STEP_LIMIT,
+            inputs: 500,
+            max_length: 8,
+            seed: 2019,
+            threads: None,
+        }
+    }
+
+    pub fn with_step_limit(mut self, step_limit: usize) -> Checker {
+        self.step_limit = step_limit;
+        self
+    }
+
+    /// Tries `inputs` random inputs, each up to `max_length` values long.
+    pub fn with_inputs(mut self, inputs: usize, max_length: usize) -> Checker {
+        self.inputs = inputs;
+        self.max_length = max_length.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Checker {
+        self.seed = seed;
+        self
+    }
+
+    /// Runs on `threads` threads rather than as many as the machine runs in
+    /// parallel.
+    pub fn with_threads(mut self, threads: usize) -> Checker {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// The inputs tried, in order: no input, each boundary value alone and
+    /// repeated, then random sequences.
+    pub fn corpus(&self) -> Vec<Vec<i64>> {
+        let mut corpus = vec![Vec::new()];
+        corpus.extend(BOUNDARY.iter().map(|v| vec![*v]));
+        corpus.extend(BOUNDARY.iter().map(|v| vec![*v; self.max_length]));
+
+        let mut rng = Xorshift::new(self.seed);
+        for _ in 0..self.inputs {
+            let len = 1 + rng.next_u64() as usize % self.max_length;
+            let input = (0..len)
+                .map(|_| {
+                    let r = rng.next_u64();
+                    match r % 4 {
+                        0 | 1 => ((r >> 8) % 33) as i64 - 16,
+                        2 => BOUNDARY[(r >> 8) as usize % BOUNDARY.len()],
+                        _ => (r >> 32) as i32 as i64,
+                    }
+                })
+                .collect();
+            corpus.push(input);
+        }
+        corpus
+    }
+
+    /// Runs `image` on `input`.
+    pub fn run(&self, image: &[i64], input: &[i64]) -> Run {
+        let outcome = run_job(image, Job::new().add_input(input), Some(self.step_limit));
+        let stop = match outcome.result {
+            Ok(()) => Stop::Halted,
+            Err(Error::MissingInput(_)) => Stop::NeedsInput,
+            Err(Error::StepLimitReached(_)) => Stop::OutOfSteps,
+            Err(e) => Stop::Failed(e),
+        };
+        Run {
+            output: outcome.output,
+            stop,
+        }
+    }
+
+    /// Runs both programs on `input`, returning how they differ if they do.
+    pub fn compare(&self, left: &[i64], right: &[i64], input: &[i64]) -> Option<Difference> {
+        let (l, r) = (self.run(left, input), self.run(right, input));
+        if l.agrees_with(&r) {
+            None
+        } else {
+            Some(Difference {
+                input: input.to_vec(),
+                left: l,
+                right: r,
+            })
+        }
+    }
+
+    /// Checks the programs against each other on the whole corpus,
+    /// returning the number of inputs tried, or the smallest difference
+    /// found.
+    pub fn check(&self, left: &[i64], right: &[i64]) -> Result<usize, Box<Difference>> {
+        let mut batch = Batch::new(left);
+        if let Some(threads) = self.threads {
+            batch = batch.with_threads(threads);
+        }
+        let results = batch.map_until(
+            self.corpus(),
+            |_, input| self.compare(left, right, &input),
+            Option::is_some,
+        );
+        match results.last() {
+            Some(Some(difference)) => Err(Box::new(self.shrink(left, right, difference.clone()))),
+            _ => Ok(results.len()),
+        }
+    }
+
+    /// Simplifies the input of `difference` for as long as the programs
+    /// still differ on it.
+    pub fn shrink(&self, left: &[i64], right: &[i64], mut difference: Difference) -> Difference {
+        let mut runs = 0;
+        'shrink: while runs < SHRINK_RUNS {
+            let input = &difference.input;
+            let shorter = (0..input.len()).map(|n| {
+                let mut candidate = input.clone();
+                candidate.remove(n);
+                candidate
+            });
+            let smaller = (0..input.len()).flat_map(|n| {
+                simpler(input[n]).map(move |v| {
+                    let mut candidate = input.clone();
+                    candidate[n] = v;
+                    candidate
+                })
+            });
+            for candidate in shorter.chain(smaller) {
+                runs += 1;
+                if let Some(smaller) = self.compare(left, right, &candidate) {
+                    difference = smaller;
+                    continue 'shrink;
+                }
+                if runs >= SHRINK_RUNS {
+                    break;
+                }
+            }
+            break;
+        }
+        difference
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimize::optimize;
+
+    // Outputs 999, 1000 or 1001 as the input is below, equal to or above 8
+    const COMPARE: [i64; 47] = [
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+
+    #[test]
+    fn optimized_programs_are_equivalent() {
+        let mut optimized = COMPARE.to_vec();
+        optimize(&mut optimized).unwrap();
+        let checker = Checker::new().with_inputs(100, 4);
+        assert_eq!(
+            checker.check(&COMPARE, &optimized),
+            Ok(checker.corpus().len())
+        );
+    }
+
+    #[test]
+    fn reports_the_smallest_difference() {
+        // Outputs the sum of two inputs, and the same but wrong once the
+        // first is above 40 and the second negative
+        let sum = [3, 13, 3, 14, 1, 13, 14, 15, 4, 15, 99, 0, 0, 0, 0, 0];
+        let mut buggy = vec![
+            3, 30, 3, 31, 1007, 30, 41, 32, 1005, 32, 23, 1007, 31, 0, 32, 1006, 32, 23, 104, -1,
+            1105, 1, 29, 1, 30, 31, 33, 4, 33, 99,
+        ];
+        buggy.resize(34, 0);
+
+        let difference = Checker::new().check(&sum, &buggy).unwrap_err();
+        assert_eq!(difference.input, [41, -1]);
+        assert_eq!(
+            difference.to_string(),
+            "programs differ on input [41, -1]\n  \
+             left:  output [40], halted\n  \
+             right: output [-1], halted"
+        );
+    }
+
+    #[test]
+    fn compares_how_runs_end() {
+        let checker = Checker::new().with_step_limit(50);
+        let halts = [99];
+        let fails = [98];
+        let reads = [3, 3, 99, 0];
+        let spins = [104, 1, 1105, 1, 0];
+
+        assert!(checker.compare(&halts, &fails, &[]).is_some());
+
+        // Failures agree on the kind of error, wherever they happen
+        assert!(checker.compare(&fails, &[1106, 0, 3, 97], &[]).is_none());
+        assert!(checker.compare(&fails, &[1105, 1, -1], &[]).is_some());
+        assert!(checker.compare(&fails, &[1, 0, 0, 9], &[]).is_some());
+        assert!(checker.compare(&reads, &reads, &[]).is_none());
+        assert_eq!(checker.compare(&halts, &reads, &[5]).map(|d| d.input), None);
+        assert!(checker.compare(&halts, &reads, &[]).is_some());
+
+        // Running out of steps only needs the output so far to match
+        assert!(checker.compare(&spins, &[104, 1, 99], &[]).is_none());
+        assert!(checker.compare(&spins, &[104, 2, 99], &[]).is_some());
+    }
+}
@@ -0,0 +1,242 @@
+//! Differential fuzzing of [`Program`] against the [`reference`] interpreter.
+//!
+//! [`generate`] turns an arbitrary byte string into a well formed program
+//! image and its input, so a fuzzer mutating the bytes explores program
+//! structure rather than tripping over decoding errors. [`check`] runs the
+//! case on both interpreters under a step limit and reports the first
+//! difference in output, final memory or the reason the run stopped.
+//!
+//! [`Program`]: ../struct.Program.html
+//! [`reference`]: ../reference/index.html
+//! [`generate`]: fn.generate.html
+//! [`check`]: fn.check.html
+
+use std::convert::TryFrom;
+
+use crate::reference::{self, Stop};
+use crate::{Error, ParamMode, Profile, Program};
+
+pub const STEP_LIMIT: usize = 1_000;
+
+const MAX_INSTRUCTIONS: usize = 32;
+const MAX_DATA: usize = 8;
+const MAX_INPUT: usize = 8;
+
+/// Opcodes the generator picks from, with the number of parameters each
+/// takes and whether the last one is written to.
+const OPS: [(i64, usize, bool); 9] = [
+    (1, 3, true),
+    (2, 3, true),
+    (3, 1, true),
+    (4, 1, false),
+    (5, 2, false),
+    (6, 2, false),
+    (7, 3, true),
+    (8, 3, true),
+    (9, 1, false),
+];
+
+/// Reads choices from a byte string, yielding zeroes once it is exhausted.
+pub struct Entropy<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Entropy<'a> {
+    pub fn new(data: &'a [u8]) -> Entropy<'a> {
+        Entropy { data, pos: 0 }
+    }
+
+    fn byte(&mut self) -> u8 {
+        let b = self.data.get(self.pos).copied().unwrap_or(0);
+        self.pos += 1;
+        b
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        let v = (self.byte() as usize) << 8 | self.byte() as usize;
+        v % n
+    }
+
+    fn value(&mut self) -> i64 {
+        i64::from(self.byte() as i8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub memory: Vec<i64>,
+    pub input: Vec<i64>,
+}
+
+/// Builds a program image out of `data`.
+///
+/// Every instruction in the image decodes, position and relative parameters
+/// point into memory while the relative base is zero, and written parameters
+/// are never in immediate mode. The image ends with a halt followed by a few
+/// data cells, and jumps prefer instruction starts, so most runs either halt,
+/// loop until the step limit or fail only after modifying their own code.
+pub fn generate(data: &[u8]) -> Case {
+    generate_for(data, Profile::Day9)
+}
+
+/// Like [`generate`](fn.generate.html), but only uses the instructions and
+/// parameter modes of `profile`.
+pub fn generate_for(data: &[u8], profile: Profile) -> Case {
+    let mut e = Entropy::new(data);
+
+    let available: Vec<_> = OPS
+        .iter()
+        .filter(|(op, _, _)| profile.allows_op(*op as u8))
+        .collect();
+    let modes: Vec<usize> = (0..3)
+        .filter(|m| profile.allows_mode(&ParamMode::try_from(*m as i64).unwrap()))
+        .collect();
+    let target_modes: Vec<usize> = modes.iter().copied().filter(|m| *m != 1).collect();
+
+    let ops: Vec<_> = (0..=e.below(MAX_INSTRUCTIONS))
+        .map(|_| *available[e.below(available.len())])
+        .collect();
+    let starts: Vec<usize> = ops
+        .iter()
+        .scan(0, |addr, (_, params, _)| {
+            let start = *addr;
+            *addr += params + 1;
+            Some(start)
+        })
+        .collect();
+    let halt = starts.last().map_or(0, |s| s + ops.last().unwrap().1 + 1);
+    let len = halt + 1 + e.below(MAX_DATA) + 1;
+
+    let mut memory = Vec::with_capacity(len);
+    for (op, params, writes) in ops.iter() {
+        let mut opcode = *op;
+        let mut operands = Vec::new();
+        for k in 0..*params {
+            let is_target = *writes && k == params - 1;
+            let mode = if is_target {
+                target_modes[e.below(target_modes.len())]
+            } else {
+                modes[e.below(modes.len())]
+            };
+            opcode += 100 * 10i64.pow(k as u32) * mode as i64;
+            if mode == 1 {
+                let is_jump = (*op == 5 || *op == 6) && k == 1;
+                operands.push(if is_jump && e.below(4) != 0 {
+                    starts.get(e.below(starts.len())).copied().unwrap_or(halt) as i64
+                } else {
+                    e.value()
+                });
+            } else {
+                operands.push(e.below(len) as i64);
+            }
+        }
+        memory.push(opcode);
+        memory.extend(operands);
+    }
+    memory.push(99);
+    while memory.len() < len {
+        memory.push(e.value());
+    }
+
+    let input = (0..e.below(MAX_INPUT)).map(|_| e.value()).collect();
+
+    Case { memory, input }
+}
+
+#[derive(Debug)]
+pub enum Mismatch {
+    Stop(Result<(), Error>, Stop),
+    Output(Vec<i64>, Vec<i64>),
+    Memory(Vec<i64>, Vec<i64>),
+}
+
+fn same_stop(result: &Result<(), Error>, stop: &Stop) -> bool {
+    match (result, stop) {
+        (Ok(()), Stop::Halted) => true,
+        (Err(Error::InvalidOp(a, _)), Stop::InvalidOp(b)) => a == b,
+        (Err(Error::ReadOutOfBounds(a)), Stop::ReadOutOfBounds(b)) => a == b,
+        (Err(Error::NegativeJumpTarget(a, _)), Stop::NegativeJumpTarget(b)) => a == b,
+        (Err(Error::MissingInput(a)), Stop::MissingInput(b)) => a == b,
+        (Err(Error::StepLimitReached(_)), Stop::StepLimitReached) => true,
+        _ => false,
+    }
+}
+
+/// Runs `case` on both interpreters and compares the results, with and
+/// without the decoded instruction cache.
+pub fn check(case: &Case, step_limit: usize) -> Result<(), Mismatch> {
+    let mut expected_mem = case.memory.clone();
+    let expected = reference::run(&mut expected_mem, &case.input, step_limit);
+
+    for cache in [true, false].iter() {
+        let mut actual_mem = case.memory.clone();
+        let (result, output) = {
+            let mut program = Program::new(&mut actual_mem)
+                .with_decode_cache(*cache)
+                .add_input(&case.input);
+            let result = program.run_with_limit(step_limit);
+            (result, program.output().to_vec())
+        };
+
+        if !same_stop(&result, &expected.stop) {
+            return Err(Mismatch::Stop(result, expected.stop));
+        }
+        if output != expected.output {
+            return Err(Mismatch::Output(output, expected.output));
+        }
+        if actual_mem != expected_mem {
+            return Err(Mismatch::Memory(actual_mem, expected_mem));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fuzz target entry point: panics when the interpreters disagree on the
+/// program generated from `data`.
+pub fn fuzz(data: &[u8]) {
+    let case = generate(data);
+    if let Err(mismatch) = check(&case, STEP_LIMIT) {
+        panic!("Interpreters disagree on {:?}: {:?}", case, mismatch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::Xorshift;
+
+    #[test]
+    fn generated_programs_are_well_formed() {
+        let mut rng = Xorshift::new(0x2019);
+        for _ in 0..200 {
+            let data: Vec<u8> = (0..256).map(|_| rng.next_u64() as u8).collect();
+            let case = generate(&data);
+            let mut mem = case.memory.clone();
+            let mut program = Program::new(&mut mem).add_input(&case.input);
+            if let Err(Error::InvalidOp(_, e)) = program.run_with_limit(1) {
+                panic!("{:?} starts with {:?}", case, e);
+            }
+        }
+    }
+
+    #[test]
+    fn interpreters_agree_on_generated_programs() {
+        let mut rng = Xorshift::new(0xadc0de);
+        for _ in 0..5_000 {
+            let len = (rng.next_u64() % 512) as usize;
+            let data: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+            fuzz(&data);
+        }
+    }
+
+    #[test]
+    fn empty_data_generates_a_program() {
+        let case = generate(&[]);
+        assert_eq!(
+            check(&case, STEP_LIMIT).map_err(|e| format!("{:?}", e)),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Closures that run before and after every instruction.
+//!
+//! Hooks are added with [`with_before_hook`] and [`with_after_hook`] and get
+//! the [`Program`] itself, so they can inspect and change its memory,
+//! registers and input while it runs. What a hook returns decides what
+//! happens to the instruction at the instruction pointer:
+//!
+//! * a hook run before an instruction can let it run, skip it, or replace it
+//!   with a jump or a halt; the replacement counts as a step, but isn't
+//!   covered or traced and doesn't tick devices, as nothing ran,
+//! * a hook run after an instruction is told its address and can redirect
+//!   the program from there, before the next instruction's hooks run.
+//!
+//! Hooks run in the order they were added, up to the first that doesn't
+//! return [`Action::Continue`]. Halting instructions and instructions that
+//! fail aren't followed by the hooks run after instructions.
+//!
+//! This makes room for cheats, fault injection and instrumentation that the
+//! interpreter doesn't provide itself:
+//!
+//! ```
+//! use intcode::hook::Action;
+//! use intcode::Program;
+//!
+//! // Outputs 1 if the input is 8, and 0 otherwise
+//! let mut memory = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+//! let program = Program::new(&mut memory)
+//!     .add_input(&[7])
+//!     .with_after_hook(|program, address| {
+//!         // Flip the comparison's result
+//!         if address == 2 {
+//!             program.write_memory(9, 1).unwrap();
+//!         }
+//!         Action::Continue
+//!     })
+//!     .run();
+//! assert_eq!(program.output(), &[1]);
+//! ```
+//!
+//! [`with_before_hook`]: Program::with_before_hook
+//! [`with_after_hook`]: Program::with_after_hook
+
+use crate::Program;
+
+/// What to do with the instruction at the instruction pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Run it as usual.
+    Continue,
+    /// Move past it without running it. A halt instruction is skipped over
+    /// as a single word.
+    Skip,
+    /// Go to the address instead.
+    Jump(usize),
+    /// Halt the program.
+    Halt,
+}
+
+pub(crate) type BeforeHook<'a> = Box<dyn FnMut(&mut Program<'a>) -> Action + 'a>;
+pub(crate) type AfterHook<'a> = Box<dyn FnMut(&mut Program<'a>, usize) -> Action + 'a>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::cell::RefCell;
+
+    // Outputs 1, 2 and 3, one at a time
+    const COUNT: [i64; 7] = [104, 1, 104, 2, 104, 3, 99];
+
+    #[test]
+    fn before_hooks_can_replace_instructions() {
+        let mut mem = COUNT;
+        let program = Program::new(&mut mem)
+            .with_before_hook(|p| match p.instruction_pointer() {
+                2 => Action::Skip,
+                _ => Action::Continue,
+            })
+            .run();
+        assert_eq!(program.output(), &[1, 3]);
+        assert_eq!(program.steps(), 4);
+
+        let mut mem = COUNT;
+        let mut program =
+            Program::new(&mut mem).with_before_hook(|p| match p.instruction_pointer() {
+                0 => Action::Jump(4),
+                4 => Action::Halt,
+                _ => Action::Continue,
+            });
+        assert_eq!(program.run_with_limit(10), Ok(()));
+        assert_eq!(program.output(), &[]);
+        assert!(program.is_halted());
+    }
+
+    #[test]
+    fn replaced_instructions_are_not_covered() {
+        let mut mem = COUNT;
+        let program = Program::new(&mut mem)
+            .with_coverage()
+            .with_before_hook(|p| match p.instruction_pointer() {
+                2 => Action::Skip,
+                4 => Action::Jump(6),
+                _ => Action::Continue,
+            })
+            .run();
+        assert_eq!(program.output(), &[1]);
+        assert_eq!(program.steps(), 4);
+        let coverage = program.coverage().unwrap();
+        let hits: Vec<_> = [0, 2, 4, 6].iter().map(|a| coverage.hits(*a)).collect();
+        assert_eq!(hits, [1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn after_hooks_see_each_instruction_and_can_redirect() {
+        let seen = RefCell::new(Vec::new());
+        let mut mem = COUNT;
+        let program = Program::new(&mut mem)
+            .with_after_hook(|p, address| {
+                seen.borrow_mut().push(address);
+                match p.output().last() {
+                    Some(1) => Action::Skip,
+                    _ => Action::Continue,
+                }
+            })
+            .run();
+        assert_eq!(program.output(), &[1, 3]);
+        assert_eq!(*seen.borrow(), [0, 4]);
+
+        // Loops back to the start until three outputs have been seen
+        let mut mem = COUNT;
+        let program = Program::new(&mut mem)
+            .with_after_hook(|p, _| match p.output().len() {
+                1 | 2 => Action::Jump(0),
+                _ => Action::Halt,
+            })
+            .run();
+        assert_eq!(program.output(), &[1, 1, 1]);
+    }
+
+    #[test]
+    fn hooks_run_in_order_until_one_acts() {
+        let calls = RefCell::new(Vec::new());
+        let mut mem = [3, 0, 99];
+        let mut program = Program::new(&mut mem)
+            .with_before_hook(|p| {
+                calls.borrow_mut().push("first");
+                p.push_input(5);
+                Action::Continue
+            })
+            .with_before_hook(|p| {
+                calls.borrow_mut().push("second");
+                match p.instruction_pointer() {
+                    2 => Action::Skip,
+                    _ => Action::Continue,
+                }
+            })
+            .with_before_hook(|_| {
+                calls.borrow_mut().push("third");
+                Action::Continue
+            });
+
+        // Skipping the halt runs into the end of memory
+        assert_eq!(program.run_with_limit(10), Err(Error::ReadOutOfBounds(3)));
+        assert_eq!(program.pending_input().len(), 2);
+        drop(program);
+        assert_eq!(mem[0], 5);
+        assert_eq!(
+            *calls.borrow(),
+            ["first", "second", "third", "first", "second", "first", "second", "third"]
+        );
+    }
+}
@@ -0,0 +1,479 @@
+//! Code generation from the parsed syntax tree to an Intcode image.
+//!
+//! Values live in three places: immediates, globals (position mode, at
+//! addresses fixed once the code size is known) and stack slots (relative
+//! mode). Each function owns a frame on the relative base: slot 0 holds the
+//! return address, the parameters follow and locals and temporaries sit above
+//! them. Return values travel through a single global cell.
+
+use std::collections::HashMap;
+
+use super::parser::{Ast, BinOp, Expr, Function, Stmt, StmtKind, UnOp};
+use super::{CompileError, Compiled};
+use crate::{OpCode, ParamMode};
+
+const ADD: u8 = 1;
+const MUL: u8 = 2;
+const IN: u8 = 3;
+const OUT: u8 = 4;
+const JUMP_IF_TRUE: u8 = 5;
+const JUMP_IF_FALSE: u8 = 6;
+const LESS_THAN: u8 = 7;
+const EQUALS: u8 = 8;
+const ADJUST_BASE: u8 = 9;
+const HALT: u8 = 99;
+
+type Label = usize;
+
+#[derive(Debug, Clone, Copy)]
+enum Word {
+    Value(i64),
+    Address(Label),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    /// A constant.
+    Imm(i64),
+    /// The address of a label, as a constant.
+    LabelImm(Label),
+    /// The memory cell at a label.
+    Global(Label),
+    /// A slot in the current frame.
+    Slot(i64),
+}
+
+impl Operand {
+    fn mode(self) -> ParamMode {
+        match self {
+            Operand::Imm(_) | Operand::LabelImm(_) => ParamMode::Immediate,
+            Operand::Global(_) => ParamMode::Position,
+            Operand::Slot(_) => ParamMode::Relative,
+        }
+    }
+
+    fn word(self) -> Word {
+        match self {
+            Operand::Imm(v) | Operand::Slot(v) => Word::Value(v),
+            Operand::LabelImm(l) | Operand::Global(l) => Word::Address(l),
+        }
+    }
+}
+
+struct Frame {
+    scopes: Vec<HashMap<String, i64>>,
+    /// Slots taken by declared variables; temporaries live above this.
+    vars: i64,
+    /// First unused slot.
+    top: i64,
+}
+
+struct Codegen<'a> {
+    code: Vec<Word>,
+    labels: Vec<Option<usize>>,
+    functions: HashMap<&'a str, (Label, usize)>,
+    /// Globals declared so far, with the line of their declaration.
+    globals: HashMap<String, (Label, usize)>,
+    ret: Label,
+    frame: Frame,
+    in_function: bool,
+    /// Line of the function being generated.
+    function_line: usize,
+}
+
+impl<'a> Codegen<'a> {
+    fn label(&mut self) -> Label {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: Label) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, op: u8, operands: &[Operand]) {
+        let mode = |i: usize| operands.get(i).map_or(ParamMode::Position, |o| o.mode());
+        let opcode = OpCode {
+            op,
+            arg0: mode(0),
+            arg1: mode(1),
+            arg2: mode(2),
+        };
+        self.code.push(Word::Value(opcode.encode()));
+        self.code.extend(operands.iter().map(|o| o.word()));
+    }
+
+    fn jump(&mut self, target: Label) {
+        self.emit(JUMP_IF_TRUE, &[Operand::Imm(1), Operand::LabelImm(target)]);
+    }
+
+    fn copy(&mut self, from: Operand, to: Operand) {
+        if from != to {
+            self.emit(ADD, &[from, Operand::Imm(0), to]);
+        }
+    }
+
+    fn temp(&mut self) -> Operand {
+        self.frame.top += 1;
+        Operand::Slot(self.frame.top - 1)
+    }
+
+    /// Frees `operand` if it is the most recently allocated temporary.
+    fn release(&mut self, operand: Operand) {
+        if let Operand::Slot(slot) = operand {
+            if slot >= self.frame.vars && slot == self.frame.top - 1 {
+                self.frame.top -= 1;
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str, line: usize) -> Result<Operand, CompileError> {
+        let local = self.frame.scopes.iter().rev().find_map(|s| s.get(name));
+        // Functions are generated after the main program, so they only see
+        // the globals declared above them.
+        let global = self
+            .globals
+            .get(name)
+            .filter(|(_, declared)| !self.in_function || *declared <= self.function_line);
+        match (local, global) {
+            (Some(slot), _) => Ok(Operand::Slot(*slot)),
+            (None, Some((label, _))) => Ok(Operand::Global(*label)),
+            _ => Err(CompileError::new(
+                line,
+                &format!("undefined variable '{}'", name),
+            )),
+        }
+    }
+
+    fn declare_global(&mut self, name: &str, line: usize) -> Result<Operand, CompileError> {
+        if self.globals.contains_key(name) {
+            return Err(CompileError::new(
+                line,
+                &format!("'{}' is already declared", name),
+            ));
+        }
+        let label = self.label();
+        self.globals.insert(name.to_string(), (label, line));
+        Ok(Operand::Global(label))
+    }
+
+    fn declare(&mut self, name: &str, line: usize) -> Result<Operand, CompileError> {
+        let scope = self.frame.scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+            return Err(CompileError::new(
+                line,
+                &format!("'{}' is already declared", name),
+            ));
+        }
+        let slot = self.frame.vars;
+        scope.insert(name.to_string(), slot);
+        self.frame.vars += 1;
+        self.frame.top = self.frame.vars;
+        Ok(Operand::Slot(slot))
+    }
+
+    fn block(&mut self, body: &'a [Stmt]) -> Result<(), CompileError> {
+        self.frame.scopes.push(HashMap::new());
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        self.frame.scopes.pop();
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) -> Result<(), CompileError> {
+        let line = stmt.line;
+        match &stmt.kind {
+            StmtKind::Var(name, value) => {
+                let value = match value {
+                    Some(e) => self.expr(e, line)?,
+                    None => Operand::Imm(0),
+                };
+                self.release(value);
+                let target = if self.frame.scopes.len() == 1 && !self.in_function {
+                    // Top level variables of the main program are globals.
+                    self.declare_global(name, line)?
+                } else {
+                    self.declare(name, line)?
+                };
+                self.copy(value, target);
+            }
+            StmtKind::Assign(name, value) => {
+                let target = self.lookup(name, line)?;
+                let value = self.expr(value, line)?;
+                self.copy(value, target);
+                self.release(value);
+            }
+            StmtKind::If(condition, then, otherwise) => {
+                let else_label = self.label();
+                let end = self.label();
+                let c = self.expr(condition, line)?;
+                self.release(c);
+                self.emit(JUMP_IF_FALSE, &[c, Operand::LabelImm(else_label)]);
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.jump(end);
+                }
+                self.place(else_label);
+                self.block(otherwise)?;
+                self.place(end);
+            }
+            StmtKind::While(condition, body) => {
+                let start = self.label();
+                let end = self.label();
+                self.place(start);
+                let c = self.expr(condition, line)?;
+                self.release(c);
+                self.emit(JUMP_IF_FALSE, &[c, Operand::LabelImm(end)]);
+                self.block(body)?;
+                self.jump(start);
+                self.place(end);
+            }
+            StmtKind::Print(value) => {
+                let v = self.expr(value, line)?;
+                self.release(v);
+                self.emit(OUT, &[v]);
+            }
+            StmtKind::Return(value) => {
+                if self.in_function {
+                    let v = match value {
+                        Some(e) => self.expr(e, line)?,
+                        None => Operand::Imm(0),
+                    };
+                    self.release(v);
+                    self.copy(v, Operand::Global(self.ret));
+                    self.emit(JUMP_IF_TRUE, &[Operand::Imm(1), Operand::Slot(0)]);
+                } else {
+                    if value.is_some() {
+                        return Err(CompileError::new(
+                            line,
+                            "the main program cannot return a value",
+                        ));
+                    }
+                    self.emit(HALT, &[]);
+                }
+            }
+            StmtKind::Expr(value) => {
+                let v = self.expr(value, line)?;
+                self.release(v);
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &'a Expr, line: usize) -> Result<Operand, CompileError> {
+        Ok(match expr {
+            Expr::Number(n) => Operand::Imm(*n),
+            Expr::Var(name) => self.lookup(name, line)?,
+            Expr::Read => {
+                let t = self.temp();
+                self.emit(IN, &[t]);
+                t
+            }
+            Expr::Call(name, args) => self.call(name, args, line)?,
+            Expr::Unary(op, value) => {
+                let v = self.expr(value, line)?;
+                self.release(v);
+                let t = self.temp();
+                match op {
+                    UnOp::Neg => self.emit(MUL, &[v, Operand::Imm(-1), t]),
+                    UnOp::Not => self.emit(EQUALS, &[v, Operand::Imm(0), t]),
+                }
+                t
+            }
+            Expr::Binary(BinOp::And, l, r) => self.short_circuit(l, r, false, line)?,
+            Expr::Binary(BinOp::Or, l, r) => self.short_circuit(l, r, true, line)?,
+            Expr::Binary(BinOp::Sub, l, r) => {
+                // There is no subtract instruction, so negate into a scratch
+                // slot that cannot alias either operand.
+                let a = self.expr(l, line)?;
+                let b = self.expr(r, line)?;
+                let negated = self.temp();
+                self.emit(MUL, &[b, Operand::Imm(-1), negated]);
+                self.release(negated);
+                self.release(b);
+                self.release(a);
+                let t = self.temp();
+                self.emit(ADD, &[a, negated, t]);
+                t
+            }
+            Expr::Binary(op, l, r) => {
+                let a = self.expr(l, line)?;
+                let b = self.expr(r, line)?;
+                self.release(b);
+                self.release(a);
+                let t = self.temp();
+                match op {
+                    BinOp::Add => self.emit(ADD, &[a, b, t]),
+                    BinOp::Mul => self.emit(MUL, &[a, b, t]),
+                    BinOp::Less => self.emit(LESS_THAN, &[a, b, t]),
+                    BinOp::Greater => self.emit(LESS_THAN, &[b, a, t]),
+                    BinOp::Equal => self.emit(EQUALS, &[a, b, t]),
+                    BinOp::LessEq | BinOp::GreaterEq | BinOp::NotEqual => {
+                        match op {
+                            BinOp::LessEq => self.emit(LESS_THAN, &[b, a, t]),
+                            BinOp::GreaterEq => self.emit(LESS_THAN, &[a, b, t]),
+                            _ => self.emit(EQUALS, &[a, b, t]),
+                        }
+                        self.emit(EQUALS, &[t, Operand::Imm(0), t]);
+                    }
+                    BinOp::Sub | BinOp::And | BinOp::Or => unreachable!(),
+                }
+                t
+            }
+        })
+    }
+
+    /// `l && r` and `l || r`, evaluating `r` only when `l` does not decide
+    /// the result. Both produce 0 or 1.
+    fn short_circuit(
+        &mut self,
+        l: &'a Expr,
+        r: &'a Expr,
+        is_or: bool,
+        line: usize,
+    ) -> Result<Operand, CompileError> {
+        let done = self.label();
+        let skip = if is_or { JUMP_IF_TRUE } else { JUMP_IF_FALSE };
+        let a = self.expr(l, line)?;
+        self.release(a);
+        let t = self.temp();
+        self.emit(EQUALS, &[a, Operand::Imm(0), t]);
+        self.emit(EQUALS, &[t, Operand::Imm(0), t]);
+        self.emit(skip, &[t, Operand::LabelImm(done)]);
+        let b = self.expr(r, line)?;
+        self.release(b);
+        self.emit(EQUALS, &[b, Operand::Imm(0), t]);
+        self.emit(EQUALS, &[t, Operand::Imm(0), t]);
+        self.place(done);
+        Ok(t)
+    }
+
+    fn call(&mut self, name: &str, args: &'a [Expr], line: usize) -> Result<Operand, CompileError> {
+        let (target, arity) = *self
+            .functions
+            .get(name)
+            .ok_or_else(|| CompileError::new(line, &format!("undefined function '{}'", name)))?;
+        if args.len() != arity {
+            return Err(CompileError::new(
+                line,
+                &format!(
+                    "'{}' takes {} argument(s) but {} were given",
+                    name,
+                    arity,
+                    args.len()
+                ),
+            ));
+        }
+
+        // The callee's frame starts at the current top: its return address
+        // and then its parameters.
+        let base = self.frame.top;
+        let slots: Vec<Operand> = (0..=arity).map(|_| self.temp()).collect();
+        for (arg, slot) in args.iter().zip(&slots[1..]) {
+            let v = self.expr(arg, line)?;
+            self.copy(v, *slot);
+            self.release(v);
+        }
+
+        let back = self.label();
+        self.copy(Operand::LabelImm(back), slots[0]);
+        self.emit(ADJUST_BASE, &[Operand::Imm(base)]);
+        self.jump(target);
+        self.place(back);
+        self.emit(ADJUST_BASE, &[Operand::Imm(-base)]);
+
+        for slot in slots.into_iter().rev() {
+            self.release(slot);
+        }
+        let t = self.temp();
+        self.copy(Operand::Global(self.ret), t);
+        Ok(t)
+    }
+
+    fn function(&mut self, function: &'a Function) -> Result<(), CompileError> {
+        let (label, _) = self.functions[function.name.as_str()];
+        self.place(label);
+        self.in_function = true;
+        self.function_line = function.line;
+        self.frame = Frame {
+            scopes: vec![HashMap::new()],
+            vars: 1,
+            top: 1,
+        };
+        for param in &function.params {
+            self.declare(param, function.line)?;
+        }
+        self.block(&function.body)?;
+        self.copy(Operand::Imm(0), Operand::Global(self.ret));
+        self.emit(JUMP_IF_TRUE, &[Operand::Imm(1), Operand::Slot(0)]);
+        Ok(())
+    }
+}
+
+pub fn generate(ast: &Ast, stack_size: usize) -> Result<Compiled, CompileError> {
+    let mut gen = Codegen {
+        code: Vec::new(),
+        labels: Vec::new(),
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        ret: 0,
+        frame: Frame {
+            scopes: vec![HashMap::new()],
+            vars: 0,
+            top: 0,
+        },
+        in_function: false,
+        function_line: 0,
+    };
+    gen.ret = gen.label();
+
+    for f in &ast.functions {
+        let label = gen.label();
+        if gen
+            .functions
+            .insert(&f.name, (label, f.params.len()))
+            .is_some()
+        {
+            return Err(CompileError::new(
+                f.line,
+                &format!("function '{}' is already defined", f.name),
+            ));
+        }
+    }
+    let stack = gen.label();
+    gen.emit(ADJUST_BASE, &[Operand::LabelImm(stack)]);
+    for stmt in &ast.main {
+        gen.statement(stmt)?;
+    }
+    gen.emit(HALT, &[]);
+    for f in &ast.functions {
+        gen.function(f)?;
+    }
+
+    // Data follows the code: the return value cell, globals, then the stack.
+    let code_len = gen.code.len();
+    let mut next = code_len;
+    let mut data: Vec<Label> = vec![gen.ret];
+    let mut globals: Vec<(&String, Label)> =
+        gen.globals.iter().map(|(n, (l, _))| (n, *l)).collect();
+    globals.sort();
+    data.extend(globals.into_iter().map(|(_, l)| l));
+    for label in data {
+        gen.labels[label] = Some(next);
+        next += 1;
+    }
+    gen.labels[stack] = Some(next);
+
+    let mut memory: Vec<i64> = gen
+        .code
+        .iter()
+        .map(|w| match *w {
+            Word::Value(v) => v,
+            Word::Address(l) => gen.labels[l].expect("label was never placed") as i64,
+        })
+        .collect();
+    memory.resize(next + stack_size, 0);
+
+    Ok(Compiled { memory, code_len })
+}
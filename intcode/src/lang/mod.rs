@@ -0,0 +1,212 @@
+//! Compiler for a small imperative language targeting Intcode.
+//!
+//! ```text
+//! // Prints the factorial of each input until a zero is read.
+//! fn fact(n) {
+//!     if (n < 2) { return 1; }
+//!     return n * fact(n - 1);
+//! }
+//!
+//! var x = read();
+//! while (x != 0) {
+//!     print(fact(x));
+//!     x = read();
+//! }
+//! ```
+//!
+//! Every value is a word. Statements are `var`, assignment, `if`/`else`,
+//! `while`, `print(e);`, `return` and bare expressions; expressions support
+//! `+ - *`, the comparisons, `&& || !`, function calls and `read()`. Top level
+//! variables are globals, everything declared inside a block or function lives
+//! on a stack addressed through the relative base, so functions may recurse.
+//! Variables can only be used below their declaration, and functions only see
+//! the globals declared above them.
+//! `return` at the top level halts. The output needs the day 9 profile.
+
+mod codegen;
+mod parser;
+
+use std::fmt;
+
+use crate::disasm;
+
+/// Number of words reserved for the stack when none is given.
+pub const DEFAULT_STACK_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(line: usize, message: &str) -> CompileError {
+        CompileError {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// A compiled program image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compiled {
+    /// The image: code, then data, then the zeroed stack.
+    pub memory: Vec<i64>,
+    /// Number of words of code at the start of `memory`.
+    pub code_len: usize,
+}
+
+impl Compiled {
+    /// Disassembly of the code part of the image.
+    pub fn listing(&self) -> String {
+        disasm::listing(&self.memory[..self.code_len])
+    }
+}
+
+pub fn compile(source: &str) -> Result<Compiled, CompileError> {
+    compile_with_stack(source, DEFAULT_STACK_SIZE)
+}
+
+pub fn compile_with_stack(source: &str, stack_size: usize) -> Result<Compiled, CompileError> {
+    let ast = parser::parse(source)?;
+    codegen::generate(&ast, stack_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    fn run(source: &str, input: &[i64]) -> Vec<i64> {
+        let mut memory = compile(source).unwrap().memory;
+        let program = Program::new(&mut memory).add_input(input).run();
+        program.output().to_vec()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(run("print(1 + 2 * 3 - 4);", &[]), vec![3]);
+        assert_eq!(run("print((1 + 2) * -(3 - 4));", &[]), vec![3]);
+        assert_eq!(
+            run("var a = 10; var b = a - 3 - 2; print(b);", &[]),
+            vec![5]
+        );
+    }
+
+    #[test]
+    fn comparisons_and_logic() {
+        let source = "
+            var a = read();
+            var b = read();
+            print(a < b); print(a <= b); print(a > b);
+            print(a >= b); print(a == b); print(a != b);
+            print(a && b); print(a || b); print(!a);
+        ";
+        assert_eq!(run(source, &[3, 3]), vec![0, 1, 0, 1, 1, 0, 1, 1, 0]);
+        assert_eq!(run(source, &[0, 5]), vec![1, 1, 0, 0, 0, 1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "
+            var n = read();
+            var sum = 0;
+            while (n > 0) {
+                if (n == 3) {
+                    print(-3);
+                } else if (n == 2) {
+                    var twice = n * 2;
+                    print(twice);
+                } else {
+                    sum = sum + n;
+                }
+                n = n - 1;
+            }
+            print(sum);
+        ";
+        assert_eq!(run(source, &[5]), vec![-3, 4, 10]);
+    }
+
+    #[test]
+    fn recursive_functions() {
+        let source = "
+            fn fib(n) {
+                if (n < 2) { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            fn add3(a, b, c) { return a + b + c; }
+
+            var x = read();
+            while (x != 0) {
+                print(fib(x));
+                x = read();
+            }
+            print(add3(fib(5), 2, fib(6)));
+        ";
+        assert_eq!(run(source, &[1, 10, 20, 0]), vec![1, 55, 6765, 15]);
+    }
+
+    #[test]
+    fn globals_and_early_halt() {
+        let source = "
+            var count = 0;
+            fn bump() { count = count + 1; }
+            bump(); bump(); bump();
+            print(count);
+            return;
+            print(99);
+        ";
+        assert_eq!(run(source, &[]), vec![3]);
+    }
+
+    #[test]
+    fn compile_errors() {
+        let error = |source| compile(source).unwrap_err().to_string();
+
+        assert_eq!(error("print(x);"), "line 1: undefined variable 'x'");
+        assert_eq!(error("x = 1;\nvar x;"), "line 1: undefined variable 'x'");
+        assert_eq!(error("var x = x;"), "line 1: undefined variable 'x'");
+        assert_eq!(
+            error("fn f() { return x; }\nvar x = 1;\nprint(f());"),
+            "line 1: undefined variable 'x'"
+        );
+        assert_eq!(error("\n\nf();"), "line 3: undefined function 'f'");
+        assert_eq!(
+            error("fn f(a) {}\nf(1, 2);"),
+            "line 2: 'f' takes 1 argument(s) but 2 were given"
+        );
+        assert_eq!(error("var a = 1\nprint(a);"), "line 2: expected ';'");
+        assert_eq!(error("print(1 / 2);"), "line 1: unexpected character '/'");
+        assert_eq!(
+            error("if (1) { var a; var a; }"),
+            "line 1: 'a' is already declared"
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let compiled = compile_with_stack("print(read() + 1);", 4).unwrap();
+        assert_eq!(compiled.memory.len(), compiled.code_len + 1 + 4);
+        assert_eq!(
+            compiled.listing(),
+            [
+                "     0  109,12                      ARB 12",
+                "     2  203,0                       IN [rb]",
+                "     4  21201,0,1,0                 ADD [rb], 1, [rb]",
+                "     8  204,0                       OUT [rb]",
+                "    10  99                          HALT",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! Tokenizer and recursive descent parser for the language.
+
+use super::CompileError;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "<", ">", "+", "-", "*",
+    "!",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, CompileError> {
+    let mut tokens = Vec::new();
+
+    for (n, line) in source.lines().enumerate() {
+        let line_no = n + 1;
+        let line = line.split("//").next().unwrap_or("");
+        let mut rest = line.trim_start();
+
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let value = rest[..len]
+                    .parse()
+                    .map_err(|_| CompileError::new(line_no, "number out of range"))?;
+                tokens.push((Token::Number(value), line_no));
+                len
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Ident(rest[..len].to_string()), line_no));
+                len
+            } else {
+                let p = PUNCTUATION
+                    .iter()
+                    .find(|p| rest.starts_with(*p))
+                    .ok_or_else(|| {
+                        CompileError::new(line_no, &format!("unexpected character '{}'", c))
+                    })?;
+                tokens.push((Token::Punct(p), line_no));
+                p.len()
+            };
+            rest = rest[len..].trim_start();
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Var(String),
+    Read,
+    Call(String, Vec<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Var(String, Option<Expr>),
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Print(Expr),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub line: usize,
+    pub kind: StmtKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub line: usize,
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+/// A parsed source file: function definitions and the top level statements,
+/// which make up the main program.
+#[derive(Debug, Default)]
+pub struct Ast {
+    pub functions: Vec<Function>,
+    pub main: Vec<Stmt>,
+}
+
+const KEYWORDS: [&str; 8] = [
+    "fn", "var", "if", "else", "while", "return", "print", "read",
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, CompileError> {
+        Err(CompileError::new(self.line(), message))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn at_punct(&self, p: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(q)) if *q == p)
+    }
+
+    fn at_keyword(&self, k: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == k)
+    }
+
+    fn eat_punct(&mut self, p: &str) -> bool {
+        let found = self.at_punct(p);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, k: &str) -> bool {
+        let found = self.at_keyword(k);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, p: &str) -> Result<(), CompileError> {
+        if self.eat_punct(p) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", p))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.peek() {
+            Some(Token::Ident(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => self.error("expected a name"),
+        }
+    }
+
+    fn program(&mut self) -> Result<Ast, CompileError> {
+        let mut ast = Ast::default();
+        while self.peek().is_some() {
+            if self.at_keyword("fn") {
+                ast.functions.push(self.function()?);
+            } else {
+                ast.main.push(self.statement()?);
+            }
+        }
+        Ok(ast)
+    }
+
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let line = self.line();
+        self.eat_keyword("fn");
+        let name = self.ident()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if !self.eat_punct(")") {
+            loop {
+                params.push(self.ident()?);
+                if self.eat_punct(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let body = self.block()?;
+
+        Ok(Function {
+            line,
+            name,
+            params,
+            body,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut body = Vec::new();
+        while !self.eat_punct("}") {
+            if self.peek().is_none() {
+                return self.error("expected '}'");
+            }
+            body.push(self.statement()?);
+        }
+        Ok(body)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        let kind = if self.eat_keyword("var") {
+            let name = self.ident()?;
+            let value = if self.eat_punct("=") {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            self.expect(";")?;
+            StmtKind::Var(name, value)
+        } else if self.eat_keyword("if") {
+            self.if_statement()?
+        } else if self.eat_keyword("while") {
+            self.expect("(")?;
+            let condition = self.expr()?;
+            self.expect(")")?;
+            StmtKind::While(condition, self.block()?)
+        } else if self.eat_keyword("print") {
+            self.expect("(")?;
+            let value = self.expr()?;
+            self.expect(")")?;
+            self.expect(";")?;
+            StmtKind::Print(value)
+        } else if self.eat_keyword("return") {
+            let value = if self.at_punct(";") {
+                None
+            } else {
+                Some(self.expr()?)
+            };
+            self.expect(";")?;
+            StmtKind::Return(value)
+        } else if self.at_keyword("fn") {
+            return self.error("functions can only be defined at the top level");
+        } else {
+            let is_assignment =
+                matches!(self.tokens.get(self.pos + 1), Some((Token::Punct("="), _)));
+            if is_assignment {
+                let name = self.ident()?;
+                self.expect("=")?;
+                let value = self.expr()?;
+                self.expect(";")?;
+                StmtKind::Assign(name, value)
+            } else {
+                let value = self.expr()?;
+                self.expect(";")?;
+                StmtKind::Expr(value)
+            }
+        };
+
+        Ok(Stmt { line, kind })
+    }
+
+    fn if_statement(&mut self) -> Result<StmtKind, CompileError> {
+        self.expect("(")?;
+        let condition = self.expr()?;
+        self.expect(")")?;
+        let then = self.block()?;
+        let otherwise = if !self.eat_keyword("else") {
+            Vec::new()
+        } else if self.at_keyword("if") {
+            let line = self.line();
+            self.eat_keyword("if");
+            vec![Stmt {
+                line,
+                kind: self.if_statement()?,
+            }]
+        } else {
+            self.block()?
+        };
+        Ok(StmtKind::If(condition, then, otherwise))
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    /// Parses binary operators of precedence `level` and above.
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        const LEVELS: [&[(&str, BinOp)]; 4] = [
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[
+                ("==", BinOp::Equal),
+                ("!=", BinOp::NotEqual),
+                ("<=", BinOp::LessEq),
+                (">=", BinOp::GreaterEq),
+                ("<", BinOp::Less),
+                (">", BinOp::Greater),
+            ],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.term();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some((_, op)) = LEVELS[level].iter().find(|(p, _)| self.at_punct(p)) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(*op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.unary()?;
+        while self.eat_punct("*") {
+            let right = self.unary()?;
+            left = Expr::Binary(BinOp::Mul, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.eat_punct("-") {
+            Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)))
+        } else if self.eat_punct("!") {
+            Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        if let Some(Token::Number(n)) = self.peek() {
+            let n = *n;
+            self.pos += 1;
+            return Ok(Expr::Number(n));
+        }
+        if self.eat_punct("(") {
+            let e = self.expr()?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        if self.eat_keyword("read") {
+            self.expect("(")?;
+            self.expect(")")?;
+            return Ok(Expr::Read);
+        }
+
+        let name = self
+            .ident()
+            .or_else(|_| self.error("expected an expression"))?;
+        if !self.eat_punct("(") {
+            return Ok(Expr::Var(name));
+        }
+        let mut args = Vec::new();
+        if !self.eat_punct(")") {
+            loop {
+                args.push(self.expr()?);
+                if self.eat_punct(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        Ok(Expr::Call(name, args))
+    }
+}
+
+pub fn parse(source: &str) -> Result<Ast, CompileError> {
+    let tokens = tokenize(source)?;
+    Parser { tokens, pos: 0 }.program()
+}
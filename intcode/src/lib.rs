@@ -1,203 +1,921 @@
 use std::borrow::Borrow;
 use std::collections::VecDeque;
 use std::convert::TryFrom;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub mod batch;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod decompile;
+pub mod devices;
+pub mod disasm;
+pub mod dump;
+pub mod equiv;
+pub mod fuzz;
+pub mod hook;
+pub mod lang;
+pub mod manifest;
+pub mod memo;
+pub mod optimize;
+pub mod profile;
+pub mod reference;
+pub mod replay;
+pub mod storage;
+pub mod taint;
+pub mod trace;
+pub mod transpile;
+pub mod tui;
+pub mod verify;
+mod xorshift;
+
+#[cfg(test)]
+mod conformance;
+#[cfg(test)]
+mod test_programs;
+
+use coverage::Coverage;
+use devices::Device;
+use hook::{Action, AfterHook, BeforeHook};
+pub use profile::Profile;
+use replay::{Event, Recording};
+use storage::Storage;
+use taint::Taint;
+use trace::Trace;
+
+/// The decode cache covers at most this many addresses, so that a program
+/// running far out into sparse storage doesn't grow it without bound.
+const MAX_CACHED: usize = 1 << 20;
+
+/// Runs with a deadline look at the clock once every this many steps.
+const CLOCK_INTERVAL: usize = 1024;
 
 pub struct Program<'a> {
-    memory: &'a mut [i32],
+    memory: Box<dyn Storage + 'a>,
+    devices: Vec<(usize, Box<dyn Device + 'a>)>,
+    profile: Profile,
+    cache: Vec<Option<Decoded>>,
+    cache_enabled: bool,
+    /// Whether decoded instructions go into the cache, which grows to the
+    /// highest address decoded.
+    caching: bool,
+    coverage: Option<Coverage>,
+    recording: Option<Recording>,
+    taint: Option<Taint>,
+    trace: Option<Trace>,
+    abort: Option<Arc<AtomicBool>>,
+    before_hooks: Vec<BeforeHook<'a>>,
+    after_hooks: Vec<AfterHook<'a>>,
+    /// Instructions completed so far.
+    steps: u64,
     next_op: usize,
-    input: VecDeque<i32>,
-    output: Vec<i32>,
+    relative_base: i64,
+    input: VecDeque<i64>,
+    output: Vec<i64>,
+    halted: bool,
 }
 
 impl<'a> Program<'a> {
-    pub fn new<'b>(memory: &'b mut [i32]) -> Program<'b> {
+    pub fn new<'b>(memory: &'b mut [i64]) -> Program<'b> {
+        Program::with_storage(memory)
+    }
+
+    /// Runs on `storage` instead of a fixed slice, see [`storage`].
+    pub fn with_storage<S: Storage + 'a>(storage: S) -> Program<'a> {
         Program {
-            memory,
+            memory: Box::new(storage),
+            devices: Vec::new(),
+            profile: Profile::default(),
+            cache: Vec::new(),
+            cache_enabled: true,
+            caching: false,
+            coverage: None,
+            recording: None,
+            taint: None,
+            trace: None,
+            abort: None,
+            before_hooks: Vec::new(),
+            after_hooks: Vec::new(),
+            steps: 0,
             next_op: 0,
+            relative_base: 0,
             input: VecDeque::new(),
             output: Vec::new(),
+            halted: false,
         }
     }
 
-    pub fn add_input_value(mut self, input: i32) -> Self {
+    /// Restricts the program to the instructions and parameter modes of
+    /// `profile`; anything else fails with an [`Error::InvalidOp`].
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self.cache.clear();
+        self
+    }
+
+    /// Turns the decoded instruction cache on or off; it is on by default.
+    ///
+    /// With the cache on, every instruction address is decoded once and the
+    /// result is reused until memory under the instruction is written.
+    pub fn with_decode_cache(mut self, enabled: bool) -> Self {
+        self.cache_enabled = enabled;
+        self.caching = false;
+        self.cache.clear();
+        self
+    }
+
+    /// Collects [`Coverage`] of the instructions that run from now on.
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(Coverage::new());
+        self
+    }
+
+    /// Records the input and output of the run from now on, see
+    /// [`replay`].
+    pub fn with_recording(mut self) -> Self {
+        self.recording = Some(Recording::new());
+        self
+    }
+
+    /// Tracks which inputs affect each output and memory cell, see
+    /// [`taint`]. With `control_dependencies` set, inputs that only decide
+    /// which branches are taken count as well.
+    pub fn with_taint(mut self, control_dependencies: bool) -> Self {
+        self.taint = Some(Taint::new(control_dependencies));
+        self
+    }
+
+    /// Counts memory accesses and keeps the address of every instruction
+    /// run, for the images in [`trace`].
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Trace::new());
+        self
+    }
+
+    /// Calls `hook` before every instruction, see [`hook`].
+    pub fn with_before_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Program<'a>) -> Action + 'a,
+    {
+        self.before_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Calls `hook` with the address of every instruction run, once it has
+    /// run, see [`hook`].
+    pub fn with_after_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&mut Program<'a>, usize) -> Action + 'a,
+    {
+        self.after_hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn add_input_value(mut self, input: i64) -> Self {
         self.input.push_back(input);
         self
     }
 
-    pub fn add_input(mut self, input: &[i32]) -> Self {
+    pub fn add_input(mut self, input: &[i64]) -> Self {
         input.iter().for_each(|v| self.input.push_back(*v));
         self
     }
 
-    pub fn output<'b>(&'b self) -> &'b [i32] {
+    /// Maps `device` at `address`, so that the `device.size()` addresses
+    /// starting there are read and written through the device instead of
+    /// memory. Devices may be mapped past the end of memory.
+    ///
+    /// Panics if the device overlaps one that is already attached, or runs
+    /// past the highest address.
+    pub fn attach<D: Device + 'a>(mut self, address: usize, device: D) -> Self {
+        let end = match address.checked_add(device.size()) {
+            Some(end) => end,
+            None => panic!(
+                "Device at {} of size {} overlaps the end of the address space",
+                address,
+                device.size()
+            ),
+        };
+        if let Some((start, _)) = self
+            .devices
+            .iter()
+            .find(|(start, d)| address < start + d.size() && *start < end)
+        {
+            panic!(
+                "Device at {}..{} overlaps device attached at {}",
+                address, end, start
+            );
+        }
+
+        self.devices.push((address, Box::new(device)));
+        self.cache.clear();
+        self
+    }
+
+    pub fn output(&self) -> &[i64] {
         &self.output
     }
 
-    fn read_at(&self, pos: usize) -> i32 {
-        *self.memory.get(pos).expect(&format!("read_at({})", pos))
+    /// Address of the next instruction to run.
+    pub fn instruction_pointer(&self) -> usize {
+        self.next_op
+    }
+
+    pub fn set_instruction_pointer(&mut self, address: usize) {
+        self.next_op = address;
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: i64) {
+        self.relative_base = relative_base;
+    }
+
+    /// Writes `value` at `address` as an instruction would, so attached
+    /// devices see the write. Fails with [`Error::WriteOutOfBounds`] if the
+    /// address is outside memory and every device.
+    pub fn write_memory(&mut self, address: usize, value: i64) -> Result<(), Error> {
+        if !self.is_addressable(address) {
+            return Err(Error::WriteOutOfBounds(address));
+        }
+        self.write_at(address, value);
+        Ok(())
+    }
+
+    /// Queues `value` after any input not yet read.
+    pub fn push_input(&mut self, value: i64) {
+        self.input.push_back(value);
+    }
+
+    /// Number of instructions completed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Input queued but not yet read, in the order it will be read.
+    pub fn pending_input(&self) -> &VecDeque<i64> {
+        &self.input
+    }
+
+    /// Whether the program has run a halt instruction.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The memory the program runs on. Attached devices are not included.
+    pub fn memory(&self) -> &dyn Storage {
+        &*self.memory
+    }
+
+    /// A snapshot of the registers and queues; memory is left out, see
+    /// [`memory`](#method.memory).
+    pub fn state(&self) -> State {
+        State {
+            instruction_pointer: self.next_op,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            input: self.input.iter().copied().collect(),
+            output: self.output.clone(),
+            halted: self.halted,
+        }
+    }
+
+    /// Decodes the instruction at the instruction pointer without running
+    /// it.
+    ///
+    /// This is not a plain query: its words are read as running it would
+    /// read them, so any device mapped over them sees the reads and may
+    /// change, and the decoded instruction goes into the decode cache.
+    pub fn decode_current_op(&mut self) -> Result<Op, Error> {
+        let decoded = self.decode()?;
+        self.parse_op(&decoded)
+    }
+
+    /// The coverage collected so far, if enabled with
+    /// [`with_coverage`](#method.with_coverage).
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// The events recorded so far, if enabled with
+    /// [`with_recording`](#method.with_recording).
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
     }
 
-    fn parse_op(&self) -> Result<Op, OpError> {
+    /// The taint collected so far, if enabled with
+    /// [`with_taint`](#method.with_taint).
+    pub fn taint(&self) -> Option<&Taint> {
+        self.taint.as_ref()
+    }
+
+    /// The trace collected so far, if enabled with
+    /// [`with_trace`](#method.with_trace).
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    fn device_at(&mut self, pos: usize) -> Option<(&mut Box<dyn Device + 'a>, usize)> {
+        self.devices
+            .iter_mut()
+            .find(|(start, d)| pos >= *start && pos < start + d.size())
+            .map(|(start, d)| {
+                let offset = pos - *start;
+                (d, offset)
+            })
+    }
+
+    fn is_addressable(&self, pos: usize) -> bool {
+        self.memory.contains(pos)
+            || self
+                .devices
+                .iter()
+                .any(|(start, d)| pos >= *start && pos < start + d.size())
+    }
+
+    fn read_at(&mut self, pos: usize) -> Result<i64, Error> {
+        if let Some((device, offset)) = self.device_at(pos) {
+            return Ok(device.read(offset));
+        }
+
+        self.memory.read(pos).ok_or(Error::ReadOutOfBounds(pos))
+    }
+
+    fn write_at(&mut self, pos: usize, value: i64) {
+        if let Some(trace) = &mut self.trace {
+            trace.write(pos);
+        }
+        match self.device_at(pos) {
+            Some((device, offset)) => device.write(offset, value),
+            None => {
+                self.memory.write(pos, value);
+                // Instructions are at most 4 words long
+                let cached = pos.saturating_sub(3)..(pos + 1).min(self.cache.len());
+                if let Some(cache) = self.cache.get_mut(cached) {
+                    cache.iter_mut().for_each(|c| *c = None);
+                }
+            }
+        }
+    }
+
+    /// Decodes the opcode and parameter words at the next instruction, from
+    /// the cache when possible.
+    fn decode(&mut self) -> Result<Decoded, Error> {
+        use OpError::*;
+
+        let address = self.next_op;
+        if let Some(decoded) = self.cache.get(address).copied().flatten() {
+            return Ok(decoded);
+        }
+
+        let invalid = move |e| Error::InvalidOp(address, e);
+
+        let opcode_val = self.read_at(address)?;
+        let opcode = OpCode::try_from(opcode_val)
+            .map_err(InvalidOpCode)
+            .map_err(invalid)?;
+        let count = opcode
+            .param_count()
+            .ok_or_else(|| invalid(UnrecognisedOpCode(opcode.op)))?;
+
+        if !self.profile.allows_op(opcode.op) {
+            return Err(invalid(OpCodeNotInProfile(opcode.op, self.profile)));
+        }
+        if let Some((n, mode)) = opcode
+            .modes()
+            .iter()
+            .take(count)
+            .enumerate()
+            .find(|(_, mode)| !self.profile.allows_mode(mode))
+        {
+            return Err(invalid(ParamModeNotInProfile(
+                n + 1,
+                mode.as_int(),
+                self.profile,
+            )));
+        }
+
+        // A missing parameter word is only reported once the parameters
+        // before it have been checked, see `parse_param`.
+        let mut params = [0; 3];
+        let mut available = 0;
+        while available < count {
+            match self.read_at(address + 1 + available) {
+                Ok(value) => params[available] = value,
+                Err(_) => break,
+            }
+            available += 1;
+        }
+
+        let decoded = Decoded {
+            opcode,
+            params,
+            available,
+        };
+
+        let end = address + count;
+        let cacheable = self.caching
+            && available == count
+            && end < MAX_CACHED
+            && !self
+                .devices
+                .iter()
+                .any(|(start, d)| *start <= end && address < start + d.size());
+        if cacheable {
+            if address >= self.cache.len() {
+                self.cache.resize(address + 1, None);
+            }
+            self.cache[address] = Some(decoded);
+        }
+
+        Ok(decoded)
+    }
+
+    fn parse_op(&mut self, decoded: &Decoded) -> Result<Op, Error> {
         use OpError::*;
 
-        let opcode_val = self.read_at(self.next_op);
-        let opcode = OpCode::try_from(opcode_val).map_err(InvalidOpCode)?;
+        let address = self.next_op;
+        let invalid = move |e| Error::InvalidOp(address, e);
+
+        let opcode = &decoded.opcode;
 
         match opcode.op {
             99 => Ok(Op::Terminate),
             1 => {
-                let left = self.parse_param(1, opcode.arg0)?;
-                let right = self.parse_param(2, opcode.arg1)?;
-                match self.parse_param(3, opcode.arg2)? {
-                    Param::Immediate(_) => Err(ImmediateTargetParam(3)),
+                let left = self.parse_param(decoded, 1)?;
+                let right = self.parse_param(decoded, 2)?;
+                match self.parse_param(decoded, 3)? {
+                    Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
                     Param::Position(target) => Ok(Op::Add((left, right, target))),
                 }
             }
             2 => {
-                let left = self.parse_param(1, opcode.arg0)?;
-                let right = self.parse_param(2, opcode.arg1)?;
-                match self.parse_param(3, opcode.arg2)? {
-                    Param::Immediate(_) => Err(ImmediateTargetParam(3)),
+                let left = self.parse_param(decoded, 1)?;
+                let right = self.parse_param(decoded, 2)?;
+                match self.parse_param(decoded, 3)? {
+                    Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
                     Param::Position(target) => Ok(Op::Mul((left, right, target))),
                 }
             }
-            3 => match self.parse_param(1, opcode.arg0)? {
-                Param::Immediate(_) => Err(ImmediateTargetParam(1)),
+            3 => match self.parse_param(decoded, 1)? {
+                Param::Immediate(_) => Err(invalid(ImmediateTargetParam(1))),
                 Param::Position(target) => Ok(Op::Input(target)),
             },
             4 => {
-                let value = self.parse_param(1, opcode.arg0)?;
+                let value = self.parse_param(decoded, 1)?;
                 Ok(Op::Output(value))
             }
             5 => {
-                let condition = self.parse_param(1, opcode.arg0)?;
-                let value = self.parse_param(2, opcode.arg1)?;
+                let condition = self.parse_param(decoded, 1)?;
+                let value = self.parse_param(decoded, 2)?;
                 Ok(Op::JumpIfTrue((condition, value)))
             }
             6 => {
-                let condition = self.parse_param(1, opcode.arg0)?;
-                let value = self.parse_param(2, opcode.arg1)?;
+                let condition = self.parse_param(decoded, 1)?;
+                let value = self.parse_param(decoded, 2)?;
                 Ok(Op::JumpIfFalse((condition, value)))
             }
             7 => {
-                let left = self.parse_param(1, opcode.arg0)?;
-                let right = self.parse_param(2, opcode.arg1)?;
-                match self.parse_param(3, opcode.arg2)? {
-                    Param::Immediate(_) => Err(ImmediateTargetParam(3)),
+                let left = self.parse_param(decoded, 1)?;
+                let right = self.parse_param(decoded, 2)?;
+                match self.parse_param(decoded, 3)? {
+                    Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
                     Param::Position(target) => Ok(Op::LessThan((left, right, target))),
                 }
             }
             8 => {
-                let left = self.parse_param(1, opcode.arg0)?;
-                let right = self.parse_param(2, opcode.arg1)?;
-                match self.parse_param(3, opcode.arg2)? {
-                    Param::Immediate(_) => Err(ImmediateTargetParam(3)),
+                let left = self.parse_param(decoded, 1)?;
+                let right = self.parse_param(decoded, 2)?;
+                match self.parse_param(decoded, 3)? {
+                    Param::Immediate(_) => Err(invalid(ImmediateTargetParam(3))),
                     Param::Position(target) => Ok(Op::Equals((left, right, target))),
                 }
             }
-            _ => Err(UnrecognisedOpCode(opcode.op)),
+            9 => {
+                let value = self.parse_param(decoded, 1)?;
+                Ok(Op::AdjustRelativeBase(value))
+            }
+            _ => Err(invalid(UnrecognisedOpCode(opcode.op))),
         }
     }
 
-    fn parse_param(&self, offset: usize, mode: ParamMode) -> Result<Param, OpError> {
-        let address = self.next_op + offset;
-        let value = self.read_at(address);
+    fn parse_param(&self, decoded: &Decoded, offset: usize) -> Result<Param, Error> {
+        if offset > decoded.available {
+            return Err(Error::ReadOutOfBounds(self.next_op + offset));
+        }
+        let value = decoded.params[offset - 1];
+
+        let position = match decoded.opcode.modes()[offset - 1] {
+            ParamMode::Immediate => return Ok(Param::Immediate(value)),
+            ParamMode::Position => value,
+            ParamMode::Relative => self.relative_base.wrapping_add(value),
+        };
 
-        match mode {
-            ParamMode::Immediate => Ok(Param::Immediate(value)),
-            ParamMode::Position => {
-                if value < 0 || value >= self.memory.len() as i32 {
-                    Err(OpError::PositionParamOutOfBounds(offset, value))
-                } else {
-                    Ok(Param::Position(value as usize))
+        if position < 0 || !self.is_addressable(position as usize) {
+            Err(Error::InvalidOp(
+                self.next_op,
+                OpError::PositionParamOutOfBounds(offset, position),
+            ))
+        } else {
+            Ok(Param::Position(position as usize))
+        }
+    }
+
+    fn read_param(&mut self, param: &Param) -> Result<i64, Error> {
+        match param {
+            Param::Immediate(value) => Ok(*value),
+            Param::Position(pos) => {
+                if let Some(trace) = &mut self.trace {
+                    trace.read(*pos);
                 }
+                self.read_at(*pos)
             }
         }
     }
 
-    fn read_param(&self, param: &Param) -> i32 {
-        match param {
-            Param::Immediate(value) => *value,
-            Param::Position(pos) => self.read_at(*pos),
+    fn jump_target(&mut self, param: &Param) -> Result<usize, Error> {
+        let target = self.read_param(param)?;
+        if target < 0 {
+            Err(Error::NegativeJumpTarget(self.next_op, target))
+        } else {
+            Ok(target as usize)
         }
     }
 
-    fn step(&mut self) -> Instruction {
-        let op = self
-            .parse_op()
-            .unwrap_or_else(|e| panic!("Error while running op at {}: {:?}", self.next_op, e));
+    fn record_branch(&mut self, jumped: bool) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_branch(self.next_op, jumped);
+        }
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(recording) = &mut self.recording {
+            recording.push(event);
+        }
+    }
 
-        match &op {
+    fn step(&mut self) -> Result<Instruction, Error> {
+        let decoded = self.decode()?;
+        let op = self.parse_op(&decoded)?;
+        let flow = self
+            .taint
+            .as_ref()
+            .map(|taint| taint.flow(self.next_op, &decoded.opcode, &op));
+
+        let instruction = match &op {
             Op::Add((p0, p1, dest)) => {
-                let left = self.read_param(p0);
-                let right = self.read_param(p1);
-                self.memory[*dest] = left + right;
+                let left = self.read_param(p0)?;
+                let right = self.read_param(p1)?;
+                self.write_at(*dest, left.wrapping_add(right));
                 Instruction::Increase(op.size().unwrap())
             }
             Op::Mul((p0, p1, dest)) => {
-                let left = self.read_param(p0);
-                let right = self.read_param(p1);
-                self.memory[*dest] = left * right;
+                let left = self.read_param(p0)?;
+                let right = self.read_param(p1)?;
+                self.write_at(*dest, left.wrapping_mul(right));
                 Instruction::Increase(op.size().unwrap())
             }
             Op::Input(dest) => match self.input.pop_front() {
                 Some(input) => {
-                    self.memory[*dest] = input;
+                    self.write_at(*dest, input);
+                    self.record(Event::Input {
+                        step: self.steps,
+                        value: input,
+                    });
                     Instruction::Increase(op.size().unwrap())
                 }
-                _ => panic!("Expected input, but it's empty!"),
+                _ => return Err(Error::MissingInput(self.next_op)),
             },
             Op::Output(src) => {
-                let value = self.read_param(src);
+                let value = self.read_param(src)?;
                 self.output.push(value);
+                self.record(Event::Output {
+                    step: self.steps,
+                    value,
+                });
                 Instruction::Increase(op.size().unwrap())
             }
             Op::JumpIfTrue((p0, target)) => {
-                if self.read_param(p0) > 0 {
-                    Instruction::GoTo(self.read_param(target) as usize)
+                let jump = self.read_param(p0)? != 0;
+                let instruction = if jump {
+                    Instruction::GoTo(self.jump_target(target)?)
                 } else {
                     Instruction::Increase(op.size().unwrap())
-                }
+                };
+                self.record_branch(jump);
+                instruction
             }
             Op::JumpIfFalse((p0, target)) => {
-                if self.read_param(p0) == 0 {
-                    Instruction::GoTo(self.read_param(target) as usize)
+                let jump = self.read_param(p0)? == 0;
+                let instruction = if jump {
+                    Instruction::GoTo(self.jump_target(target)?)
                 } else {
                     Instruction::Increase(op.size().unwrap())
-                }
+                };
+                self.record_branch(jump);
+                instruction
             }
             Op::LessThan((p0, p1, target)) => {
-                let left = self.read_param(p0);
-                let right = self.read_param(p1);
+                let left = self.read_param(p0)?;
+                let right = self.read_param(p1)?;
 
-                self.memory[*target] = if left < right { 1 } else { 0 };
+                self.write_at(*target, if left < right { 1 } else { 0 });
 
                 Instruction::Increase(op.size().unwrap())
             }
             Op::Equals((p0, p1, target)) => {
-                let left = self.read_param(p0);
-                let right = self.read_param(p1);
+                let left = self.read_param(p0)?;
+                let right = self.read_param(p1)?;
 
-                self.memory[*target] = if left == right { 1 } else { 0 };
+                self.write_at(*target, if left == right { 1 } else { 0 });
 
                 Instruction::Increase(op.size().unwrap())
             }
-            Op::Terminate => Instruction::Stop,
+            Op::AdjustRelativeBase(p0) => {
+                let value = self.read_param(p0)?;
+                self.relative_base = self.relative_base.wrapping_add(value);
+                Instruction::Increase(op.size().unwrap())
+            }
+            Op::Terminate => {
+                self.record(Event::Halt { step: self.steps });
+                Instruction::Stop
+            }
+        };
+
+        if let (Some(taint), Some(flow)) = (&mut self.taint, flow) {
+            taint.apply(flow, self.output.last().copied());
         }
+        Ok(instruction)
     }
 
-    pub fn run(mut self) -> Program<'a> {
-        'main: loop {
-            match self.step() {
+    /// Size of the instruction at the instruction pointer, counting a halt
+    /// as one word.
+    fn current_size(&mut self) -> Result<usize, Error> {
+        Ok(self.decode_current_op()?.size().unwrap_or(1))
+    }
+
+    /// Runs the hooks before an instruction up to the first that acts. They
+    /// are taken out of the program while they run, as they get all of it.
+    fn run_before_hooks(&mut self) -> Action {
+        if self.before_hooks.is_empty() {
+            return Action::Continue;
+        }
+        let mut hooks = std::mem::take(&mut self.before_hooks);
+        let action = hooks
+            .iter_mut()
+            .map(|hook| hook(self))
+            .find(|action| *action != Action::Continue);
+        self.before_hooks = hooks;
+        action.unwrap_or(Action::Continue)
+    }
+
+    fn run_after_hooks(&mut self, address: usize) -> Action {
+        if self.after_hooks.is_empty() {
+            return Action::Continue;
+        }
+        let mut hooks = std::mem::take(&mut self.after_hooks);
+        let action = hooks
+            .iter_mut()
+            .map(|hook| hook(self, address))
+            .find(|action| *action != Action::Continue);
+        self.after_hooks = hooks;
+        action.unwrap_or(Action::Continue)
+    }
+
+    /// Runs until the program halts, fails or executes `step_limit`
+    /// instructions, or with `until_output` set, until it outputs a value.
+    fn execute(&mut self, step_limit: Option<usize>, until_output: bool) -> Result<(), Error> {
+        self.execute_until(step_limit, None, until_output)
+    }
+
+    /// Whether [`AbortHandle::abort`] was called since the last check,
+    /// clearing the request so that it stops a single run.
+    fn take_abort(&self) -> bool {
+        self.abort
+            .as_ref()
+            .is_some_and(|abort| abort.swap(false, Ordering::Relaxed))
+    }
+
+    /// Like `execute`, but also stops once `deadline` has passed.
+    fn execute_until(
+        &mut self,
+        step_limit: Option<usize>,
+        deadline: Option<Instant>,
+        until_output: bool,
+    ) -> Result<(), Error> {
+        let outputs = self.output.len();
+        let mut steps = 0;
+        loop {
+            if step_limit.is_some_and(|limit| steps >= limit) {
+                return Err(Error::StepLimitReached(steps));
+            }
+            if self.take_abort() {
+                return Err(Error::Aborted(steps));
+            }
+            if let Some(deadline) = deadline {
+                if steps % CLOCK_INTERVAL == 0 && Instant::now() >= deadline {
+                    return Err(Error::TimedOut(steps));
+                }
+            }
+            steps += 1;
+
+            let address = self.next_op;
+            let action = self.run_before_hooks();
+            let instruction = match action {
+                Action::Continue => self.step()?,
+                Action::Skip => Instruction::Increase(self.current_size()?),
+                Action::Jump(target) => Instruction::GoTo(target),
+                Action::Halt => Instruction::Stop,
+            };
+            self.steps += 1;
+            // Only instructions that ran are covered, traced and ticked
+            if action == Action::Continue {
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record(self.next_op);
+                }
+                if let Some(trace) = &mut self.trace {
+                    trace.execute(self.next_op);
+                }
+                self.devices.iter_mut().for_each(|(_, d)| d.tick());
+            }
+
+            match instruction {
                 Instruction::Increase(val) => self.next_op += val,
-                Instruction::GoTo(instr) => self.next_op = instr,
-                Instruction::Stop => break,
+                Instruction::GoTo(instr) => {
+                    // Code that never jumps runs every instruction once, so
+                    // the cache only pays off from the first jump on.
+                    self.caching |= self.cache_enabled;
+                    self.next_op = instr
+                }
+                Instruction::Stop => {
+                    self.halted = true;
+                    return Ok(());
+                }
+            }
+
+            match self.run_after_hooks(address) {
+                Action::Continue => {}
+                Action::Skip => self.next_op += self.current_size()?,
+                Action::Jump(target) => self.next_op = target,
+                Action::Halt => {
+                    self.halted = true;
+                    return Ok(());
+                }
+            }
+
+            if until_output && self.output.len() > outputs {
+                return Ok(());
             }
         }
+    }
+
+    pub fn run(mut self) -> Program<'a> {
+        if let Err(e) = self.execute(None, false) {
+            panic!("Error while running program: {}", e);
+        }
 
         self
     }
+
+    /// Runs the program until it halts or executes `step_limit` instructions.
+    ///
+    /// Unlike [`run`](#method.run) this does not panic: a failing instruction
+    /// is reported as an [`Error`] and the program is left at the instruction
+    /// that failed, so missing input can be supplied and the run resumed.
+    pub fn run_with_limit(&mut self, step_limit: usize) -> Result<(), Error> {
+        self.execute(Some(step_limit), false)
+    }
+
+    /// Runs the program until it halts or `timeout` has passed, after which
+    /// it fails with [`Error::TimedOut`] between two instructions. Like
+    /// [`run_with_limit`](#method.run_with_limit) the program can be resumed.
+    ///
+    /// The clock is only read every thousand or so instructions, so the run
+    /// can take slightly longer than `timeout`.
+    pub fn run_with_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.execute_until(None, Some(Instant::now() + timeout), false)
+    }
+
+    /// A handle that stops the program from another thread.
+    ///
+    /// Once [`AbortHandle::abort`] is called, the current or next run of the
+    /// program fails with [`Error::Aborted`] before its next instruction, and
+    /// the program can be resumed from there. All handles of a program share
+    /// one flag, which the run it stops clears.
+    pub fn abort_handle(&mut self) -> AbortHandle {
+        let flag = self.abort.get_or_insert_with(Default::default);
+        AbortHandle(Arc::clone(flag))
+    }
+
+    /// Iterates over the values the program outputs, running it only as far
+    /// as the next output each time.
+    ///
+    /// Values are handed out by the iterator instead of being collected in
+    /// [`output`](#method.output). The iterator ends when the program halts,
+    /// or after yielding the first error, such as running out of input.
+    pub fn outputs(&mut self) -> Outputs<'_, 'a> {
+        Outputs {
+            program: self,
+            input: None,
+            done: false,
+        }
+    }
+
+    /// Like [`outputs`](#method.outputs), but when the input queue runs dry
+    /// `input` is called for the next value. The program fails with
+    /// [`Error::MissingInput`] only once `input` returns `None`.
+    pub fn outputs_with_input<'p, F>(&'p mut self, input: F) -> Outputs<'p, 'a>
+    where
+        F: FnMut() -> Option<i64> + 'p,
+    {
+        Outputs {
+            program: self,
+            input: Some(Box::new(input)),
+            done: false,
+        }
+    }
+}
+
+/// Stops a [`Program`] from another thread, see [`Program::abort_handle`].
+#[derive(Debug, Clone)]
+pub struct AbortHandle(Arc<AtomicBool>);
+
+impl AbortHandle {
+    pub fn abort(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Iterator over the output of a [`Program`], see [`Program::outputs`].
+pub struct Outputs<'p, 'a> {
+    program: &'p mut Program<'a>,
+    input: Option<Box<dyn FnMut() -> Option<i64> + 'p>>,
+    done: bool,
+}
+
+impl Iterator for Outputs<'_, '_> {
+    type Item = Result<i64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let outputs = self.program.output.len();
+        loop {
+            match self.program.execute(None, true) {
+                Ok(()) if self.program.output.len() > outputs => {
+                    return self.program.output.pop().map(Ok);
+                }
+                Ok(()) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(Error::MissingInput(address)) => {
+                    match self.input.as_mut().and_then(|input| input()) {
+                        Some(value) => self.program.input.push_back(value),
+                        None => {
+                            self.done = true;
+                            return Some(Err(Error::MissingInput(address)));
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// The observable state of a [`Program`], see [`Program::state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub instruction_pointer: usize,
+    pub relative_base: i64,
+    /// Instructions completed so far.
+    pub steps: u64,
+    /// Input not yet read, in the order it will be read.
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    pub halted: bool,
+}
+
+/// An instruction's opcode and parameter words, as read from memory.
+///
+/// `available` counts the parameter words that could be read, which is less
+/// than the opcode needs only when the instruction runs past memory.
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+    opcode: OpCode,
+    params: [i64; 3],
+    available: usize,
 }
 
 #[derive(Debug)]
@@ -207,101 +925,245 @@ enum Instruction {
     Stop,
 }
 
-#[derive(Debug)]
-enum OpError {
+/// Reasons a program can stop before reaching a `99` instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The instruction at the address could not be decoded.
+    InvalidOp(usize, OpError),
+    /// An instruction or parameter was read past the end of memory.
+    ReadOutOfBounds(usize),
+    /// [`Program::write_memory`] was given an address outside memory and
+    /// every device.
+    WriteOutOfBounds(usize),
+    /// The jump at the address resolved to a negative target.
+    NegativeJumpTarget(usize, i64),
+    /// The input instruction at the address found the input queue empty.
+    MissingInput(usize),
+    /// The step limit was reached before the program halted.
+    StepLimitReached(usize),
+    /// An [`AbortHandle`] stopped the program after this many steps.
+    Aborted(usize),
+    /// The timeout passed before the program halted, after this many steps.
+    TimedOut(usize),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidOp(address, e) => write!(f, "invalid op at {}: {}", address, e),
+            Error::ReadOutOfBounds(address) => write!(f, "read out of bounds at {}", address),
+            Error::WriteOutOfBounds(address) => write!(f, "write out of bounds at {}", address),
+            Error::NegativeJumpTarget(address, target) => {
+                write!(f, "jump at {} to negative target {}", address, target)
+            }
+            Error::MissingInput(address) => {
+                write!(f, "expected input at {}, but it's empty", address)
+            }
+            Error::StepLimitReached(steps) => write!(f, "step limit reached after {} steps", steps),
+            Error::Aborted(steps) => write!(f, "aborted after {} steps", steps),
+            Error::TimedOut(steps) => write!(f, "timed out after {} steps", steps),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpError {
     UnrecognisedOpCode(u8),
-    PositionParamOutOfBounds(usize, i32),
+    PositionParamOutOfBounds(usize, i64),
     ImmediateTargetParam(usize),
     InvalidOpCode(OpCodeError),
+    OpCodeNotInProfile(u8, Profile),
+    ParamModeNotInProfile(usize, i64, Profile),
 }
 
-#[derive(Debug)]
-enum Op {
+impl Display for OpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpError::UnrecognisedOpCode(op) => write!(f, "unrecognised opcode {}", op),
+            OpError::PositionParamOutOfBounds(n, pos) => {
+                write!(f, "parameter {} points outside memory at {}", n, pos)
+            }
+            OpError::ImmediateTargetParam(n) => {
+                write!(f, "parameter {} is written to but in immediate mode", n)
+            }
+            OpError::InvalidOpCode(e) => write!(f, "{}", e),
+            OpError::OpCodeNotInProfile(op, profile) => write!(
+                f,
+                "opcode {} is not part of the {} instruction set",
+                op, profile
+            ),
+            OpError::ParamModeNotInProfile(n, mode, profile) => write!(
+                f,
+                "parameter {} uses mode {}, which is not part of the {} instruction set",
+                n, mode, profile
+            ),
+        }
+    }
+}
+
+/// A decoded instruction with its parameters resolved against the relative
+/// base. Written parameters are given as the address they write to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// `(left, right, target)`: stores `left + right` at `target`.
     Add((Param, Param, usize)),
+    /// `(left, right, target)`: stores `left * right` at `target`.
     Mul((Param, Param, usize)),
+    /// Stores the next input value at the address.
     Input(usize),
     Output(Param),
+    /// `(condition, target)`: jumps to `target` if `condition` is non-zero.
     JumpIfTrue((Param, Param)),
+    /// `(condition, target)`: jumps to `target` if `condition` is zero.
     JumpIfFalse((Param, Param)),
+    /// `(left, right, target)`: stores 1 at `target` if `left < right`, else 0.
     LessThan((Param, Param, usize)),
+    /// `(left, right, target)`: stores 1 at `target` if `left == right`, else 0.
     Equals((Param, Param, usize)),
+    /// Adds the parameter to the relative base.
+    AdjustRelativeBase(Param),
     Terminate,
 }
 
 impl Op {
-    fn size(&self) -> Option<usize> {
+    /// Number of words the instruction takes up; `None` for
+    /// [`Terminate`](Op::Terminate), after which nothing runs.
+    pub fn size(&self) -> Option<usize> {
         match self {
             Op::Add(_) | Op::Mul(_) | Op::LessThan(_) | Op::Equals(_) => Some(4),
             Op::JumpIfTrue(_) | Op::JumpIfFalse(_) => Some(3),
-            Op::Input(_) | Op::Output(_) => Some(2),
+            Op::Input(_) | Op::Output(_) | Op::AdjustRelativeBase(_) => Some(2),
             Op::Terminate => None,
         }
     }
 }
 
-#[derive(Debug)]
-enum Param {
+/// A parameter that is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    /// The value at the address. Relative parameters are resolved to this.
     Position(usize),
-    Immediate(i32),
+    /// The parameter word itself.
+    Immediate(i64),
 }
 
-#[derive(Debug, PartialEq)]
-enum ParamModeError {
-    UnrecognisedMode(i32),
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamModeError {
+    UnrecognisedMode(i64),
 }
 
-#[derive(Debug, PartialEq)]
-enum ParamMode {
+impl Display for ParamModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamModeError::UnrecognisedMode(mode) => write!(f, "unrecognised mode {}", mode),
+        }
+    }
+}
+
+/// How a parameter word is interpreted, as given by the digits of the opcode
+/// word above the opcode itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamMode {
+    /// Mode 0: the word is an address.
     Position,
+    /// Mode 1: the word is the value.
     Immediate,
+    /// Mode 2: the word is an address relative to the relative base.
+    Relative,
 }
 
 impl ParamMode {
-    fn as_int(&self) -> i32 {
+    /// The mode's digit in an opcode word.
+    pub fn as_int(&self) -> i64 {
         match self {
             ParamMode::Position => 0,
             ParamMode::Immediate => 1,
+            ParamMode::Relative => 2,
         }
     }
 }
 
-impl TryFrom<i32> for ParamMode {
+impl TryFrom<i64> for ParamMode {
     type Error = ParamModeError;
 
-    fn try_from(v: i32) -> Result<Self, Self::Error> {
+    fn try_from(v: i64) -> Result<Self, Self::Error> {
         match v {
             0 => Ok(ParamMode::Position),
             1 => Ok(ParamMode::Immediate),
+            2 => Ok(ParamMode::Relative),
             n => Err(ParamModeError::UnrecognisedMode(n)),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-struct OpCode {
-    op: u8,
-    arg0: ParamMode,
-    arg1: ParamMode,
-    arg2: ParamMode,
+/// An opcode word split into the opcode and the modes of its parameters.
+/// Parsed with `OpCode::try_from(word)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpCode {
+    /// The two lowest digits of the word.
+    pub op: u8,
+    /// Mode of the first parameter, the hundreds digit.
+    pub arg0: ParamMode,
+    /// Mode of the second parameter, the thousands digit.
+    pub arg1: ParamMode,
+    /// Mode of the third parameter, the ten thousands digit.
+    pub arg2: ParamMode,
 }
 
-const ARG2_MASK: i32 = 10_000;
-const ARG1_MASK: i32 = 1_000;
-const ARG0_MASK: i32 = 100;
+const ARG2_MASK: i64 = 10_000;
+const ARG1_MASK: i64 = 1_000;
+const ARG0_MASK: i64 = 100;
 
-#[derive(Debug, PartialEq)]
-enum OpCodeError {
-    InvalidOpcode(i32),
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpCodeError {
+    InvalidOpcode(i64),
     InvalidParamMode(usize, ParamModeError),
 }
 
-impl TryFrom<i32> for OpCode {
+impl Display for OpCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpCodeError::InvalidOpcode(op) => write!(f, "invalid opcode {}", op),
+            OpCodeError::InvalidParamMode(n, e) => write!(f, "{} for parameter {}", e, n),
+        }
+    }
+}
+
+impl OpCode {
+    /// The modes of the three parameters, in order.
+    pub fn modes(&self) -> [ParamMode; 3] {
+        [self.arg0, self.arg1, self.arg2]
+    }
+
+    /// Inverse of `OpCode::try_from`.
+    pub fn encode(&self) -> i64 {
+        i64::from(self.op)
+            + ARG0_MASK * self.arg0.as_int()
+            + ARG1_MASK * self.arg1.as_int()
+            + ARG2_MASK * self.arg2.as_int()
+    }
+
+    /// Number of parameters the opcode takes, or `None` if it isn't one.
+    pub fn param_count(&self) -> Option<usize> {
+        match self.op {
+            1 | 2 | 7 | 8 => Some(3),
+            5 | 6 => Some(2),
+            3 | 4 | 9 => Some(1),
+            99 => Some(0),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<i64> for OpCode {
     type Error = OpCodeError;
 
-    fn try_from(v: i32) -> Result<Self, Self::Error> {
+    fn try_from(v: i64) -> Result<Self, Self::Error> {
         use OpCodeError::*;
 
-        let mut value: i32 = *v.borrow();
+        let mut value: i64 = *v.borrow();
 
         let arg2 = ParamMode::try_from(value / ARG2_MASK).map_err(|e| InvalidParamMode(2, e))?;
         value -= ARG2_MASK * arg2.as_int();
@@ -333,7 +1195,7 @@ mod tests {
 
     #[test]
     fn opcode_parsing_works_for_all_combinations_of_arguments() {
-        vec![
+        [
             (
                 10010,
                 OpCode {
@@ -379,88 +1241,202 @@ mod tests {
     }
 
     #[test]
-    fn check_simple_add_program() {
-        let mut mem = vec![1101, 2, 3, 3, 99];
-        Program::new(&mut mem).run();
-        assert_eq!(
-            *mem.get(3).unwrap(),
-            5,
-            "Invalid sum value in memory position 3"
-        );
+    fn check_decode_cache_sees_modified_instructions() {
+        let program = vec![
+            104, 1, 1005, 17, 16, 1101, 0, 2, 1, 1101, 0, 1, 17, 1105, 1, 0, 99, 0,
+        ];
+        for cache in [true, false].iter() {
+            let mut mem = program.clone();
+            let p = Program::new(&mut mem).with_decode_cache(*cache).run();
+            assert_eq!(p.output(), &[1, 2], "Expected modified operand to be read");
+        }
     }
 
     #[test]
-    fn check_simple_mul_program() {
-        let mut mem = vec![1102, 2, 3, 3, 99];
-        Program::new(&mut mem).run();
-        assert_eq!(
-            *mem.get(3).unwrap(),
-            6,
-            "Invalid mul value in memory position 3"
-        );
+    fn check_decode_cache_grows_with_the_code_run() {
+        // Writes far out into sparse storage, then jumps to the halt
+        let image = [1101, 1, 1, 1 << 30, 1105, 1, 7, 99];
+        let mut p = Program::with_storage(storage::Paged::new(&image));
+        assert_eq!(p.run_with_limit(10), Ok(()));
+        assert!(p.memory().len() > 1 << 30);
+        assert_eq!(p.cache.len(), 8);
+        assert!(p.cache[7].is_some());
     }
 
     #[test]
-    fn check_simple_input_program() {
-        let mut mem = vec![3, 1, 99];
-        Program::new(&mut mem).add_input(&vec![1024]).run();
+    fn check_missing_input_is_resumable() {
+        let mut mem = vec![3, 3, 104, 0, 99];
+        let mut p = Program::new(&mut mem);
+        assert_eq!(p.run_with_limit(100), Err(Error::MissingInput(0)));
+
+        let mut p = p.add_input_value(42);
+        assert_eq!(p.run_with_limit(100), Ok(()));
+        assert_eq!(p.output(), &[42]);
+    }
+
+    #[test]
+    fn check_write_memory_stays_in_bounds() {
+        let mut mem = vec![4, 5, 99, 0, 0, 0];
+        let mut p = Program::new(&mut mem).attach(6, devices::CycleCounter::new());
+        assert_eq!(p.write_memory(5, 7), Ok(()));
+        assert_eq!(p.write_memory(6, 3), Ok(()));
+        assert_eq!(p.write_memory(7, 1), Err(Error::WriteOutOfBounds(7)));
         assert_eq!(
-            *mem.get(1).unwrap(),
-            1024,
-            "Expected inputed value in memory position 1"
+            Error::WriteOutOfBounds(7).to_string(),
+            "write out of bounds at 7"
         );
+        assert_eq!(p.run_with_limit(10), Ok(()));
+        assert_eq!(p.output(), &[7]);
     }
 
     #[test]
-    fn check_simple_output() {
-        let mut mem = vec![104, 1024, 99];
-        let p = Program::new(&mut mem).run();
+    fn check_invalid_opcodes_are_described() {
+        let run = |mem: &mut [i64]| Program::new(mem).run_with_limit(10).unwrap_err();
+        assert_eq!(
+            run(&mut [301, 0, 0, 0]).to_string(),
+            "invalid op at 0: unrecognised mode 3 for parameter 0"
+        );
         assert_eq!(
-            p.output(),
-            &[1024],
-            "Expected inputed value in memory position 1"
+            run(&mut [0]).to_string(),
+            "invalid op at 0: invalid opcode 0"
         );
     }
 
     #[test]
-    fn check_simple_jump_if_true() {
-        let mut mem = vec![1105, 1, 5, 104, 1, 99];
-        let p = Program::new(&mut mem).run();
-        assert_eq!(p.output(), &[], "Expected output to be empty");
+    fn check_step_limit_stops_infinite_loop() {
+        let mut mem = vec![1105, 1, 0];
+        let mut p = Program::new(&mut mem);
+        assert_eq!(p.run_with_limit(10), Err(Error::StepLimitReached(10)));
     }
 
     #[test]
-    fn check_simple_jump_if_false() {
-        let mut mem = vec![1106, 0, 5, 104, 1, 99];
-        let p = Program::new(&mut mem).run();
-        assert_eq!(p.output(), &[], "Expected output to be empty");
+    fn check_timeout_stops_infinite_loop() {
+        // Counts up in cell 7 forever
+        let mut mem = vec![1001, 7, 1, 7, 1105, 1, 0, 0];
+        let mut p = Program::new(&mut mem);
+        let steps = match p.run_with_timeout(Duration::from_millis(20)) {
+            Err(Error::TimedOut(steps)) => steps,
+            other => panic!("Expected a timeout, got {:?}", other),
+        };
+        assert!(steps > 0 && steps % CLOCK_INTERVAL == 0);
+        assert_eq!(p.steps(), steps as u64);
+        assert_eq!(p.instruction_pointer(), 0);
+        assert_eq!(p.run_with_limit(2), Err(Error::StepLimitReached(2)));
+        drop(p);
+        assert_eq!(mem[7] as usize, steps / 2 + 1);
+
+        let mut mem = vec![104, 1, 99];
+        let mut p = Program::new(&mut mem);
+        assert_eq!(p.run_with_timeout(Duration::from_secs(60)), Ok(()));
+        assert_eq!(p.output(), &[1]);
     }
 
     #[test]
-    fn check_simple_less_than_when_true() {
-        let mut mem = vec![1107, 0, 1, 1, 99];
-        Program::new(&mut mem).run();
-        assert_eq!(mem, &[1107, 1, 1, 1, 99], "Expected output to be empty");
+    fn check_abort_handle_stops_a_running_program() {
+        let mut mem = vec![1001, 7, 1, 7, 1105, 1, 0, 0];
+        let mut p = Program::new(&mut mem);
+        let handle = p.abort_handle();
+
+        // An abort before the run stops it straight away, just once
+        handle.abort();
+        assert_eq!(p.run_with_limit(10), Err(Error::Aborted(0)));
+        assert_eq!(p.run_with_limit(10), Err(Error::StepLimitReached(10)));
+
+        let result = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(20));
+                handle.abort();
+            });
+            p.run_with_timeout(Duration::from_secs(60))
+        });
+        let steps = match result {
+            Err(Error::Aborted(steps)) => steps,
+            other => panic!("Expected an abort, got {:?}", other),
+        };
+        assert_eq!(p.steps(), steps as u64 + 10);
+        assert_eq!(p.run_with_limit(3), Err(Error::StepLimitReached(3)));
     }
 
     #[test]
-    fn check_simple_less_than_when_false() {
-        let mut mem = vec![1107, 1, 1, 1, 99];
-        Program::new(&mut mem).run();
-        assert_eq!(mem, &[1107, 0, 1, 1, 99], "Expected output to be empty");
+    fn check_outputs_run_lazily() {
+        // Outputs 1, 2, 3 and then loops forever
+        let mut mem = vec![104, 1, 104, 2, 104, 3, 1105, 1, 6];
+        let mut p = Program::new(&mut mem);
+        let first: Vec<_> = p.outputs().take(3).collect();
+        assert_eq!(first, vec![Ok(1), Ok(2), Ok(3)]);
+        assert!(p.output().is_empty());
     }
 
     #[test]
-    fn check_simple_equals_when_true() {
-        let mut mem = vec![1108, 0, 0, 1, 99];
-        Program::new(&mut mem).run();
-        assert_eq!(mem, &[1108, 1, 0, 1, 99], "Expected output to be empty");
+    fn check_outputs_group_into_triples() {
+        let mut mem = vec![104, 1, 104, 2, 104, 3, 104, 4, 104, 5, 104, 6, 99];
+        let mut p = Program::new(&mut mem);
+        let mut outputs = p.outputs().map(Result::unwrap);
+        let mut triples = Vec::new();
+        while let (Some(x), Some(y), Some(tile)) = (outputs.next(), outputs.next(), outputs.next())
+        {
+            triples.push((x, y, tile));
+        }
+        assert_eq!(triples, vec![(1, 2, 3), (4, 5, 6)]);
+    }
+
+    #[test]
+    fn check_outputs_with_input_callback() {
+        // Echoes input until it reads a zero
+        let mut mem = vec![3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0];
+        let mut inputs = vec![0, 5, 7];
+        let mut asked = 0;
+        let mut p = Program::new(&mut mem).add_input_value(3);
+        let out: Result<Vec<_>, _> = p
+            .outputs_with_input(|| {
+                asked += 1;
+                inputs.pop()
+            })
+            .collect();
+        assert_eq!(out, Ok(vec![3, 7, 5]));
+        assert_eq!(asked, 3);
+    }
+
+    #[test]
+    fn check_outputs_stop_at_first_error() {
+        let mut mem = vec![104, 1, 3, 0, 99];
+        let mut p = Program::new(&mut mem);
+        let out: Vec<_> = p.outputs().collect();
+        assert_eq!(out, vec![Ok(1), Err(Error::MissingInput(2))]);
+
+        let mut p = p.add_input_value(9);
+        assert_eq!(p.outputs().next(), None);
     }
 
     #[test]
-    fn check_simple_equals_when_false() {
-        let mut mem = vec![1108, 1, 0, 1, 99];
-        Program::new(&mut mem).run();
-        assert_eq!(mem, &[1108, 0, 0, 1, 99], "Expected output to be empty");
+    fn check_state_is_observable() {
+        let mut mem = vec![109, 5, 3, 12, 204, 7, 99, 0, 0, 0, 0, 0, 0];
+        let mut p = Program::new(&mut mem).add_input(&[8, 9]);
+        assert_eq!(
+            p.decode_current_op(),
+            Ok(Op::AdjustRelativeBase(Param::Immediate(5)))
+        );
+
+        assert_eq!(p.run_with_limit(2), Err(Error::StepLimitReached(2)));
+        assert_eq!(
+            p.state(),
+            State {
+                instruction_pointer: 4,
+                relative_base: 5,
+                steps: 2,
+                input: vec![9],
+                output: vec![],
+                halted: false,
+            }
+        );
+        assert_eq!(p.memory().read(12), Some(8));
+        assert_eq!(p.decode_current_op(), Ok(Op::Output(Param::Position(12))));
+
+        assert_eq!(p.run_with_limit(10), Ok(()));
+        assert!(p.is_halted());
+        assert_eq!(p.instruction_pointer(), 6);
+        assert_eq!(p.steps(), 4);
+        assert_eq!(p.pending_input(), &[9]);
+        assert_eq!(p.output(), &[8]);
     }
 }
@@ -0,0 +1,401 @@
+//! Run manifests: programs, their input and what they should produce, as
+//! plain text.
+//!
+//! A manifest holds one setting per line, `#` starts a comment line:
+//!
+//! ```text
+//! # Compares the input with 8
+//! program compare.txt
+//! steps 1000
+//!
+//! case below
+//! input 7
+//! output 999
+//!
+//! case equal
+//! input 8
+//! output 1000
+//! memory 20 1000
+//! ```
+//!
+//! * `program <file>` names a file of comma separated words, relative to
+//!   the manifest, and `image <words>` gives the words inline,
+//! * `patch <address> <value>` writes to memory before the run,
+//! * `input <values>` queues input values,
+//! * `output <values>` expects exactly these values to be output, and a
+//!   bare `output` expects none,
+//! * `memory <address> <values>` expects the cells from the address on to
+//!   end up holding the values,
+//! * `steps <limit>` fails the run if it doesn't halt within the limit,
+//!   [`DEFAULT_STEPS`] otherwise.
+//!
+//! Settings before the first `case` line apply to every case; without any
+//! `case` lines the manifest is a single case. Values are separated by
+//! commas or spaces, and `input` and `output` lines add to each other.
+//!
+//! [`run_dir`] runs every manifest found under a directory, each case as a
+//! test, and reports the differences of those that fail.
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::batch::{run_job, Job};
+
+/// Extension of manifest files.
+pub const EXTENSION: &str = "manifest";
+
+/// Step limit of cases that don't set one.
+pub const DEFAULT_STEPS: usize = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    /// Empty for a manifest without `case` lines.
+    pub name: String,
+    /// Line the case starts on, counting from 1.
+    pub line: usize,
+    pub image: Vec<i64>,
+    pub patches: Vec<(usize, i64)>,
+    pub input: Vec<i64>,
+    pub output: Option<Vec<i64>>,
+    /// Expected values of memory cells, by address.
+    pub memory: Vec<(usize, i64)>,
+    pub steps: usize,
+}
+
+/// A manifest that couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestError {
+    pub path: PathBuf,
+    /// Line of the error counting from 1, or 0 if it's about the whole file.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+fn values(text: &str) -> Result<Vec<i64>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse().map_err(|_| format!("invalid value {:?}", v)))
+        .collect()
+}
+
+fn address(text: &str) -> Result<usize, String> {
+    text.parse()
+        .map_err(|_| format!("invalid address {:?}", text))
+}
+
+/// Applies the setting `key` with `value` to `case`.
+fn apply(case: &mut Case, key: &str, value: &str, dir: &Path) -> Result<(), String> {
+    let (first, rest) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+    match key {
+        "program" => {
+            let path = dir.join(value);
+            let text = fs::read_to_string(&path)
+                .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+            case.image = values(&text)?;
+        }
+        "image" => case.image = values(value)?,
+        "patch" => match values(rest)?[..] {
+            [v] => case.patches.push((address(first)?, v)),
+            _ => return Err("`patch` takes an address and a value".into()),
+        },
+        "input" => case.input.extend(values(value)?),
+        "output" => case
+            .output
+            .get_or_insert_with(Vec::new)
+            .extend(values(value)?),
+        "memory" => {
+            let start = address(first)?;
+            let expected = values(rest)?;
+            if expected.is_empty() {
+                return Err("`memory` takes an address and values".into());
+            }
+            case.memory.extend(
+                expected
+                    .into_iter()
+                    .enumerate()
+                    .map(|(n, v)| (start + n, v)),
+            );
+        }
+        "steps" => {
+            case.steps = value
+                .parse()
+                .map_err(|_| format!("invalid step limit {:?}", value))?
+        }
+        _ => return Err(format!("unknown setting `{}`", key)),
+    }
+    Ok(())
+}
+
+/// Parses the cases of a manifest; programs are looked up relative to
+/// `dir`. Errors are reported by line number and message.
+pub fn parse(text: &str, dir: &Path) -> Result<Vec<Case>, (usize, String)> {
+    let mut shared = Case {
+        name: String::new(),
+        line: 1,
+        image: Vec::new(),
+        patches: Vec::new(),
+        input: Vec::new(),
+        output: None,
+        memory: Vec::new(),
+        steps: DEFAULT_STEPS,
+    };
+    let mut cases: Vec<Case> = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let value = value.trim();
+        if key == "case" {
+            if value.is_empty() {
+                return Err((n + 1, "`case` needs a name".into()));
+            }
+            cases.push(Case {
+                name: value.to_string(),
+                line: n + 1,
+                ..shared.clone()
+            });
+            continue;
+        }
+        let case = cases.last_mut().unwrap_or(&mut shared);
+        apply(case, key, value, dir).map_err(|e| (n + 1, e))?;
+    }
+
+    if cases.is_empty() {
+        cases.push(shared);
+    }
+    match cases.iter().find(|c| c.image.is_empty()) {
+        Some(case) => Err((case.line, "no `program` or `image`".into())),
+        None => Ok(cases),
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Case>, ManifestError> {
+    let path = path.as_ref();
+    let error = |line, message| ManifestError {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+    let text = fs::read_to_string(path).map_err(|e| error(0, e.to_string()))?;
+    parse(&text, path.parent().unwrap_or_else(|| Path::new(".")))
+        .map_err(|(line, message)| error(line, message))
+}
+
+/// Every manifest under `dir`, sorted by path.
+pub fn discover<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    let mut manifests = Vec::new();
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == EXTENSION) {
+                manifests.push(path);
+            }
+        }
+    }
+    manifests.sort();
+    Ok(manifests)
+}
+
+fn join(values: &[i64]) -> String {
+    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+    values.join(", ")
+}
+
+impl Case {
+    /// Runs the case, returning what differed from the expectations.
+    pub fn run(&self) -> Result<(), String> {
+        let job = Job {
+            patches: self.patches.clone(),
+            input: self.input.clone(),
+        };
+        let outcome = run_job(&self.image, job, Some(self.steps));
+
+        let mut problems = Vec::new();
+        if let Err(e) = &outcome.result {
+            problems.push(format!("did not halt: {}", e));
+        }
+        if let Some(expected) = &self.output {
+            if *expected != outcome.output {
+                let at = expected
+                    .iter()
+                    .zip(&outcome.output)
+                    .take_while(|(e, a)| e == a)
+                    .count();
+                problems.push(format!(
+                    "output differs at {}\n  expected: {}\n  actual:   {}",
+                    at,
+                    join(expected),
+                    join(&outcome.output)
+                ));
+            }
+        }
+        for (address, expected) in &self.memory {
+            match outcome.memory.get(*address) {
+                Some(actual) if actual == expected => {}
+                Some(actual) => problems.push(format!(
+                    "memory at {}: expected {}, actual {}",
+                    address, expected, actual
+                )),
+                None => problems.push(format!(
+                    "memory at {}: expected {}, but memory ends at {}",
+                    address,
+                    expected,
+                    outcome.memory.len()
+                )),
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+}
+
+/// Name of a test: the manifest's path under `dir` without its extension,
+/// and the case.
+fn test_name(dir: &Path, path: &Path, case: &str) -> String {
+    let name = path.strip_prefix(dir).unwrap_or(path).with_extension("");
+    let name = name.to_string_lossy().replace('\\', "/");
+    if case.is_empty() {
+        name
+    } else {
+        format!("{}::{}", name, case)
+    }
+}
+
+/// Runs every case of every manifest under `dir` whose test name contains
+/// `filter`, reporting each to `out` like `cargo test` does. Returns whether
+/// all of them passed.
+pub fn run_dir<P: AsRef<Path>, W: Write>(dir: P, filter: &str, mut out: W) -> io::Result<bool> {
+    let dir = dir.as_ref();
+    let mut tests = Vec::new();
+    let mut failures = Vec::new();
+    for path in discover(dir)? {
+        match load(&path) {
+            Ok(cases) => tests.extend(cases.into_iter().map(|c| (path.clone(), c))),
+            Err(e) => failures.push((test_name(dir, &path, ""), e.to_string())),
+        }
+    }
+    tests.retain(|(path, case)| test_name(dir, path, &case.name).contains(filter));
+
+    writeln!(out, "\nrunning {} tests", tests.len())?;
+    let mut passed = 0;
+    for (path, case) in &tests {
+        let name = test_name(dir, path, &case.name);
+        match case.run() {
+            Ok(()) => {
+                passed += 1;
+                writeln!(out, "test {} ... ok", name)?;
+            }
+            Err(problems) => {
+                writeln!(out, "test {} ... FAILED", name)?;
+                let location = format!("{}:{}", path.display(), case.line);
+                failures.push((name, format!("{}\n{}", location, problems)));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        writeln!(out, "\nfailures:")?;
+        for (name, report) in &failures {
+            writeln!(out, "\n---- {} ----\n{}", name, report)?;
+        }
+    }
+    let status = if failures.is_empty() { "ok" } else { "FAILED" };
+    writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed\n",
+        status,
+        passed,
+        failures.len()
+    )?;
+    Ok(failures.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(text: &str) -> Case {
+        parse(text, Path::new(".")).unwrap().remove(0)
+    }
+
+    #[test]
+    fn cases_share_the_settings_before_them() {
+        let text = "# Doubles the input\n\
+                    image 3,9, 1002,9,2,9, 4,9, 99,0\n\
+                    steps 50\n\
+                    \n\
+                    case one\n\
+                    input 1\n\
+                    output 2\n\
+                    case two\n\
+                    input 4 5\n\
+                    patch 4 3\n\
+                    output 12\n\
+                    memory 9 12\n";
+        let cases = parse(text, Path::new(".")).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!((cases[0].name.as_str(), cases[0].line), ("one", 5));
+        assert_eq!(cases[0].input, [1]);
+        assert_eq!(cases[1].input, [4, 5]);
+        assert_eq!(cases[1].patches, [(4, 3)]);
+        assert_eq!(cases[1].steps, 50);
+        assert_eq!(cases[1].memory, [(9, 12)]);
+        assert!(cases.iter().all(|c| c.run().is_ok()));
+    }
+
+    #[test]
+    fn failures_show_what_differs() {
+        let failure = case("image 104,1,104,2,1101,2,2,9,99,0\noutput 1 3\nmemory 8 99,5,0")
+            .run()
+            .unwrap_err();
+        assert_eq!(
+            failure,
+            "output differs at 1\n  \
+             expected: 1, 3\n  \
+             actual:   1, 2\n\
+             memory at 9: expected 5, actual 4\n\
+             memory at 10: expected 0, but memory ends at 10"
+        );
+
+        let failure = case("image 3,0,99\noutput").run().unwrap_err();
+        assert_eq!(failure, "did not halt: expected input at 0, but it's empty");
+    }
+
+    #[test]
+    fn reports_malformed_manifests_by_line() {
+        let error = |text| parse(text, Path::new(".")).unwrap_err();
+        assert_eq!(
+            error("image 99\ncolour red"),
+            (2, "unknown setting `colour`".to_string())
+        );
+        assert_eq!(
+            error("image 99\npatch 1"),
+            (2, "`patch` takes an address and a value".to_string())
+        );
+        assert_eq!(
+            error("input 1\ncase a\nimage 99\ncase b"),
+            (4, "no `program` or `image`".to_string())
+        );
+        assert_eq!(error("program missing.txt").0, 1);
+    }
+}
@@ -0,0 +1,593 @@
+//! Memoized runs, for sweeps that run the same program on the same input
+//! over and over.
+//!
+//! A [`Memo`] keys each run by a hash of everything it depends on: the
+//! memory, the registers, the pending input, the profile and the step limit.
+//! On a hit the program is put in the state the first run left it in,
+//! without running anything; on a miss it runs and the result is stored.
+//!
+//! Programs are always run, bypassing the memo, while a device is attached,
+//! as a hit would leave the device as it was, or while they record coverage,
+//! a trace, taint or a replay, none of which a hit would fill in. So are
+//! programs using more than [`MAX_MEMORY`] cells, such as [`Paged`] storage
+//! written in the billions, which would take too long to hash and store;
+//! runs that leave memory that large aren't stored either.
+//!
+//! [`Paged`]: crate::storage::Paged
+//!
+//! Entries live in a [`Store`]: [`Lru`] keeps the most recently used ones in
+//! memory, [`Disk`] keeps one text file per entry in a directory, so they
+//! outlive the process. Runs that failed on an invalid instruction are not
+//! written to disk.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+
+use crate::batch::{self, Job, Outcome};
+use crate::{Error, Program};
+
+/// A 128-bit hash of a run's starting state.
+pub type Key = u128;
+
+/// Most memory cells a memoized run can start or end with.
+pub const MAX_MEMORY: usize = 1 << 20;
+
+/// FNV-1a, which unlike the standard library's hasher is stable across
+/// releases, as keys on disk need to be.
+struct Hasher(u128);
+
+impl Hasher {
+    fn new() -> Hasher {
+        Hasher(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u128::from(*byte);
+            self.0 = self
+                .0
+                .wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+    }
+
+    fn int(&mut self, value: i64) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// What a run did, enough to put another program starting from the same
+/// state where it ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Memory once the run stopped.
+    pub memory: Vec<i64>,
+    /// The values output by the run.
+    pub output: Vec<i64>,
+    pub instruction_pointer: usize,
+    pub relative_base: i64,
+    /// Instructions the run completed.
+    pub steps: u64,
+    /// Number of input values the run read.
+    pub input_read: usize,
+    pub halted: bool,
+    pub result: Result<(), Error>,
+}
+
+pub trait Store {
+    fn get(&mut self, key: Key) -> Option<Entry>;
+
+    fn put(&mut self, key: Key, entry: Entry);
+}
+
+/// The `capacity` most recently used entries, in memory.
+#[derive(Debug, Clone)]
+pub struct Lru {
+    capacity: usize,
+    entries: HashMap<Key, (Entry, u64)>,
+    /// Keys by when they were last used.
+    order: BTreeMap<u64, Key>,
+    clock: u64,
+}
+
+impl Lru {
+    pub fn new(capacity: usize) -> Lru {
+        Lru {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn touch(&mut self, key: Key) -> Option<&mut (Entry, u64)> {
+        self.clock += 1;
+        let clock = self.clock;
+        let (_, used) = self.entries.get_mut(&key)?;
+        self.order.remove(used);
+        self.order.insert(clock, key);
+        *used = clock;
+        self.entries.get_mut(&key)
+    }
+}
+
+impl Store for Lru {
+    fn get(&mut self, key: Key) -> Option<Entry> {
+        self.touch(key).map(|(entry, _)| entry.clone())
+    }
+
+    fn put(&mut self, key: Key, entry: Entry) {
+        if let Some(existing) = self.touch(key) {
+            existing.0 = entry;
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.order.insert(self.clock, key);
+        self.entries.insert(key, (entry, self.clock));
+    }
+}
+
+/// One file per entry in a directory. Entries that can't be read are
+/// misses, and entries that can't be written are dropped.
+#[derive(Debug, Clone)]
+pub struct Disk {
+    dir: PathBuf,
+}
+
+fn join(values: &[i64]) -> String {
+    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+fn encode_result(result: &Result<(), Error>) -> Option<String> {
+    Some(match result {
+        Ok(()) => "ok".to_string(),
+        Err(Error::ReadOutOfBounds(address)) => format!("read-out-of-bounds {}", address),
+        Err(Error::NegativeJumpTarget(address, target)) => {
+            format!("negative-jump {} {}", address, target)
+        }
+        Err(Error::MissingInput(address)) => format!("missing-input {}", address),
+        Err(Error::StepLimitReached(steps)) => format!("step-limit {}", steps),
+        Err(Error::InvalidOp(..))
+        | Err(Error::WriteOutOfBounds(_))
+        | Err(Error::Aborted(_))
+        | Err(Error::TimedOut(_)) => return None,
+    })
+}
+
+fn decode_result(text: &str) -> Option<Result<(), Error>> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    Some(match words[..] {
+        ["ok"] => Ok(()),
+        ["read-out-of-bounds", address] => Err(Error::ReadOutOfBounds(address.parse().ok()?)),
+        ["negative-jump", address, target] => Err(Error::NegativeJumpTarget(
+            address.parse().ok()?,
+            target.parse().ok()?,
+        )),
+        ["missing-input", address] => Err(Error::MissingInput(address.parse().ok()?)),
+        ["step-limit", steps] => Err(Error::StepLimitReached(steps.parse().ok()?)),
+        _ => return None,
+    })
+}
+
+fn encode(entry: &Entry) -> Option<String> {
+    Some(format!(
+        "result {}\nip {}\nrb {}\nsteps {}\ninput {}\nhalted {}\noutput {}\nmemory {}\n",
+        encode_result(&entry.result)?,
+        entry.instruction_pointer,
+        entry.relative_base,
+        entry.steps,
+        entry.input_read,
+        entry.halted,
+        join(&entry.output),
+        join(&entry.memory),
+    ))
+}
+
+fn decode(text: &str) -> Option<Entry> {
+    let fields: HashMap<&str, &str> = text
+        .lines()
+        .map(|line| line.split_once(' ').unwrap_or((line, "")))
+        .collect();
+    let list = |name: &str| -> Option<Vec<i64>> {
+        fields
+            .get(name)?
+            .split(',')
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().ok())
+            .collect()
+    };
+    Some(Entry {
+        memory: list("memory")?,
+        output: list("output")?,
+        instruction_pointer: fields.get("ip")?.parse().ok()?,
+        relative_base: fields.get("rb")?.parse().ok()?,
+        steps: fields.get("steps")?.parse().ok()?,
+        input_read: fields.get("input")?.parse().ok()?,
+        halted: fields.get("halted")?.parse().ok()?,
+        result: decode_result(fields.get("result")?)?,
+    })
+}
+
+impl Disk {
+    /// Keeps entries in `dir`, creating it if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Disk> {
+        fs::create_dir_all(&dir)?;
+        Ok(Disk {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, key: Key) -> PathBuf {
+        self.dir.join(format!("{:032x}.run", key))
+    }
+}
+
+impl Store for Disk {
+    fn get(&mut self, key: Key) -> Option<Entry> {
+        decode(&fs::read_to_string(self.path(key)).ok()?)
+    }
+
+    fn put(&mut self, key: Key, entry: Entry) {
+        let text = match encode(&entry) {
+            Some(text) => text,
+            None => return,
+        };
+        // Written aside and renamed, so that readers never see half a file
+        let path = self.path(key);
+        let partial = path.with_extension(format!("{}.partial", process::id()));
+        if fs::write(&partial, text).is_ok() && fs::rename(&partial, &path).is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Runs that couldn't be memoized, see the [module docs](self).
+    pub bypassed: u64,
+}
+
+/// Memoizes runs in a [`Store`]. Shared between threads, it can serve a
+/// whole [`Batch`](crate::batch::Batch).
+pub struct Memo {
+    store: Mutex<Box<dyn Store + Send>>,
+    stats: Mutex<Stats>,
+}
+
+impl fmt::Debug for Memo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memo")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Memo {
+    pub fn new<S: Store + Send + 'static>(store: S) -> Memo {
+        Memo {
+            store: Mutex::new(Box::new(store)),
+            stats: Mutex::new(Stats::default()),
+        }
+    }
+
+    /// A memo of the `capacity` most recently used runs.
+    pub fn in_memory(capacity: usize) -> Memo {
+        Memo::new(Lru::new(capacity))
+    }
+
+    /// A memo kept in `dir`.
+    pub fn on_disk<P: AsRef<Path>>(dir: P) -> io::Result<Memo> {
+        Ok(Memo::new(Disk::new(dir)?))
+    }
+
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
+
+    fn count(&self, f: impl FnOnce(&mut Stats)) {
+        f(&mut self.stats.lock().unwrap());
+    }
+
+    /// The key of running `program` from its current state, or `None` if
+    /// the run can't be memoized.
+    pub fn key(program: &Program, step_limit: Option<usize>) -> Option<Key> {
+        let replayable = program.coverage.is_none()
+            && program.recording.is_none()
+            && program.taint.is_none()
+            && program.trace.is_none()
+            && program.before_hooks.is_empty()
+            && program.after_hooks.is_empty()
+            && program.devices.is_empty();
+        if !replayable || program.memory.len() > MAX_MEMORY {
+            return None;
+        }
+
+        let mut hasher = Hasher::new();
+        let memory = &program.memory;
+        hasher.int(memory.len() as i64);
+        for address in 0..memory.len() {
+            hasher.int(memory.read(address).unwrap_or(0));
+        }
+        hasher.int(program.next_op as i64);
+        hasher.int(program.relative_base);
+        hasher.int(program.halted as i64);
+        hasher.int(program.input.len() as i64);
+        program.input.iter().for_each(|v| hasher.int(*v));
+        hasher.bytes(format!("{:?}", program.profile).as_bytes());
+        hasher.int(step_limit.map_or(-1, |limit| limit as i64));
+        Some(hasher.0)
+    }
+
+    /// Runs `program` until it halts, fails or executes `step_limit`
+    /// instructions, as [`Program::run_with_limit`] does, or puts it in the
+    /// state an earlier run from the same state ended in.
+    pub fn run(&self, program: &mut Program, step_limit: Option<usize>) -> Result<(), Error> {
+        let key = match Memo::key(program, step_limit) {
+            Some(key) => key,
+            None => {
+                self.count(|s| s.bypassed += 1);
+                return program.execute(step_limit, false);
+            }
+        };
+
+        let entry = self.store.lock().unwrap().get(key);
+        if let Some(entry) = entry {
+            // A hit stands in for a run, so it honours an abort as one would
+            if program.take_abort() {
+                return Err(Error::Aborted(0));
+            }
+            self.count(|s| s.hits += 1);
+            return replay(program, entry);
+        }
+
+        self.count(|s| s.misses += 1);
+        let (steps, input, output) = (program.steps, program.input.len(), program.output.len());
+        let result = program.execute(step_limit, false);
+        // Where an aborted run stopped depends on timing
+        if let Err(Error::Aborted(_)) = result {
+            return result;
+        }
+        if program.memory.len() > MAX_MEMORY {
+            return result;
+        }
+        let memory = &program.memory;
+        let entry = Entry {
+            memory: (0..memory.len())
+                .map(|address| memory.read(address).unwrap_or(0))
+                .collect(),
+            output: program.output[output..].to_vec(),
+            instruction_pointer: program.next_op,
+            relative_base: program.relative_base,
+            steps: program.steps - steps,
+            input_read: input - program.input.len(),
+            halted: program.halted,
+            result: result.clone(),
+        };
+        self.store.lock().unwrap().put(key, entry);
+        result
+    }
+
+    /// Runs `job` on a copy of `image` like [`batch::run_job`], through the
+    /// memo.
+    pub fn run_job(&self, image: &[i64], job: Job, step_limit: Option<usize>) -> Outcome {
+        batch::run_with(image, job, |program| self.run(program, step_limit))
+    }
+}
+
+fn replay(program: &mut Program, entry: Entry) -> Result<(), Error> {
+    let size = program.memory.len().max(entry.memory.len());
+    for address in 0..size {
+        let value = entry.memory.get(address).copied().unwrap_or(0);
+        if program.memory.read(address) != Some(value) {
+            program.memory.write(address, value);
+        }
+    }
+    program.cache.clear();
+    program.output.extend(entry.output);
+    program.next_op = entry.instruction_pointer;
+    program.relative_base = entry.relative_base;
+    program.steps += entry.steps;
+    program.input.drain(..entry.input_read);
+    program.halted = entry.halted;
+    entry.result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::Batch;
+    use crate::devices::{CycleCounter, Framebuffer, Random};
+    use crate::storage::Paged;
+    use crate::test_programs::FACTORIALS;
+    use std::env;
+
+    #[test]
+    fn hits_leave_programs_as_runs_do() {
+        let memo = Memo::in_memory(4);
+        let mut states = Vec::new();
+        for _ in 0..2 {
+            let mut memory = FACTORIALS.to_vec();
+            let mut program = Program::new(&mut memory).add_input(&[3, 4]);
+            // Stops waiting for more input, then goes on to halt
+            assert_eq!(memo.run(&mut program, None), Err(Error::MissingInput(0)));
+            program.input.push_back(0);
+            assert_eq!(memo.run(&mut program, Some(100)), Ok(()));
+            states.push(program.state());
+        }
+        assert_eq!(states[0], states[1]);
+        assert_eq!(states[1].output, [6, 24]);
+        assert_eq!(
+            memo.stats(),
+            Stats {
+                hits: 2,
+                misses: 2,
+                bypassed: 0
+            }
+        );
+
+        // A different limit is a different run
+        let mut memory = FACTORIALS.to_vec();
+        let mut program = Program::new(&mut memory).add_input(&[3, 0]);
+        assert_eq!(
+            memo.run(&mut program, Some(5)),
+            Err(Error::StepLimitReached(5))
+        );
+        assert_eq!(memo.stats().misses, 3);
+    }
+
+    #[test]
+    fn hits_honour_aborts() {
+        let memo = Memo::in_memory(4);
+        let mut memory = FACTORIALS.to_vec();
+        let mut program = Program::new(&mut memory).add_input(&[3, 0]);
+        assert_eq!(memo.run(&mut program, None), Ok(()));
+
+        let mut memory = FACTORIALS.to_vec();
+        let mut program = Program::new(&mut memory).add_input(&[3, 0]);
+        program.abort_handle().abort();
+        assert_eq!(memo.run(&mut program, None), Err(Error::Aborted(0)));
+        assert_eq!(program.steps(), 0);
+        assert_eq!(program.output(), &[]);
+
+        // The abort only stops one run
+        assert_eq!(memo.run(&mut program, None), Ok(()));
+        assert_eq!(program.output(), &[6]);
+        assert_eq!(memo.stats().hits, 1);
+    }
+
+    #[test]
+    fn least_recently_used_entries_go_first() {
+        let mut lru = Lru::new(2);
+        let entry = |steps| Entry {
+            memory: vec![],
+            output: vec![],
+            instruction_pointer: 0,
+            relative_base: 0,
+            steps,
+            input_read: 0,
+            halted: true,
+            result: Ok(()),
+        };
+        lru.put(1, entry(1));
+        lru.put(2, entry(2));
+        assert_eq!(lru.get(1).map(|e| e.steps), Some(1));
+        lru.put(3, entry(3));
+        assert_eq!(lru.len(), 2);
+        assert!(lru.get(2).is_none());
+        assert!(lru.get(1).is_some());
+        assert!(lru.get(3).is_some());
+    }
+
+    #[test]
+    fn bypasses_what_it_cannot_replay() {
+        let memo = Memo::in_memory(4);
+        // Outputs the device's first cell twice
+        let image = [4, 7, 4, 7, 99, 0, 0, 0];
+        let run = |counted: bool| {
+            let mut memory = image.to_vec();
+            let mut program = if counted {
+                Program::new(&mut memory).attach(7, CycleCounter::new())
+            } else {
+                Program::new(&mut memory).attach(7, Random::with_seed(1))
+            };
+            memo.run(&mut program, None).unwrap();
+            program.output().to_vec()
+        };
+        assert_eq!(run(true), [0, 1]);
+        assert_eq!(run(true), [0, 1]);
+        run(false);
+        run(false);
+
+        let mut memory = image.to_vec();
+        let mut program = Program::new(&mut memory).with_coverage();
+        memo.run(&mut program, None).unwrap();
+        assert_eq!(
+            memo.stats(),
+            Stats {
+                hits: 0,
+                misses: 0,
+                bypassed: 5
+            }
+        );
+    }
+
+    #[test]
+    fn devices_see_every_run() {
+        let memo = Memo::in_memory(4);
+        // Lights the second pixel
+        let image = [1101, 1, 0, 9, 99];
+        let draw = || {
+            let mut screen = Framebuffer::new(2, 1);
+            let mut memory = image.to_vec();
+            let mut program = Program::new(&mut memory).attach(8, &mut screen);
+            memo.run(&mut program, None).unwrap();
+            drop(program);
+            screen.render()
+        };
+        assert_eq!(draw(), " #\n");
+        assert_eq!(draw(), " #\n");
+        assert_eq!(memo.stats().bypassed, 2);
+    }
+
+    #[test]
+    fn sparse_memory_is_not_stored() {
+        let memo = Memo::in_memory(4);
+        // Writes a cell in the billions
+        let image = [1101, 7, 0, 5_000_000_000, 99];
+        for _ in 0..2 {
+            let mut storage = Paged::new(&image);
+            let mut program = Program::with_storage(&mut storage);
+            assert_eq!(memo.run(&mut program, None), Ok(()));
+            // Too large to key from where it ended
+            assert_eq!(memo.run(&mut program, None), Ok(()));
+        }
+        assert_eq!(
+            memo.stats(),
+            Stats {
+                hits: 0,
+                misses: 2,
+                bypassed: 2
+            }
+        );
+    }
+
+    #[test]
+    fn entries_persist_on_disk() {
+        let dir = env::temp_dir().join(format!("intcode-memo-{}", process::id()));
+        let jobs = || (1..=5).map(|n| Job::new().add_input(&[n, 0]));
+
+        let memo = Memo::on_disk(&dir).unwrap();
+        let first = Batch::new(&FACTORIALS).with_memo(&memo).run(jobs());
+        assert_eq!(memo.stats().misses, 5);
+        // Runs that fail on an invalid instruction aren't written
+        memo.run_job(&[0], Job::new(), None);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 5);
+
+        let memo = Memo::on_disk(&dir).unwrap();
+        let second = Batch::new(&FACTORIALS).with_memo(&memo).run(jobs());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(second[4].output, [120]);
+        assert_eq!(memo.stats().hits, 5);
+    }
+}
@@ -0,0 +1,456 @@
+//! Peephole optimizer for program images.
+//!
+//! [`optimize`] rewrites an image in place before it is run. It finds every
+//! statically reachable instruction and then:
+//!
+//! * turns position mode reads of cells that no instruction ever writes into
+//!   immediate mode reads of the cell's value,
+//! * folds arithmetic and comparisons whose operands are all immediate into
+//!   an add of the result and zero,
+//! * points jumps that land on an unconditional jump straight at its target.
+//!
+//! Cells that the program writes or reads as data are never rewritten, and
+//! programs whose control flow or writes can't be followed
+//! statically (jumps to computed targets, relative mode, or writes into code
+//! that may still run) are not touched at all.
+//!
+//! The optimized image produces the same output and leaves the same values in
+//! every cell it did not rewrite, but it may take fewer steps and it uses
+//! immediate mode, so it needs the day 5 profile or later. Devices mapped over
+//! the image are not taken into account.
+//!
+//! [`optimize`]: fn.optimize.html
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+use crate::{OpCode, ParamMode};
+
+/// A rewrite made by the optimizer, identified by the instruction address.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Parameter `param` (1-based) now holds the constant `value`.
+    ImmediateRead {
+        address: usize,
+        param: usize,
+        value: i64,
+    },
+    /// The instruction now writes the constant `value`.
+    Folded { address: usize, value: i64 },
+    /// The jump now goes straight to `to` instead of through `from`.
+    JumpThreaded {
+        address: usize,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::ImmediateRead {
+                address,
+                param,
+                value,
+            } => write!(
+                f,
+                "{}: parameter {} reads constant {}",
+                address, param, value
+            ),
+            Change::Folded { address, value } => write!(f, "{}: folded to {}", address, value),
+            Change::JumpThreaded { address, from, to } => {
+                write!(f, "{}: jump to {} threaded to {}", address, from, to)
+            }
+        }
+    }
+}
+
+/// Why a program was left unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unsupported {
+    /// The jump at the address has a target computed at run time.
+    DynamicJump(usize),
+    /// The instruction at the address uses relative mode.
+    RelativeMode(usize),
+    /// The instruction at the address writes into code that may still run.
+    SelfModifying(usize),
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsupported::DynamicJump(a) => write!(f, "{}: jump target computed at run time", a),
+            Unsupported::RelativeMode(a) => write!(f, "{}: uses relative mode", a),
+            Unsupported::SelfModifying(a) => write!(f, "{}: modifies code that may run", a),
+        }
+    }
+}
+
+struct Instr {
+    opcode: OpCode,
+    params: usize,
+    writes: bool,
+}
+
+impl Instr {
+    fn mode(&self, k: usize) -> &ParamMode {
+        match k {
+            0 => &self.opcode.arg0,
+            1 => &self.opcode.arg1,
+            _ => &self.opcode.arg2,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.params + 1
+    }
+
+    fn is_jump(&self) -> bool {
+        self.opcode.op == 5 || self.opcode.op == 6
+    }
+}
+
+fn decode(memory: &[i64], address: usize) -> Option<Instr> {
+    let opcode = OpCode::try_from(*memory.get(address)?).ok()?;
+    let (params, writes) = match opcode.op {
+        1 | 2 | 7 | 8 => (3, true),
+        3 => (1, true),
+        4 | 9 => (1, false),
+        5 | 6 => (2, false),
+        99 => (0, false),
+        _ => return None,
+    };
+    if address + params >= memory.len() {
+        return None;
+    }
+
+    Some(Instr {
+        opcode,
+        params,
+        writes,
+    })
+}
+
+/// Finds every instruction reachable from address 0, along with the
+/// reachable addresses that don't hold a valid instruction.
+type Code = (BTreeMap<usize, Instr>, BTreeSet<usize>);
+
+fn reachable(memory: &[i64]) -> Result<Code, Unsupported> {
+    let mut found = BTreeMap::new();
+    let mut invalid = BTreeSet::new();
+    let mut pending = vec![0];
+
+    while let Some(address) = pending.pop() {
+        if found.contains_key(&address) {
+            continue;
+        }
+        let instr = match decode(memory, address) {
+            Some(instr) => instr,
+            None => {
+                invalid.insert(address);
+                continue;
+            }
+        };
+
+        if (0..instr.params).any(|k| *instr.mode(k) == ParamMode::Relative) {
+            return Err(Unsupported::RelativeMode(address));
+        }
+        if instr.is_jump() {
+            if *instr.mode(1) != ParamMode::Immediate {
+                return Err(Unsupported::DynamicJump(address));
+            }
+            let target = memory[address + 2];
+            if target >= 0 {
+                pending.push(target as usize);
+            }
+        }
+        if instr.opcode.op != 99 {
+            pending.push(address + instr.size());
+        }
+
+        found.insert(address, instr);
+    }
+
+    Ok((found, invalid))
+}
+
+/// Rewrites `memory` in place and reports what changed.
+pub fn optimize(memory: &mut [i64]) -> Result<Vec<Change>, Unsupported> {
+    let (code, invalid) = reachable(memory)?;
+    let straight_line = !code.values().any(|i| i.is_jump());
+
+    // Writing over an invalid instruction could make it run, so those
+    // addresses count as code that runs last.
+    let mut owners: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (address, instr) in code.iter() {
+        for cell in *address..address + instr.size() {
+            owners.entry(cell).or_default().push(*address);
+        }
+    }
+    for address in invalid.iter() {
+        owners.entry(*address).or_default().push(usize::MAX);
+    }
+
+    let mut written = BTreeSet::new();
+    let mut read = BTreeSet::new();
+    for (address, instr) in code.iter() {
+        for k in 0..instr.params {
+            if *instr.mode(k) != ParamMode::Position {
+                continue;
+            }
+            let cell = memory[address + 1 + k] as usize;
+            if instr.writes && k == instr.params - 1 {
+                // Without jumps every instruction runs once, in order, so
+                // writing into code that already ran is harmless.
+                let harmless = owners
+                    .get(&cell)
+                    .is_none_or(|o| straight_line && o.iter().all(|owner| owner <= address));
+                if !harmless {
+                    return Err(Unsupported::SelfModifying(*address));
+                }
+                written.insert(cell);
+            } else {
+                read.insert(cell);
+            }
+        }
+    }
+
+    // Cells the program reads as data or writes keep their values, and so do
+    // instructions that share cells with another instruction.
+    let free = |cell: usize| !written.contains(&cell) && !read.contains(&cell);
+    let overlapping = |address: usize, instr: &Instr| {
+        (address..address + instr.size()).any(|cell| owners[&cell].len() > 1)
+    };
+
+    let mut changes = Vec::new();
+    for (address, instr) in code.iter().filter(|(a, i)| !overlapping(**a, i)) {
+        let reads = if instr.writes {
+            instr.params - 1
+        } else {
+            instr.params
+        };
+        let reads = if instr.is_jump() { 1 } else { reads };
+
+        for k in 0..reads {
+            let cell = memory[address + 1 + k];
+            let constant = *instr.mode(k) == ParamMode::Position
+                && (cell as usize) < memory.len()
+                && !written.contains(&(cell as usize))
+                && free(*address)
+                && free(address + 1 + k);
+            if constant {
+                let value = memory[cell as usize];
+                memory[*address] += 100 * 10i64.pow(k as u32);
+                memory[address + 1 + k] = value;
+                changes.push(Change::ImmediateRead {
+                    address: *address,
+                    param: k + 1,
+                    value,
+                });
+            }
+        }
+
+        let op = memory[*address] % 100;
+        let folds = [1, 2, 7, 8].contains(&op)
+            // Both reads immediate and the target in position mode, the only
+            // form the rewrite to 1101 keeps
+            && memory[*address] / 100 == 11
+            && !(op == 1 && memory[address + 2] == 0)
+            && (*address..address + 3).all(free);
+        if folds {
+            let (left, right) = (memory[address + 1], memory[address + 2]);
+            let value = match op {
+                1 => left.wrapping_add(right),
+                2 => left.wrapping_mul(right),
+                7 => (left < right) as i64,
+                _ => (left == right) as i64,
+            };
+            memory[*address] = 1101;
+            memory[address + 1] = value;
+            memory[address + 2] = 0;
+            changes.push(Change::Folded {
+                address: *address,
+                value,
+            });
+        }
+    }
+
+    // The target of an unconditional jump, if the instruction at `address`
+    // is one that the program never modifies.
+    let unconditional = |memory: &[i64], address: usize| {
+        let instr = code.get(&address)?;
+        if !instr.is_jump() || (address..address + 3).any(|c| written.contains(&c)) {
+            return None;
+        }
+        let opcode = OpCode::try_from(memory[address]).ok()?;
+        if opcode.arg0 != ParamMode::Immediate || opcode.arg1 != ParamMode::Immediate {
+            return None;
+        }
+        let jumps = (memory[address + 1] != 0) == (opcode.op == 5);
+        let target = memory[address + 2];
+        if jumps && target >= 0 {
+            Some(target as usize)
+        } else {
+            None
+        }
+    };
+
+    for (address, _) in code
+        .iter()
+        .filter(|(a, i)| i.is_jump() && !overlapping(**a, i) && free(*a + 2))
+    {
+        let from = memory[address + 2] as usize;
+        let mut to = from;
+        let mut visited = BTreeSet::new();
+        while let Some(next) = unconditional(memory, to) {
+            if !visited.insert(to) {
+                break;
+            }
+            to = next;
+        }
+        if to != from {
+            memory[address + 2] = to as i64;
+            changes.push(Change::JumpThreaded {
+                address: *address,
+                from,
+                to,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzz;
+    use crate::xorshift::Xorshift;
+    use crate::{Error, Profile, Program};
+
+    fn run(memory: &mut [i64], input: &[i64]) -> (Result<(), Error>, Vec<i64>) {
+        let mut p = Program::new(memory).add_input(input);
+        let result = p.run_with_limit(fuzz::STEP_LIMIT);
+        (result, p.output().to_vec())
+    }
+
+    #[test]
+    fn constant_reads_become_immediate() {
+        let mut mem = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let changes = optimize(&mut mem).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![Change::ImmediateRead {
+                address: 4,
+                param: 2,
+                value: 50
+            }]
+        );
+        assert_eq!(mem, &[1, 9, 10, 3, 1002, 3, 50, 0, 99, 30, 40, 50]);
+
+        assert_eq!(run(&mut mem, &[]).0, Ok(()));
+        assert_eq!(mem[0], 3500);
+    }
+
+    #[test]
+    fn constant_arithmetic_is_folded() {
+        let mut mem = vec![1, 8, 9, 10, 4, 10, 99, 0, 3, 4, 0];
+        let changes = optimize(&mut mem).unwrap();
+
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[2],
+            Change::Folded {
+                address: 0,
+                value: 7
+            }
+        );
+        assert_eq!(&mem[..4], &[1101, 7, 0, 10]);
+        assert_eq!(run(&mut mem, &[]).1, &[7]);
+    }
+
+    #[test]
+    fn immediate_targets_are_not_folded() {
+        let image = [11101, 2, 3, 5, 99, 0];
+        let mut mem = image;
+        let expected = run(&mut mem.clone(), &[]);
+        assert_eq!(optimize(&mut mem), Ok(vec![]));
+        assert_eq!(mem, image);
+        assert_eq!(run(&mut mem, &[]), expected);
+    }
+
+    #[test]
+    fn jumps_to_jumps_are_threaded() {
+        let mut mem = vec![1105, 1, 3, 1105, 1, 6, 104, 5, 99];
+        let changes = optimize(&mut mem).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![Change::JumpThreaded {
+                address: 0,
+                from: 3,
+                to: 6
+            }]
+        );
+        assert_eq!(run(&mut mem, &[]).1, &[5]);
+    }
+
+    #[test]
+    fn self_modifying_code_is_left_alone() {
+        let mut mem = vec![3, 6, 1105, 1, 3, 104, 0, 99];
+        let original = mem.clone();
+
+        assert_eq!(optimize(&mut mem), Err(Unsupported::SelfModifying(0)));
+        assert_eq!(mem, original);
+    }
+
+    #[test]
+    fn computed_jumps_and_relative_mode_are_left_alone() {
+        assert_eq!(
+            optimize(&mut [105, 1, 3, 99]),
+            Err(Unsupported::DynamicJump(0))
+        );
+        assert_eq!(
+            optimize(&mut [204, 0, 99]),
+            Err(Unsupported::RelativeMode(0))
+        );
+    }
+
+    #[test]
+    fn optimized_generated_programs_behave_the_same() {
+        let mut rng = Xorshift::new(0x0971);
+        let mut next = || rng.next_u64();
+        let mut optimized = 0;
+        for _ in 0..20_000 {
+            let len = next() % 256;
+            let data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            let profile = [Profile::Day2, Profile::Day5][(len % 2) as usize];
+            let case = fuzz::generate_for(&data, profile);
+
+            let mut expected_mem = case.memory.clone();
+            let expected = run(&mut expected_mem, &case.input);
+
+            let mut mem = case.memory.clone();
+            let changes = match optimize(&mut mem) {
+                Ok(changes) if !changes.is_empty() => changes,
+                _ => continue,
+            };
+            optimized += 1;
+            let rewritten: Vec<_> = (0..mem.len())
+                .filter(|i| mem[*i] != case.memory[*i])
+                .collect();
+            let actual = run(&mut mem, &case.input);
+
+            assert_eq!(actual.1, expected.1, "{:?} after {:?}", case, changes);
+            if expected.0 != Err(Error::StepLimitReached(fuzz::STEP_LIMIT)) {
+                assert_eq!(actual.0, expected.0, "{:?} after {:?}", case, changes);
+            }
+            for i in (0..mem.len()).filter(|i| !rewritten.contains(i)) {
+                assert_eq!(mem[i], expected_mem[i], "cell {} of {:?}", i, case);
+            }
+        }
+        assert!(optimized > 0);
+    }
+}
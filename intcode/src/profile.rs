@@ -0,0 +1,45 @@
+//! Instruction sets introduced by the puzzles, in the order they appeared.
+
+use std::fmt::{self, Display};
+
+use crate::ParamMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// Add, multiply and halt, with position mode parameters only.
+    Day2,
+    /// Adds input, output, jumps and comparisons, and immediate mode.
+    Day5,
+    /// Adds relative mode and the relative base adjustment; the full set.
+    #[default]
+    Day9,
+}
+
+impl Profile {
+    pub fn allows_op(self, op: u8) -> bool {
+        match op {
+            1 | 2 | 99 => true,
+            3..=8 => self != Profile::Day2,
+            9 => self == Profile::Day9,
+            _ => false,
+        }
+    }
+
+    pub fn allows_mode(self, mode: &ParamMode) -> bool {
+        match mode {
+            ParamMode::Position => true,
+            ParamMode::Immediate => self != Profile::Day2,
+            ParamMode::Relative => self == Profile::Day9,
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Profile::Day2 => write!(f, "day 2"),
+            Profile::Day5 => write!(f, "day 5"),
+            Profile::Day9 => write!(f, "day 9"),
+        }
+    }
+}
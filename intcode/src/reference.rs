@@ -0,0 +1,151 @@
+//! A deliberately simple Intcode interpreter used as an oracle for [`Program`].
+//!
+//! It shares no decoding or execution code with [`Program`]: instructions are
+//! split into digits with plain arithmetic and executed straight from memory.
+//! It is slow and unstructured on purpose, so that it stays easy to check
+//! against the puzzle descriptions by reading it.
+//!
+//! [`Program`]: ../struct.Program.html
+
+/// Why the reference interpreter stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    Halted,
+    InvalidOp(usize),
+    ReadOutOfBounds(usize),
+    NegativeJumpTarget(usize),
+    MissingInput(usize),
+    StepLimitReached,
+}
+
+#[derive(Debug)]
+pub struct Outcome {
+    pub output: Vec<i64>,
+    pub stop: Stop,
+}
+
+/// Runs `memory` with the given `input` for at most `step_limit` instructions.
+pub fn run(memory: &mut [i64], input: &[i64], step_limit: usize) -> Outcome {
+    let mut output = Vec::new();
+    let mut input = input.iter();
+    let mut ip = 0;
+    let mut base: i64 = 0;
+    let mut steps = 0;
+
+    let stop = loop {
+        if steps == step_limit {
+            break Stop::StepLimitReached;
+        }
+        steps += 1;
+
+        let instr = match memory.get(ip) {
+            Some(v) => *v,
+            None => break Stop::ReadOutOfBounds(ip),
+        };
+
+        let opcode = instr % 100;
+        let modes = [instr / 100 % 10, instr / 1000 % 10, instr / 10000 % 10];
+        if instr <= 0 || instr >= 30000 || modes.iter().any(|m| *m > 2) {
+            break Stop::InvalidOp(ip);
+        }
+
+        let (count, writes) = match opcode {
+            1 | 2 | 7 | 8 => (3, true),
+            3 => (1, true),
+            4 => (1, false),
+            5 | 6 => (2, false),
+            9 => (1, false),
+            99 => break Stop::Halted,
+            _ => break Stop::InvalidOp(ip),
+        };
+
+        // Raw parameter words and the addresses they refer to, checked the
+        // way the puzzles require: addresses must point into memory and the
+        // written parameter must not be in immediate mode.
+        let mut raw = [0; 3];
+        let mut addr = [0; 3];
+        let mut failed = None;
+        for k in 0..count {
+            match memory.get(ip + 1 + k) {
+                Some(v) => raw[k] = *v,
+                None => {
+                    failed = Some(Stop::ReadOutOfBounds(ip + 1 + k));
+                    break;
+                }
+            }
+            addr[k] = if modes[k] == 2 {
+                base.wrapping_add(raw[k])
+            } else {
+                raw[k]
+            };
+            if modes[k] != 1 && (addr[k] < 0 || addr[k] as usize >= memory.len()) {
+                failed = Some(Stop::InvalidOp(ip));
+                break;
+            }
+        }
+        if failed.is_none() && writes && modes[count - 1] == 1 {
+            failed = Some(Stop::InvalidOp(ip));
+        }
+        if let Some(stop) = failed {
+            break stop;
+        }
+
+        let value = |k: usize| {
+            if modes[k] == 1 {
+                raw[k]
+            } else {
+                memory[addr[k] as usize]
+            }
+        };
+        let a = value(0);
+        let b = value(1);
+        let dest = addr[count - 1] as usize;
+
+        match opcode {
+            1 => {
+                memory[dest] = a.wrapping_add(b);
+                ip += 4;
+            }
+            2 => {
+                memory[dest] = a.wrapping_mul(b);
+                ip += 4;
+            }
+            3 => match input.next() {
+                Some(v) => {
+                    memory[dest] = *v;
+                    ip += 2;
+                }
+                None => break Stop::MissingInput(ip),
+            },
+            4 => {
+                output.push(a);
+                ip += 2;
+            }
+            5 | 6 => {
+                let jump = if opcode == 5 { a != 0 } else { a == 0 };
+                if !jump {
+                    ip += 3;
+                } else if b < 0 {
+                    break Stop::NegativeJumpTarget(ip);
+                } else {
+                    ip = b as usize;
+                }
+            }
+            7 => {
+                memory[dest] = if a < b { 1 } else { 0 };
+                ip += 4;
+            }
+            8 => {
+                memory[dest] = if a == b { 1 } else { 0 };
+                ip += 4;
+            }
+            9 => {
+                base = base.wrapping_add(a);
+                ip += 2;
+            }
+            _ => unreachable!(),
+        }
+    };
+
+    Outcome { output, stop }
+}
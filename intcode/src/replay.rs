@@ -0,0 +1,296 @@
+//! Recording and replaying the input and output of a run.
+//!
+//! A [`Program`] built with [`with_recording`](Program::with_recording) logs
+//! every value it reads and writes, and when it halts, along with the number
+//! of instructions it had completed at that point. A [`Recording`] is saved as
+//! plain text, one event per line:
+//!
+//! ```text
+//! # intcode recording
+//! in 0 5
+//! out 18 120
+//! in 20 0
+//! halt 22
+//! ```
+//!
+//! [`replay`] runs an image on the recorded input and checks that it
+//! produces the same events at the same steps, so a session found by hand
+//! can be kept as a regression test. Devices are not recorded, so runs that
+//! depend on them can't be replayed.
+
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Error, Program};
+
+const HEADER: &str = "# intcode recording";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// An input instruction read the value.
+    Input {
+        step: u64,
+        value: i64,
+    },
+    /// An output instruction wrote the value.
+    Output {
+        step: u64,
+        value: i64,
+    },
+    Halt {
+        step: u64,
+    },
+}
+
+impl Event {
+    fn step(&self) -> u64 {
+        match self {
+            Event::Input { step, .. } | Event::Output { step, .. } | Event::Halt { step } => *step,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Input { step, value } => write!(f, "in {} {}", step, value),
+            Event::Output { step, value } => write!(f, "out {} {}", step, value),
+            Event::Halt { step } => write!(f, "halt {}", step),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    pub fn new() -> Recording {
+        Recording::default()
+    }
+
+    pub(crate) fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// The values read by input instructions, in order.
+    pub fn inputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.events.iter().filter_map(|e| match e {
+            Event::Input { value, .. } => Some(*value),
+            _ => None,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Recording> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        self.events.iter().try_for_each(|e| writeln!(f, "{}", e))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid recording event on line {}: {}",
+            self.line, self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Recording {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut recording = Recording::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = || ParseError {
+                line: n + 1,
+                text: line.to_string(),
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let number = |i: usize| fields.get(i).and_then(|f| f.parse::<i64>().ok());
+            let step = number(1).filter(|s| *s >= 0).ok_or_else(error)? as u64;
+            let event = match (fields[0], fields.len()) {
+                ("in", 3) => Event::Input {
+                    step,
+                    value: number(2).ok_or_else(error)?,
+                },
+                ("out", 3) => Event::Output {
+                    step,
+                    value: number(2).ok_or_else(error)?,
+                },
+                ("halt", 2) => Event::Halt { step },
+                _ => return Err(error()),
+            };
+            recording.push(event);
+        }
+        Ok(recording)
+    }
+}
+
+/// Where a replay first differed from its recording. `None` stands for the
+/// end of the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Index of the first event that differs.
+    pub index: usize,
+    pub expected: Option<Event>,
+    pub actual: Option<Event>,
+    /// The error the replayed run stopped on, if it didn't halt or run out of
+    /// recorded input.
+    pub error: Option<Error>,
+}
+
+fn describe(event: &Option<Event>) -> String {
+    event.map_or_else(|| "the end of the run".to_string(), |e| format!("`{}`", e))
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "replay diverged at event {}: expected {}, got {}",
+            self.index,
+            describe(&self.expected),
+            describe(&self.actual)
+        )?;
+        if let Some(e) = &self.error {
+            write!(f, " ({})", e)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `memory` on the recorded input, up to the step of the last recorded
+/// event, and compares what happens with `recording`.
+pub fn replay(memory: &mut [i64], recording: &Recording) -> Result<(), Divergence> {
+    let steps = recording.events.last().map_or(0, |e| e.step() + 1);
+    let inputs: Vec<i64> = recording.inputs().collect();
+
+    let mut program = Program::new(memory).with_recording().add_input(&inputs);
+    let error = match program.run_with_limit(steps as usize) {
+        Ok(()) | Err(Error::StepLimitReached(_)) | Err(Error::MissingInput(_)) => None,
+        Err(e) => Some(e),
+    };
+    let actual = &program.recording().unwrap().events;
+    let expected = &recording.events;
+
+    let index =
+        match (0..expected.len().max(actual.len())).find(|i| expected.get(*i) != actual.get(*i)) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+    Err(Divergence {
+        index,
+        expected: expected.get(index).copied(),
+        actual: actual.get(index).copied(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::FACTORIALS;
+
+    fn session(inputs: &[i64]) -> Recording {
+        let mut memory = FACTORIALS.to_vec();
+        let mut remaining = inputs.iter().copied();
+        let mut program = Program::new(&mut memory).with_recording();
+        program
+            .outputs_with_input(|| remaining.next())
+            .for_each(drop);
+        program.recording().unwrap().clone()
+    }
+
+    #[test]
+    fn records_steps_of_every_event() {
+        let recording = session(&[3, 0]);
+        assert_eq!(
+            recording.to_string(),
+            "# intcode recording\n\
+             in 0 3\n\
+             out 12 6\n\
+             in 14 0\n\
+             halt 16\n"
+        );
+    }
+
+    #[test]
+    fn recordings_round_trip_through_text() {
+        let recording = session(&[4, 2, 0]);
+        assert_eq!(recording.to_string().parse(), Ok(recording));
+
+        assert_eq!(
+            "in 0 1\nout x 2\n".parse::<Recording>(),
+            Err(ParseError {
+                line: 2,
+                text: "out x 2".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn replay_matches_the_same_image() {
+        let recording = session(&[5, 3, 0]);
+        assert_eq!(replay(&mut FACTORIALS.to_vec(), &recording), Ok(()));
+
+        // A session that ended waiting for input replays as well
+        let recording = session(&[5, 3]);
+        assert_eq!(replay(&mut FACTORIALS.to_vec(), &recording), Ok(()));
+    }
+
+    #[test]
+    fn replay_flags_the_first_divergence() {
+        let recording = session(&[5, 3, 0]);
+
+        // Start the product at 2 instead of 1
+        let mut changed = FACTORIALS.to_vec();
+        changed[6] = 2;
+        let divergence = replay(&mut changed, &recording).unwrap_err();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(
+            divergence.to_string(),
+            "replay diverged at event 1: expected `out 18 120`, got `out 18 240`"
+        );
+
+        // Halt straight away
+        let mut changed = FACTORIALS.to_vec();
+        changed[0] = 99;
+        let divergence = replay(&mut changed, &recording).unwrap_err();
+        assert_eq!(
+            divergence.expected,
+            Some(Event::Input { step: 0, value: 5 })
+        );
+        assert_eq!(divergence.actual, Some(Event::Halt { step: 0 }));
+    }
+}
@@ -0,0 +1,293 @@
+//! Backing storage for program memory.
+//!
+//! [`Program::new`](crate::Program::new) runs on a borrowed slice, whose size
+//! is fixed: reads and writes outside it are errors. Any other [`Storage`]
+//! can be given to [`Program::with_storage`](crate::Program::with_storage):
+//!
+//! * a `Vec<i64>` grows to cover every address the program writes, and
+//!   reads past its end see zero,
+//! * [`Paged`] only allocates the pages the program touches, so it suits
+//!   programs that use a handful of addresses in the millions or beyond.
+//!
+//! Storage is usually passed as `&mut storage`, so it can be inspected once
+//! the program is done with it.
+
+use std::collections::HashMap;
+
+pub trait Storage {
+    /// Reads the cell at `address`, or `None` if it's outside the storage.
+    fn read(&self, address: usize) -> Option<i64>;
+
+    /// Writes the cell at `address`, which the program has checked with
+    /// [`contains`](#tymethod.contains).
+    fn write(&mut self, address: usize, value: i64);
+
+    fn contains(&self, address: usize) -> bool;
+
+    /// One past the highest address ever written with a value other than
+    /// zero, or the size of fixed storage. Writing zero back doesn't lower
+    /// it.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Storage for [i64] {
+    fn read(&self, address: usize) -> Option<i64> {
+        self.get(address).copied()
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        self[address] = value;
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        address < <[i64]>::len(self)
+    }
+
+    fn len(&self) -> usize {
+        <[i64]>::len(self)
+    }
+}
+
+impl Storage for Vec<i64> {
+    fn read(&self, address: usize) -> Option<i64> {
+        Some(self.get(address).copied().unwrap_or(0))
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        if address >= Vec::len(self) {
+            if value == 0 {
+                return;
+            }
+            self.resize(address + 1, 0);
+        }
+        self[address] = value;
+    }
+
+    fn contains(&self, _: usize) -> bool {
+        true
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &mut S {
+    fn read(&self, address: usize) -> Option<i64> {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        (**self).write(address, value)
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (**self).contains(address)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn read(&self, address: usize) -> Option<i64> {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        (**self).write(address, value)
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (**self).contains(address)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+}
+
+const PAGE_SIZE: usize = 1024;
+
+/// Sparse storage made of fixed size pages, allocated on first write.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Paged {
+    pages: HashMap<usize, Box<[i64; PAGE_SIZE]>>,
+    len: usize,
+}
+
+impl Paged {
+    pub fn new(image: &[i64]) -> Paged {
+        let mut paged = Paged::default();
+        for (address, value) in image.iter().enumerate() {
+            paged.write(address, *value);
+        }
+        paged
+    }
+
+    /// Number of pages allocated.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl Storage for Paged {
+    fn read(&self, address: usize) -> Option<i64> {
+        let value = self
+            .pages
+            .get(&(address / PAGE_SIZE))
+            .map_or(0, |page| page[address % PAGE_SIZE]);
+        Some(value)
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+        let page = match self.pages.get_mut(&(address / PAGE_SIZE)) {
+            Some(page) => page,
+            None if value == 0 => return,
+            None => self
+                .pages
+                .entry(address / PAGE_SIZE)
+                .or_insert_with(|| Box::new([0; PAGE_SIZE])),
+        };
+        page[address % PAGE_SIZE] = value;
+        if value != 0 {
+            self.len = self.len.max(address + 1);
+        }
+    }
+
+    fn contains(&self, _: usize) -> bool {
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, OpError, Program};
+
+    /// Programs every backend must run the same way: the image, input,
+    /// expected output and expected cells afterwards.
+    type Case = (
+        &'static [i64],
+        &'static [i64],
+        &'static [i64],
+        &'static [(usize, i64)],
+    );
+
+    const CASES: [Case; 5] = [
+        (
+            &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50],
+            &[],
+            &[],
+            &[(0, 3500), (3, 70)],
+        ),
+        (&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[8], &[1], &[(9, 1)]),
+        (
+            &[1102, 34915192, 34915192, 7, 4, 7, 99, 0],
+            &[],
+            &[1219070632396864],
+            &[],
+        ),
+        // Reads and writes past the image through the relative base
+        (
+            &[109, 20, 21101, 5, 6, 0, 204, 0, 99],
+            &[],
+            &[11],
+            &[(20, 11)],
+        ),
+        // Self-modifying code
+        (&[1101, 3, 1, 5, 104, 7, 99], &[], &[4], &[(5, 4)]),
+    ];
+
+    fn run_suite<S: Storage>(make: impl Fn(&[i64]) -> S, padding: usize) {
+        for (image, input, output, cells) in CASES.iter() {
+            let mut padded = image.to_vec();
+            padded.resize(image.len() + padding, 0);
+            let mut storage = make(&padded);
+
+            let mut p = Program::with_storage(&mut storage).add_input(input);
+            assert_eq!(p.run_with_limit(1000), Ok(()), "running {:?}", image);
+            assert_eq!(p.output(), *output, "output of {:?}", image);
+            drop(p);
+
+            for (address, value) in cells.iter() {
+                assert_eq!(storage.read(*address), Some(*value), "{:?}", image);
+            }
+        }
+    }
+
+    #[test]
+    fn slice_runs_the_suite() {
+        for cache in [true, false].iter() {
+            for (image, input, output, _) in CASES.iter() {
+                let mut memory = image.to_vec();
+                memory.resize(image.len() + 16, 0);
+                let p = Program::new(&mut memory)
+                    .with_decode_cache(*cache)
+                    .add_input(input)
+                    .run();
+                assert_eq!(p.output(), *output);
+            }
+        }
+        run_suite(|image| image.to_vec().into_boxed_slice(), 16);
+    }
+
+    #[test]
+    fn vec_runs_the_suite() {
+        run_suite(|image| image.to_vec(), 0);
+    }
+
+    #[test]
+    fn paged_runs_the_suite() {
+        run_suite(Paged::new, 0);
+    }
+
+    #[test]
+    fn slice_is_fixed_size() {
+        let mut memory = vec![1101, 1, 2, 10, 99];
+        let mut p = Program::new(&mut memory);
+        assert_eq!(
+            p.run_with_limit(10),
+            Err(Error::InvalidOp(
+                0,
+                OpError::PositionParamOutOfBounds(3, 10)
+            ))
+        );
+    }
+
+    #[test]
+    fn vec_grows_on_write() {
+        let mut memory = vec![1101, 1, 2, 10, 4, 20, 99];
+        let p = Program::with_storage(&mut memory).run();
+        assert_eq!(p.output(), &[0]);
+        drop(p);
+        assert_eq!(memory.len(), 11);
+        assert_eq!(memory[10], 3);
+    }
+
+    #[test]
+    fn paged_handles_huge_addresses() {
+        // Copies a value to an address in the billions and prints it back
+        let image = [1101, 7, 0, 5_000_000_000, 4, 5_000_000_000, 99];
+        let mut storage = Paged::new(&image);
+        let p = Program::with_storage(&mut storage).run();
+        assert_eq!(p.output(), &[7]);
+        drop(p);
+        assert_eq!(storage.read(5_000_000_000), Some(7));
+        assert_eq!(storage.pages(), 2);
+        assert_eq!(storage.len(), 5_000_000_001);
+
+        // Clearing the cell keeps the length
+        storage.write(5_000_000_000, 0);
+        assert_eq!(storage.len(), 5_000_000_001);
+    }
+}
@@ -0,0 +1,276 @@
+//! Taint tracking from input values to outputs and memory.
+//!
+//! A [`Program`](crate::Program) built with
+//! [`with_taint`](crate::Program::with_taint) labels every value it reads
+//! with the index of the input it was, counting from 0, and follows the
+//! labels through the arithmetic and comparison instructions into the cells
+//! they write. A parameter picks up the labels of its own word as well as
+//! those of the cell it points to, so input that ends up in an address, or
+//! in the relative base, taints whatever is read through it.
+//!
+//! Data flow alone misses values that depend on input only through the
+//! branches taken. With control dependencies turned on, the labels of every
+//! jump's condition and target are added to everything the program writes or
+//! outputs from then on. That over-approximates, since nothing tells where
+//! the branches join again, but never misses an input that mattered.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+
+use crate::{Op, OpCode, Param, ParamMode};
+
+/// Indices of the inputs a value depends on.
+pub type Labels = BTreeSet<usize>;
+
+static UNTAINTED: Labels = BTreeSet::new();
+
+/// An output value and the inputs that affected it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintedOutput {
+    pub value: i64,
+    pub inputs: Labels,
+}
+
+impl Display for TaintedOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.inputs.is_empty() {
+            return write!(f, "{} <- no inputs", self.value);
+        }
+        let inputs: Vec<_> = self.inputs.iter().map(|i| i.to_string()).collect();
+        write!(f, "{} <- inputs {}", self.value, inputs.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Taint {
+    control_dependencies: bool,
+    cells: BTreeMap<usize, Labels>,
+    relative_base: Labels,
+    /// Labels of the branches taken so far, with control dependencies on.
+    branches: Labels,
+    inputs: usize,
+    outputs: Vec<TaintedOutput>,
+}
+
+/// The labels an instruction moves, worked out before it runs so that its
+/// own writes don't affect them.
+pub(crate) struct Flow {
+    write: Option<(usize, Labels)>,
+    input: Option<(usize, Labels)>,
+    output: Option<Labels>,
+    relative_base: Option<Labels>,
+    branch: Option<Labels>,
+}
+
+impl Taint {
+    pub fn new(control_dependencies: bool) -> Taint {
+        Taint {
+            control_dependencies,
+            ..Taint::default()
+        }
+    }
+
+    /// The labels of the cell at `address`, empty if it's untainted.
+    pub fn cell(&self, address: usize) -> &Labels {
+        self.cells.get(&address).unwrap_or(&UNTAINTED)
+    }
+
+    /// The tainted cells, by address.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, &Labels)> {
+        self.cells
+            .iter()
+            .map(|(address, labels)| (*address, labels))
+    }
+
+    pub fn relative_base(&self) -> &Labels {
+        &self.relative_base
+    }
+
+    /// Number of input values read, and so labels handed out.
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Every value output so far, in order.
+    pub fn outputs(&self) -> &[TaintedOutput] {
+        &self.outputs
+    }
+
+    /// One line per output with the inputs that affected it.
+    pub fn report(&self) -> String {
+        self.outputs
+            .iter()
+            .enumerate()
+            .map(|(n, output)| format!("output {}: {}\n", n, output))
+            .collect()
+    }
+
+    pub(crate) fn flow(&self, address: usize, opcode: &OpCode, op: &Op) -> Flow {
+        let modes = opcode.modes();
+        // The labels of the address a parameter stands for
+        let target = |n: usize| {
+            let mut labels = self.cell(address + 1 + n).clone();
+            if modes[n] == ParamMode::Relative {
+                labels.extend(&self.relative_base);
+            }
+            labels
+        };
+        let read = |n: usize, param: &Param| {
+            let mut labels = target(n);
+            if let Param::Position(position) = param {
+                labels.extend(self.cell(*position));
+            }
+            labels
+        };
+        let binary = |left: &Param, right: &Param, dest: usize| {
+            let mut labels = read(0, left);
+            labels.extend(read(1, right));
+            labels.extend(target(2));
+            Some((dest, labels))
+        };
+
+        let mut flow = Flow {
+            write: None,
+            input: None,
+            output: None,
+            relative_base: None,
+            branch: None,
+        };
+        match op {
+            Op::Add((left, right, dest))
+            | Op::Mul((left, right, dest))
+            | Op::LessThan((left, right, dest))
+            | Op::Equals((left, right, dest)) => flow.write = binary(left, right, *dest),
+            Op::Input(dest) => flow.input = Some((*dest, target(0))),
+            Op::Output(src) => flow.output = Some(read(0, src)),
+            Op::JumpIfTrue((condition, to)) | Op::JumpIfFalse((condition, to)) => {
+                let mut labels = read(0, condition);
+                labels.extend(read(1, to));
+                flow.branch = Some(labels);
+            }
+            Op::AdjustRelativeBase(value) => {
+                let mut labels = read(0, value);
+                labels.extend(&self.relative_base);
+                flow.relative_base = Some(labels);
+            }
+            Op::Terminate => {}
+        }
+        flow
+    }
+
+    fn set(&mut self, address: usize, labels: Labels) {
+        if labels.is_empty() {
+            self.cells.remove(&address);
+        } else {
+            self.cells.insert(address, labels);
+        }
+    }
+
+    /// Applies the flow of an instruction that completed; `output` is the
+    /// last value output.
+    pub(crate) fn apply(&mut self, flow: Flow, output: Option<i64>) {
+        let control = if self.control_dependencies {
+            self.branches.clone()
+        } else {
+            Labels::new()
+        };
+
+        if let Some((dest, mut labels)) = flow.write {
+            labels.extend(&control);
+            self.set(dest, labels);
+        }
+        if let Some((dest, mut labels)) = flow.input {
+            labels.insert(self.inputs);
+            labels.extend(&control);
+            self.inputs += 1;
+            self.set(dest, labels);
+        }
+        if let (Some(mut labels), Some(value)) = (flow.output, output) {
+            labels.extend(&control);
+            self.outputs.push(TaintedOutput {
+                value,
+                inputs: labels,
+            });
+        }
+        if let Some(mut labels) = flow.relative_base {
+            labels.extend(&control);
+            self.relative_base = labels;
+        }
+        if let Some(labels) = flow.branch {
+            if self.control_dependencies {
+                self.branches.extend(labels);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    fn taint(memory: &[i64], input: &[i64], control_dependencies: bool) -> Taint {
+        let mut memory = memory.to_vec();
+        let program = Program::new(&mut memory)
+            .with_taint(control_dependencies)
+            .add_input(input)
+            .run();
+        program.taint().unwrap().clone()
+    }
+
+    fn labels(inputs: &[usize]) -> Labels {
+        inputs.iter().copied().collect()
+    }
+
+    #[test]
+    fn labels_follow_arithmetic() {
+        // An amplifier from day 7: outputs the signal times 10 plus the phase
+        let amplifier = [
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let t = taint(&amplifier, &[4, 3], false);
+        assert_eq!(t.inputs(), 2);
+        assert_eq!(t.cell(16), &labels(&[1]));
+        assert_eq!(t.cell(15), &labels(&[0, 1]));
+        assert!(t.cell(0).is_empty());
+        assert_eq!(t.report(), "output 0: 34 <- inputs 0, 1\n");
+    }
+
+    #[test]
+    fn control_dependencies_are_optional() {
+        // Outputs the second input if the first is non-zero, otherwise 7
+        let memory = [3, 13, 3, 14, 1006, 13, 10, 4, 14, 99, 104, 7, 99, 0, 0];
+
+        let t = taint(&memory, &[0, 5], false);
+        assert_eq!(t.outputs()[0].to_string(), "7 <- no inputs");
+        let t = taint(&memory, &[0, 5], true);
+        assert_eq!(t.outputs()[0].to_string(), "7 <- inputs 0");
+
+        let t = taint(&memory, &[1, 5], false);
+        assert_eq!(t.outputs()[0].to_string(), "5 <- inputs 1");
+        let t = taint(&memory, &[1, 5], true);
+        assert_eq!(t.outputs()[0].to_string(), "5 <- inputs 0, 1");
+    }
+
+    #[test]
+    fn addresses_carry_labels() {
+        // Patches the input into the address of the output instruction
+        let memory = [3, 9, 1001, 9, 0, 7, 4, 0, 99, 0, 42];
+        let t = taint(&memory, &[10], false);
+        assert_eq!(t.cell(7), &labels(&[0]));
+        assert!(t.cell(10).is_empty());
+        assert_eq!(
+            t.outputs(),
+            &[TaintedOutput {
+                value: 42,
+                inputs: labels(&[0])
+            }]
+        );
+
+        // The same through the relative base
+        let memory = [3, 3, 109, 0, 204, 0, 99, 0, 0, 0, 42];
+        let t = taint(&memory, &[10], false);
+        assert_eq!(t.relative_base(), &labels(&[0]));
+        assert_eq!(t.outputs()[0].inputs, labels(&[0]));
+    }
+}
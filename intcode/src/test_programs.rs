@@ -0,0 +1,7 @@
+//! Programs shared by the tests of several modules.
+
+/// Prints the factorial of each input until it reads a zero.
+pub(crate) const FACTORIALS: [i64; 28] = [
+    3, 27, 1006, 27, 25, 1101, 1, 0, 26, 2, 26, 27, 26, 1001, 27, -1, 27, 1005, 27, 9, 4, 26, 1105,
+    1, 0, 99, 0, 0,
+];
@@ -0,0 +1,264 @@
+//! Memory heatmaps and execution timelines, rendered as images.
+//!
+//! A [`Program`](crate::Program) built with
+//! [`with_trace`](crate::Program::with_trace) counts the reads and writes of
+//! every address made by instruction parameters, and keeps the address of
+//! every instruction it runs. From a [`Trace`]:
+//!
+//! * [`heatmap`](Trace::heatmap) lays memory out in rows, one pixel per
+//!   address, with writes in red, reads in green and executed instructions
+//!   in blue, so loops, tables and self-modifying code stand out,
+//! * [`timeline`](Trace::timeline) plots the instruction pointer (down)
+//!   against the step number (right).
+//!
+//! Both are plain [`Image`]s that can be saved as binary PPM, which most
+//! image viewers open. Intensities are scaled logarithmically to the
+//! largest count.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Addresses from here on are left out of the trace, so that a program
+/// writing far out into sparse storage doesn't allocate counts for every
+/// address in between.
+pub const MAX_ADDRESS: usize = 1 << 24;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+    executed: Vec<u64>,
+    instruction_pointers: Vec<usize>,
+}
+
+fn bump(counts: &mut Vec<u64>, address: usize) {
+    if address >= MAX_ADDRESS {
+        return;
+    }
+    if address >= counts.len() {
+        counts.resize(address + 1, 0);
+    }
+    counts[address] += 1;
+}
+
+fn count(counts: &[u64], address: usize) -> u64 {
+    counts.get(address).copied().unwrap_or(0)
+}
+
+/// Intensity of `count` relative to `max`, on a log scale.
+fn intensity(count: u64, max: u64) -> u8 {
+    if count == 0 {
+        return 0;
+    }
+    let scaled = (count as f64).ln_1p() / (max as f64).ln_1p();
+    // Anything touched at all stays visible
+    (63.0 + 192.0 * scaled).round() as u8
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    pub(crate) fn read(&mut self, address: usize) {
+        bump(&mut self.reads, address);
+    }
+
+    pub(crate) fn write(&mut self, address: usize) {
+        bump(&mut self.writes, address);
+    }
+
+    pub(crate) fn execute(&mut self, address: usize) {
+        bump(&mut self.executed, address);
+        self.instruction_pointers.push(address);
+    }
+
+    pub fn reads(&self, address: usize) -> u64 {
+        count(&self.reads, address)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        count(&self.writes, address)
+    }
+
+    /// Number of instructions run at `address`.
+    pub fn executed(&self, address: usize) -> u64 {
+        count(&self.executed, address)
+    }
+
+    /// The address of every instruction run, in order.
+    pub fn instruction_pointers(&self) -> &[usize] {
+        &self.instruction_pointers
+    }
+
+    /// One past the highest address traced.
+    pub fn len(&self) -> usize {
+        self.reads
+            .len()
+            .max(self.writes.len())
+            .max(self.executed.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Memory from address 0 up to `size`, or the highest address traced if
+    /// that's further, in rows of `width` addresses.
+    pub fn heatmap(&self, size: usize, width: usize) -> Image {
+        let width = width.max(1);
+        let size = size.max(self.len());
+        let mut image = Image::new(width, size.div_ceil(width).max(1));
+
+        let max_reads = self.reads.iter().max().copied().unwrap_or(0);
+        let max_writes = self.writes.iter().max().copied().unwrap_or(0);
+        let max_executed = self.executed.iter().max().copied().unwrap_or(0);
+        for address in 0..size {
+            let pixel = [
+                intensity(self.writes(address), max_writes),
+                intensity(self.reads(address), max_reads),
+                intensity(self.executed(address), max_executed),
+            ];
+            image.set(address % width, address / width, pixel);
+        }
+        image
+    }
+
+    /// The instruction pointer over time. Steps are spread over `width`
+    /// columns and the addresses up to the highest one run over `height`
+    /// rows; a pixel is brighter the more steps fall into it.
+    pub fn timeline(&self, width: usize, height: usize) -> Image {
+        let (width, height) = (width.max(1), height.max(1));
+        let steps = self.instruction_pointers.len().max(1);
+        let addresses = self.executed.len().max(1);
+
+        let mut counts = vec![0u64; width * height];
+        for (step, address) in self.instruction_pointers.iter().enumerate() {
+            let x = step * width / steps;
+            // Instructions past `MAX_ADDRESS` end up in the last row
+            let y = (address * height / addresses).min(height - 1);
+            counts[y * width + x] += 1;
+        }
+
+        let max = counts.iter().max().copied().unwrap_or(0);
+        let mut image = Image::new(width, height);
+        for (i, count) in counts.iter().enumerate() {
+            let level = intensity(*count, max);
+            image.set(i % width, i / width, [level, level, level]);
+        }
+        image
+    }
+}
+
+/// An RGB image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// A black image.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![[0; 3]; width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: [u8; 3]) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+
+    /// The image with every pixel blown up to `factor` by `factor` pixels.
+    pub fn scaled(&self, factor: usize) -> Image {
+        let mut image = Image::new(self.width * factor, self.height * factor);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.set(x, y, self.pixel(x / factor, y / factor));
+            }
+        }
+        image
+    }
+
+    /// The image as a binary (P6) PPM file.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend(self.pixels.iter().flatten());
+        ppm
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_ppm())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    // Counts down from the input, printing each value
+    const COUNTDOWN: [i64; 15] = [3, 14, 4, 14, 1001, 14, -1, 14, 1005, 14, 2, 99, 0, 0, 0];
+
+    fn trace() -> Trace {
+        let mut memory = COUNTDOWN.to_vec();
+        let program = Program::new(&mut memory)
+            .with_trace()
+            .add_input_value(3)
+            .run();
+        program.trace().unwrap().clone()
+    }
+
+    #[test]
+    fn counts_accesses_and_instructions() {
+        let t = trace();
+        // Written by the input and three additions, read by three outputs,
+        // additions and jumps each
+        assert_eq!(t.writes(14), 4);
+        assert_eq!(t.reads(14), 9);
+        assert_eq!(t.reads(0), 0);
+        assert_eq!(t.executed(2), 3);
+        assert_eq!(t.executed(11), 1);
+        assert_eq!(t.instruction_pointers()[..5], [0, 2, 4, 8, 2]);
+        assert_eq!(t.len(), 15);
+    }
+
+    #[test]
+    fn heatmap_colours_accesses() {
+        let image = trace().heatmap(COUNTDOWN.len(), 8);
+        assert_eq!((image.width, image.height), (8, 2));
+        // The counter is the most written and read cell, and never runs
+        assert_eq!(image.pixel(6, 1), [255, 255, 0]);
+        // The output instruction ran three times, as often as any
+        assert_eq!(image.pixel(2, 0), [0, 0, 255]);
+        // The input instruction ran once
+        assert_eq!(image.pixel(0, 0), [0, 0, 63 + 96]);
+        assert_eq!(image.pixel(1, 0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn timeline_follows_the_instruction_pointer() {
+        let t = trace();
+        let steps = t.instruction_pointers().len();
+        let image = t.timeline(steps, 12);
+        assert_eq!(image.pixel(0, 0), [255, 255, 255]);
+        assert_eq!(image.pixel(1, 2), [255, 255, 255]);
+        assert_eq!(image.pixel(1, 0), [0, 0, 0]);
+        assert_eq!(image.pixel(steps - 1, 11), [255, 255, 255]);
+
+        let ppm = image.scaled(2).to_ppm();
+        assert!(ppm.starts_with(b"P6\n"));
+        assert_eq!(
+            ppm.len(),
+            format!("P6\n{} 24\n255\n", steps * 2).len() + steps * 2 * 24 * 3
+        );
+    }
+}
@@ -0,0 +1,501 @@
+//! Ahead-of-time translation of program images into Rust source.
+//!
+//! [`transpile`] follows the control flow of an image from address 0, splits
+//! the reachable code into basic blocks and emits a function that runs each
+//! block as straight-line Rust inside a `match` on the program counter, so
+//! jumps to computed targets still work as long as they land on a block.
+//!
+//! Anything the translation can't be sure of is handed to the interpreter
+//! through [`interpret`], with memory and registers as they are at that
+//! point:
+//!
+//! * jumps to an address that doesn't start a known block,
+//! * instructions that would fail, such as reads outside memory or missing
+//!   input, so the interpreter reports the same [`Error`] as
+//!   [`Program`] would,
+//! * writes into the reachable code, after the write has been made, since
+//!   the translated blocks no longer match memory.
+//!
+//! The generated function embeds the image, takes closures for input and
+//! output and returns the final memory. Devices and profiles other than the
+//! default are not supported.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::{disasm, Error, OpCode, ParamMode, Program};
+
+/// A reachable instruction the translation can handle.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Instr {
+    pub(crate) opcode: OpCode,
+    pub(crate) params: [i64; 3],
+    pub(crate) len: usize,
+}
+
+impl Instr {
+    pub(crate) fn is_jump(&self) -> bool {
+        self.opcode.op == 5 || self.opcode.op == 6
+    }
+
+    /// The jump target, when it is an immediate.
+    pub(crate) fn static_target(&self) -> Option<usize> {
+        let immediate = self.is_jump() && self.opcode.arg1 == ParamMode::Immediate;
+        if immediate && self.params[1] >= 0 {
+            Some(self.params[1] as usize)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn writes(&self) -> bool {
+        matches!(self.opcode.op, 1 | 2 | 3 | 7 | 8)
+    }
+}
+
+/// Decodes the instruction at `address`, or `None` if running it would fail
+/// or the failure can't be ruled out statically.
+pub(crate) fn decode(memory: &[i64], address: usize) -> Option<Instr> {
+    let opcode = OpCode::try_from(*memory.get(address)?).ok()?;
+    let count = opcode.param_count()?;
+    let words = memory.get(address + 1..address + 1 + count)?;
+
+    let mut params = [0; 3];
+    params[..count].copy_from_slice(words);
+    let instr = Instr {
+        opcode,
+        params,
+        len: count + 1,
+    };
+
+    let modes = opcode.modes();
+    for (n, value) in words.iter().enumerate() {
+        if modes[n] == ParamMode::Position && (*value < 0 || *value as usize >= memory.len()) {
+            return None;
+        }
+    }
+    if instr.writes() && modes[count - 1] == ParamMode::Immediate {
+        return None;
+    }
+
+    Some(instr)
+}
+
+/// The reachable code of an image.
+pub(crate) struct Flow {
+    /// Every reachable address, with `None` where the interpreter takes over.
+    pub(crate) instrs: BTreeMap<usize, Option<Instr>>,
+    pub(crate) block_starts: BTreeSet<usize>,
+    /// Addresses of the words of every reachable instruction.
+    pub(crate) code: BTreeSet<usize>,
+}
+
+pub(crate) fn analyse(memory: &[i64]) -> Flow {
+    let mut flow = Flow {
+        instrs: BTreeMap::new(),
+        block_starts: BTreeSet::new(),
+        code: BTreeSet::new(),
+    };
+    flow.block_starts.insert(0);
+
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+    while let Some(address) = queue.pop_front() {
+        if flow.instrs.contains_key(&address) {
+            continue;
+        }
+        let instr = decode(memory, address);
+        flow.instrs.insert(address, instr);
+
+        let instr = match instr {
+            Some(instr) => instr,
+            None => continue,
+        };
+        flow.code.extend(address..address + instr.len);
+        if instr.opcode.op == 99 {
+            continue;
+        }
+
+        let next = address + instr.len;
+        queue.push_back(next);
+        if instr.is_jump() {
+            flow.block_starts.insert(next);
+            if let Some(target) = instr.static_target() {
+                flow.block_starts.insert(target);
+                queue.push_back(target);
+            }
+        }
+    }
+
+    flow
+}
+
+fn literal(value: i64) -> String {
+    if value == i64::MIN {
+        "i64::MIN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Source for one translated block, indented to sit inside a match arm.
+struct Block<'a> {
+    flow: &'a Flow,
+    out: String,
+}
+
+impl Block<'_> {
+    fn line(&mut self, text: &str) {
+        writeln!(self.out, "                {}", text).unwrap();
+    }
+
+    /// Hands over to the interpreter at `address`.
+    fn bail(&self, address: usize) -> String {
+        format!("{{ pc = {}; break; }}", address)
+    }
+
+    /// Emits the address computation for parameter `n` if it is relative,
+    /// and returns the expression for the cell it refers to.
+    fn cell(&mut self, address: usize, instr: &Instr, n: usize) -> Option<String> {
+        let value = instr.params[n];
+        match instr.opcode.modes()[n] {
+            ParamMode::Immediate => None,
+            ParamMode::Position => Some(format!("m[{}]", value)),
+            ParamMode::Relative => {
+                let bail = self.bail(address);
+                self.line(&format!(
+                    "let a{} = rb.wrapping_add({});",
+                    n,
+                    literal(value)
+                ));
+                self.line(&format!(
+                    "if a{0} < 0 || a{0} as usize >= m.len() {1}",
+                    n, bail
+                ));
+                Some(format!("m[a{} as usize]", n))
+            }
+        }
+    }
+
+    fn read(&mut self, address: usize, instr: &Instr, n: usize) -> String {
+        self.cell(address, instr, n)
+            .unwrap_or_else(|| literal(instr.params[n]))
+    }
+
+    /// Emits `instr`; returns whether the block carries on after it.
+    fn instr(&mut self, address: usize, instr: &Instr) -> bool {
+        let next = address + instr.len;
+        let op = instr.opcode.op;
+        match op {
+            1 | 2 | 7 | 8 => {
+                let left = self.read(address, instr, 0);
+                let right = self.read(address, instr, 1);
+                let target = self.cell(address, instr, 2).unwrap();
+                let value = match op {
+                    1 => format!("i64::wrapping_add({}, {})", left, right),
+                    2 => format!("i64::wrapping_mul({}, {})", left, right),
+                    7 => format!("({} < {}) as i64", left, right),
+                    _ => format!("({} == {}) as i64", left, right),
+                };
+                self.line(&format!("{} = {};", target, value));
+                self.check_write(instr, 2, next)
+            }
+            3 => {
+                let target = self.cell(address, instr, 0).unwrap();
+                let bail = self.bail(address);
+                self.line(&format!(
+                    "{} = match input() {{ Some(v) => v, None => {} }};",
+                    target, bail
+                ));
+                self.check_write(instr, 0, next)
+            }
+            4 => {
+                let value = self.read(address, instr, 0);
+                self.line(&format!("output({});", value));
+                true
+            }
+            5 | 6 => {
+                let condition = self.read(address, instr, 0);
+                let test = if op == 5 { "!=" } else { "==" };
+                match instr.static_target() {
+                    Some(target) => self.line(&format!(
+                        "pc = if {} {} 0 {{ {} }} else {{ {} }};",
+                        condition, test, target, next
+                    )),
+                    None if instr.opcode.arg1 == ParamMode::Immediate => self.line(&format!(
+                        "if {} {} 0 {} else {{ pc = {}; }}",
+                        condition,
+                        test,
+                        self.bail(address),
+                        next
+                    )),
+                    None => {
+                        let target = self.read(address, instr, 1);
+                        self.line(&format!("if {} {} 0 {{", condition, test));
+                        self.line(&format!("    if {} < 0 {}", target, self.bail(address)));
+                        self.line(&format!("    pc = {} as usize;", target));
+                        self.line("} else {");
+                        self.line(&format!("    pc = {};", next));
+                        self.line("}");
+                    }
+                }
+                false
+            }
+            9 => {
+                let value = self.read(address, instr, 0);
+                self.line(&format!("rb = rb.wrapping_add({});", value));
+                true
+            }
+            _ => {
+                self.line("return Ok(m);");
+                false
+            }
+        }
+    }
+
+    /// Hands over to the interpreter after a write that may have changed
+    /// reachable code; returns whether the block carries on.
+    fn check_write(&mut self, instr: &Instr, n: usize, next: usize) -> bool {
+        match instr.opcode.modes()[n] {
+            ParamMode::Relative => {
+                let bail = self.bail(next);
+                self.line(&format!("if is_code(a{} as usize) {}", n, bail));
+                true
+            }
+            _ if self.flow.code.contains(&(instr.params[n] as usize)) => {
+                self.line(&format!("pc = {};", next));
+                self.line("break;");
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Ranges of consecutive addresses, for the generated `is_code` check.
+fn ranges(addresses: &BTreeSet<usize>) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &a in addresses {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == a => *end = a,
+            _ => ranges.push((a, a)),
+        }
+    }
+    ranges
+}
+
+/// Translates `memory` into the source of a Rust function called `name`:
+///
+/// ```text
+/// pub fn name(
+///     input: &mut dyn FnMut() -> Option<i64>,
+///     output: &mut dyn FnMut(i64),
+/// ) -> Result<Vec<i64>, intcode::Error>
+/// ```
+///
+/// Running it gives the same output, final memory and error as running the
+/// image with [`Program`], calling `input` whenever an input instruction
+/// runs. If `input` returns `None` the function fails with
+/// [`Error::MissingInput`].
+pub fn transpile(memory: &[i64], name: &str) -> String {
+    let flow = analyse(memory);
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "/// Translated from a {} word Intcode image by `intcode::transpile`.",
+        memory.len()
+    )
+    .unwrap();
+    out.push_str("#[allow(unused_mut, unused_variables, unreachable_code, dead_code)]\n");
+    out.push_str("#[allow(clippy::all)]\n");
+    writeln!(out, "pub fn {}(", name).unwrap();
+    out.push_str("    input: &mut dyn FnMut() -> Option<i64>,\n");
+    out.push_str("    output: &mut dyn FnMut(i64),\n");
+    out.push_str(") -> Result<Vec<i64>, intcode::Error> {\n");
+
+    let code: Vec<String> = ranges(&flow.code)
+        .iter()
+        .map(|(start, end)| format!("{}..={}", start, end))
+        .collect();
+    if code.is_empty() {
+        out.push_str("    fn is_code(_: usize) -> bool {\n        false\n    }\n\n");
+    } else {
+        writeln!(
+            out,
+            "    fn is_code(a: usize) -> bool {{\n        matches!(a, {})\n    }}\n",
+            code.join(" | ")
+        )
+        .unwrap();
+    }
+
+    out.push_str("    let mut m: Vec<i64> = vec![\n");
+    for chunk in memory.chunks(16) {
+        let words: Vec<String> = chunk.iter().map(|v| literal(*v)).collect();
+        writeln!(out, "        {},", words.join(", ")).unwrap();
+    }
+    out.push_str("    ];\n");
+    out.push_str("    let mut pc: usize = 0;\n");
+    out.push_str("    let mut rb: i64 = 0;\n");
+    out.push_str("    loop {\n");
+    out.push_str("        match pc {\n");
+
+    for &start in &flow.block_starts {
+        let mut block = Block {
+            flow: &flow,
+            out: String::new(),
+        };
+        let mut address = start;
+        loop {
+            let instr = match flow.instrs.get(&address) {
+                Some(Some(instr)) => *instr,
+                // Left to the interpreter, from this instruction on
+                _ => {
+                    if address != start {
+                        block.line(&format!("pc = {};", address));
+                    }
+                    block.line("break;");
+                    break;
+                }
+            };
+            if let Some(line) = disasm::decode(memory, address) {
+                block.line(&format!("// {}: {}", address, line.text));
+            }
+            if !block.instr(address, &instr) {
+                break;
+            }
+            address += instr.len;
+            if flow.block_starts.contains(&address) {
+                block.line(&format!("pc = {};", address));
+                break;
+            }
+        }
+
+        writeln!(out, "            {} => {{", start).unwrap();
+        out.push_str(&block.out);
+        out.push_str("            }\n");
+    }
+
+    out.push_str("            _ => break,\n");
+    out.push_str("        }\n");
+    out.push_str("    }\n");
+    out.push_str("    intcode::transpile::interpret(&mut m, pc, rb, input, output)?;\n");
+    out.push_str("    Ok(m)\n");
+    out.push_str("}\n");
+    out
+}
+
+/// Runs `memory` on the interpreter from `address` with the given relative
+/// base, taking input from and passing output to the closures. Translated
+/// code calls this for anything it doesn't handle itself.
+pub fn interpret(
+    memory: &mut [i64],
+    address: usize,
+    relative_base: i64,
+    input: &mut dyn FnMut() -> Option<i64>,
+    output: &mut dyn FnMut(i64),
+) -> Result<(), Error> {
+    let mut program = Program::new(memory);
+    program.next_op = address;
+    program.relative_base = relative_base;
+
+    let mut written = 0;
+    loop {
+        let result = program.run_with_limit(usize::MAX);
+        program.output[written..].iter().for_each(|v| output(*v));
+        written = program.output.len();
+
+        match result {
+            Err(Error::MissingInput(at)) => match input() {
+                Some(value) => program.input.push_back(value),
+                None => return Err(Error::MissingInput(at)),
+            },
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_split_at_jumps_and_targets() {
+        // Counts down from 3, printing each value
+        let memory = [1101, 0, 3, 13, 4, 13, 1001, 13, -1, 13, 1005, 13, 4, 0, 99];
+        let flow = analyse(&memory);
+        assert_eq!(flow.block_starts, [0, 4, 13].iter().copied().collect());
+        // The cell at 13 is data, it only decodes as an invalid instruction
+        assert!(flow.instrs[&13].is_none());
+        assert_eq!(ranges(&flow.code), vec![(0, 12)]);
+    }
+
+    #[test]
+    fn failing_instructions_are_left_to_the_interpreter() {
+        assert!(decode(&[1, 0, 0, 100], 0).is_none());
+        assert!(decode(&[1101, 0, 0, 3], 0).is_some());
+        assert!(decode(&[11101, 0, 0, 3], 0).is_none());
+        assert!(decode(&[1, 0, 0], 0).is_none());
+        assert!(decode(&[42], 0).is_none());
+    }
+
+    #[test]
+    fn interpret_resumes_with_registers() {
+        // IN [rb+0], OUT [rb+0], HALT, then data
+        let mut memory = vec![203, 0, 204, 0, 99, 0, 0];
+        let mut input = vec![7];
+        let mut output = Vec::new();
+        let result = interpret(&mut memory, 0, 6, &mut || input.pop(), &mut |v| {
+            output.push(v)
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, vec![7]);
+        assert_eq!(memory[6], 7);
+
+        let result = interpret(&mut memory, 0, 6, &mut || None, &mut |_| {});
+        assert_eq!(result, Err(Error::MissingInput(0)));
+    }
+
+    fn parse(image: &str) -> Vec<i64> {
+        image
+            .trim()
+            .split(',')
+            .map(|v| v.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn generated_sources_are_up_to_date() {
+        let examples = [
+            (
+                "compare",
+                include_str!("../tests/transpiled/compare.txt"),
+                include_str!("../tests/transpiled/compare.rs"),
+            ),
+            (
+                "fib",
+                include_str!("../tests/transpiled/fib.txt"),
+                include_str!("../tests/transpiled/fib.rs"),
+            ),
+            (
+                "self_modifying",
+                include_str!("../tests/transpiled/self_modifying.txt"),
+                include_str!("../tests/transpiled/self_modifying.rs"),
+            ),
+            (
+                "undecodable",
+                include_str!("../tests/transpiled/undecodable.txt"),
+                include_str!("../tests/transpiled/undecodable.rs"),
+            ),
+        ];
+        for (name, image, expected) in examples.iter() {
+            assert!(
+                transpile(&parse(image), name) == *expected,
+                "tests/transpiled/{0}.rs is out of date, regenerate it with `cargo run \\
+                 --example transpile -- tests/transpiled/{0}.txt {0} > tests/transpiled/{0}.rs`",
+                name
+            );
+        }
+    }
+}
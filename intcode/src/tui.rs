@@ -0,0 +1,574 @@
+//! A terminal UI for stepping through programs.
+//!
+//! A [`Session`] wraps a [`Debugger`] with what the UI shows around it: a
+//! disassembly that follows the instruction pointer, with a cursor to set
+//! breakpoints and run to, and a memory pane that highlights the cells the
+//! last command changed. [`Session::render`] draws it all as a full screen
+//! of ANSI escape sequences; the `intcode-tui` binary puts the terminal in
+//! raw mode and maps keys to [`Command`]s.
+//!
+//! Where there is no terminal, [`run_lines`] reads one command per line and
+//! prints what changed after each, so sessions can be scripted:
+//!
+//! * `s [n]` or `step [n]` runs `n` instructions, 1 by default,
+//! * `c` or `continue` runs to the next breakpoint,
+//! * `r <address>` or `run <address>` runs to an address,
+//! * `b <address>` or `break <address>` sets or clears a breakpoint,
+//! * `i <values>` or `input <values>` queues input,
+//! * `m <address>` or `memory <address>` shows memory from an address,
+//! * `dump` prints all of memory as a [dump](crate::dump::dump),
+//! * `d` or `diff` lists the cells changed since the program started,
+//! * `q` or `quit` ends the session.
+//!
+//! Running commands give up after [`RUN_LIMIT`] instructions, so that a
+//! program stuck in a loop hands control back.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::debugger::{Debugger, Stop};
+use crate::disasm::{self, Line};
+use crate::dump;
+
+/// Most instructions a single command runs.
+pub const RUN_LIMIT: usize = 1_000_000;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const REVERSE: &str = "\x1b[7m";
+const CHANGED: &str = "\x1b[1;33m";
+
+/// Smallest screen drawn; larger ones get larger panes.
+const MIN_WIDTH: usize = 40;
+const MIN_HEIGHT: usize = 10;
+
+/// Width of a memory cell, with the space before it.
+const CELL: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(usize),
+    Continue,
+    RunTo(usize),
+    ToggleBreakpoint(usize),
+    Input(Vec<i64>),
+    ShowMemory(usize),
+    Dump,
+    Diff,
+    Quit,
+}
+
+impl Command {
+    /// Whether the command runs the program.
+    pub fn runs(&self) -> bool {
+        matches!(
+            self,
+            Command::Step(_) | Command::Continue | Command::RunTo(_)
+        )
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        let address = || match args[..] {
+            [address] => address
+                .parse()
+                .map_err(|_| format!("invalid address {:?}", address)),
+            _ => Err(format!("`{}` takes an address", name)),
+        };
+
+        match name {
+            "s" | "step" => match args[..] {
+                [] => Ok(Command::Step(1)),
+                [n] => n
+                    .parse()
+                    .map(Command::Step)
+                    .map_err(|_| format!("invalid count {:?}", n)),
+                _ => Err("`step` takes at most a count".to_string()),
+            },
+            "c" | "continue" => Ok(Command::Continue),
+            "r" | "run" => address().map(Command::RunTo),
+            "b" | "break" => address().map(Command::ToggleBreakpoint),
+            "m" | "memory" => address().map(Command::ShowMemory),
+            "dump" => Ok(Command::Dump),
+            "d" | "diff" => Ok(Command::Diff),
+            "i" | "input" => args
+                .iter()
+                .flat_map(|a| a.split(','))
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse().map_err(|_| format!("invalid input {:?}", v)))
+                .collect::<Result<_, _>>()
+                .map(Command::Input),
+            "q" | "quit" => Ok(Command::Quit),
+            _ => Err(format!("unknown command {:?}", line.trim())),
+        }
+    }
+}
+
+pub struct Session {
+    debugger: Debugger,
+    name: String,
+    /// Memory as it was before the last command.
+    previous: Vec<i64>,
+    /// Number of values output before the last command.
+    printed: usize,
+    /// Address of the disassembly line under the cursor.
+    cursor: usize,
+    /// First address of the memory pane.
+    memory_start: usize,
+    status: String,
+}
+
+/// Truncates or pads `text` to `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut text: String = text.chars().take(width).collect();
+    let len = text.chars().count();
+    text.extend(std::iter::repeat_n(' ', width - len));
+    text
+}
+
+/// Width of the disassembly pane on a screen `width` characters wide.
+fn left_pane(width: usize) -> usize {
+    width * 2 / 5
+}
+
+/// Number of cells in each row of the memory pane on a screen `width`
+/// characters wide.
+pub fn cells_per_row(width: usize) -> usize {
+    let width = width.max(MIN_WIDTH);
+    let right = width - left_pane(width) - 1;
+    (right.saturating_sub(7) / CELL).max(1)
+}
+
+fn join(values: &[i64]) -> String {
+    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+    values.join(", ")
+}
+
+impl Session {
+    pub fn new(name: &str, image: &[i64], input: &[i64]) -> Session {
+        let mut debugger = Debugger::new(image);
+        debugger.add_input(input);
+        Session {
+            debugger,
+            name: name.to_string(),
+            previous: image.to_vec(),
+            printed: 0,
+            cursor: 0,
+            memory_start: 0,
+            status: "stopped on entry".to_string(),
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// What the last command did.
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Replaces the status, for example with an error from the front end.
+    pub fn set_status(&mut self, status: String) {
+        self.status = status;
+    }
+
+    fn lines(&self, memory: &[i64]) -> Vec<Line> {
+        disasm::disassemble_at(memory, self.debugger.program().instruction_pointer())
+    }
+
+    /// Moves the cursor by `delta` lines of the disassembly.
+    pub fn move_cursor(&mut self, delta: isize) {
+        let lines = self.lines(&self.debugger.memory());
+        if lines.is_empty() {
+            return;
+        }
+        let i = lines.partition_point(|l| l.address + l.len <= self.cursor);
+        let i = (i as isize + delta).clamp(0, lines.len() as isize - 1);
+        self.cursor = lines[i as usize].address;
+    }
+
+    /// Scrolls the memory pane by `delta` cells.
+    pub fn scroll_memory(&mut self, delta: isize) {
+        let size = self.debugger.program().memory().len();
+        let start = (self.memory_start as isize + delta).max(0) as usize;
+        self.memory_start = start.min(size.saturating_sub(1));
+    }
+
+    pub fn execute(&mut self, command: &Command) {
+        if command.runs() {
+            self.previous = self.debugger.memory();
+            self.printed = self.debugger.output().len();
+            if self.debugger.program().is_halted() {
+                self.status = "the program has halted".to_string();
+                return;
+            }
+        }
+
+        self.status = match command {
+            Command::Step(n) => {
+                let mut stop = None;
+                for _ in 0..*n {
+                    stop = self.debugger.step();
+                    if stop.is_some() {
+                        break;
+                    }
+                }
+                self.describe(stop, "stepped")
+            }
+            Command::Continue => {
+                let stop = self.debugger.run(RUN_LIMIT);
+                self.describe(stop, "still running")
+            }
+            Command::RunTo(address) => {
+                let stop = self.debugger.run_to(*address, RUN_LIMIT);
+                self.describe(stop, "still running")
+            }
+            Command::ToggleBreakpoint(address) => {
+                if self.debugger.toggle_breakpoint(*address) {
+                    format!("breakpoint set at {}", address)
+                } else {
+                    format!("breakpoint cleared at {}", address)
+                }
+            }
+            Command::Input(values) => {
+                self.debugger.add_input(values);
+                format!("queued {} input values", values.len())
+            }
+            Command::ShowMemory(address) => {
+                self.memory_start = 0;
+                self.scroll_memory(*address as isize);
+                format!("memory from {}", self.memory_start)
+            }
+            Command::Dump => format!("{} cells of memory", self.debugger.memory().len()),
+            Command::Diff => {
+                let changes = dump::diff(self.debugger.image(), &self.debugger.memory());
+                format!("{} cells changed since the start", changes.len())
+            }
+            Command::Quit => String::new(),
+        };
+        if command.runs() {
+            self.cursor = self.debugger.program().instruction_pointer();
+        }
+    }
+
+    fn describe(&self, stop: Option<Stop>, running: &str) -> String {
+        let ip = self.debugger.program().instruction_pointer();
+        match stop {
+            None => running.to_string(),
+            Some(Stop::Breakpoint) => format!("breakpoint at {}", ip),
+            Some(Stop::Reached) => format!("reached {}", ip),
+            Some(Stop::Failed(e)) => format!("error: {}", e),
+            Some(Stop::Halted) => "halted".to_string(),
+        }
+    }
+
+    /// The addresses the last command changed.
+    pub fn changed(&self) -> Vec<usize> {
+        let memory = self.debugger.memory();
+        (0..memory.len())
+            .filter(|a| self.previous.get(*a) != memory.get(*a))
+            .collect()
+    }
+
+    /// The values output by the last command.
+    pub fn new_output(&self) -> &[i64] {
+        &self.debugger.output()[self.printed.min(self.debugger.output().len())..]
+    }
+
+    fn registers(&self) -> String {
+        let program = self.debugger.program();
+        format!(
+            "ip {}  rb {}  steps {}",
+            program.instruction_pointer(),
+            program.relative_base(),
+            program.steps()
+        )
+    }
+
+    /// A report of the last command, for the line-based mode.
+    pub fn report(&self) -> String {
+        let memory = self.debugger.memory();
+        let ip = self.debugger.program().instruction_pointer();
+        let instruction = disasm::decode(&memory, ip).map_or("???".to_string(), |l| l.text);
+
+        let mut report = format!("{}\n{}: {}\n", self.status, self.registers(), instruction);
+        let changes: Vec<_> = self
+            .changed()
+            .iter()
+            .map(|a| format!("[{}] {} -> {}", a, self.previous[*a], memory[*a]))
+            .collect();
+        if !changes.is_empty() {
+            writeln!(report, "changed {}", changes.join(", ")).unwrap();
+        }
+        if !self.new_output().is_empty() {
+            writeln!(report, "output {}", join(self.new_output())).unwrap();
+        }
+        report
+    }
+
+    /// Memory from the start of the memory pane, `per_row` cells to a row.
+    fn memory_rows(
+        &self,
+        memory: &[i64],
+        rows: usize,
+        per_row: usize,
+        styled: bool,
+    ) -> Vec<String> {
+        let changed = self.changed();
+        (0..rows)
+            .map(|row| {
+                let start = self.memory_start + row * per_row;
+                if start >= memory.len() {
+                    return String::new();
+                }
+                let mut text = format!("{:>6}:", start);
+                let end = (start + per_row).min(memory.len());
+                for (address, value) in memory[start..end].iter().enumerate() {
+                    let address = start + address;
+                    let cell = format!("{:>1$}", value, CELL);
+                    if styled && changed.binary_search(&address).is_ok() {
+                        write!(text, "{}{}{}", CHANGED, cell, RESET).unwrap();
+                    } else {
+                        text.push_str(&cell);
+                    }
+                }
+                text
+            })
+            .collect()
+    }
+
+    /// The memory pane as plain text, for the line-based mode.
+    pub fn memory_pane(&self, rows: usize) -> String {
+        let memory = self.debugger.memory();
+        self.memory_rows(&memory, rows, 8, false)
+            .into_iter()
+            .filter(|row| !row.is_empty())
+            .map(|row| row + "\n")
+            .collect()
+    }
+
+    /// A full screen of `width` by `height` characters. `prompt` replaces
+    /// the key help on the last line while input is being typed.
+    pub fn render(&self, width: usize, height: usize, prompt: Option<&str>) -> String {
+        let (width, height) = (width.max(MIN_WIDTH), height.max(MIN_HEIGHT));
+        let memory = self.debugger.memory();
+        let program = self.debugger.program();
+        let ip = program.instruction_pointer();
+        let breakpoints = self.debugger.breakpoints();
+
+        let mut screen = String::from("\x1b[H");
+        let mut line = |text: &str, style: &str| {
+            if style.is_empty() {
+                screen.push_str(text);
+            } else {
+                write!(screen, "{}{}{}", style, text, RESET).unwrap();
+            }
+            screen.push_str("\x1b[K\r\n");
+        };
+
+        let title = format!(" {}  {}  {}", self.name, self.registers(), self.status);
+        line(&fit(&title, width), REVERSE);
+
+        // Disassembly on the left, memory on the right
+        let pane_height = height - 5;
+        let left = left_pane(width);
+        let lines = self.lines(&memory);
+        let current = lines.partition_point(|l| l.address + l.len <= self.cursor);
+        let first = current
+            .saturating_sub(pane_height / 2)
+            .min(lines.len().saturating_sub(pane_height));
+        let rows = self.memory_rows(&memory, pane_height, cells_per_row(width), true);
+        for (n, row) in rows.iter().enumerate() {
+            let mut text = match lines.get(first + n) {
+                Some(l) => {
+                    let marker = if l.address == ip { '>' } else { ' ' };
+                    let breakpoint = if breakpoints.contains(&l.address) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    let text = format!("{}{}{:>6}  {}", marker, breakpoint, l.address, l.text);
+                    let text = fit(&text, left);
+                    match (first + n == current, l.address == ip) {
+                        (true, _) => format!("{}{}{}", REVERSE, text, RESET),
+                        (false, true) => format!("{}{}{}", BOLD, text, RESET),
+                        _ => text,
+                    }
+                }
+                None => fit("", left),
+            };
+            text.push('|');
+            text.push_str(row);
+            line(&text, "");
+        }
+
+        let input: Vec<_> = program.pending_input().iter().copied().collect();
+        let output = self.debugger.output();
+        let shown = output.len().min(width / 4);
+        let breakpoints: Vec<_> = breakpoints.iter().map(|b| b.to_string()).collect();
+        line(&fit(&format!("input:  {}", join(&input)), width), "");
+        line(
+            &fit(
+                &format!("output: {}", join(&output[output.len() - shown..])),
+                width,
+            ),
+            "",
+        );
+        line(
+            &fit(&format!("breakpoints: {}", breakpoints.join(", ")), width),
+            "",
+        );
+        let help = match prompt {
+            Some(prompt) => format!("input> {}", prompt),
+            None => "s:step c:continue r:run to cursor b:break j/k:move [/]:memory \
+                     d:diff i:input q:quit"
+                .to_string(),
+        };
+        screen.push_str(&fit(&help, width));
+        screen.push_str("\x1b[K");
+        screen
+    }
+}
+
+/// Runs a session reading one command per line from `input`, until `quit`
+/// or the end of `input`, and writes a report after each to `output`.
+pub fn run_lines<R: BufRead, W: Write>(
+    session: &mut Session,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    write!(output, "{}", session.report())?;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<Command>() {
+            Ok(Command::Quit) => break,
+            Ok(command @ Command::ShowMemory(_)) => {
+                session.execute(&command);
+                write!(output, "{}", session.memory_pane(8))?;
+            }
+            Ok(command @ Command::Dump) | Ok(command @ Command::Diff) => {
+                session.execute(&command);
+                writeln!(output, "{}", session.status())?;
+                let text = match command {
+                    Command::Dump => session.debugger().dump(),
+                    _ => session.debugger().diff(),
+                };
+                write!(output, "{}", text)?;
+            }
+            Ok(command) => {
+                session.execute(&command);
+                if command.runs() {
+                    write!(output, "{}", session.report())?;
+                } else {
+                    writeln!(output, "{}", session.status())?;
+                }
+            }
+            Err(e) => writeln!(output, "{}", e)?,
+        }
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::FACTORIALS;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!("s".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 5".parse(), Ok(Command::Step(5)));
+        assert_eq!(" c ".parse(), Ok(Command::Continue));
+        assert_eq!("r 17".parse(), Ok(Command::RunTo(17)));
+        assert_eq!("break 4".parse(), Ok(Command::ToggleBreakpoint(4)));
+        assert_eq!("i 1, 2 -3".parse(), Ok(Command::Input(vec![1, 2, -3])));
+        assert_eq!("dump".parse(), Ok(Command::Dump));
+        assert_eq!("d".parse(), Ok(Command::Diff));
+        assert_eq!(
+            "b".parse::<Command>(),
+            Err("`b` takes an address".to_string())
+        );
+        assert_eq!(
+            "jump 3".parse::<Command>(),
+            Err("unknown command \"jump 3\"".to_string())
+        );
+    }
+
+    #[test]
+    fn line_mode_reports_each_command() {
+        let mut session = Session::new("factorials", &FACTORIALS, &[3]);
+        let script = "b 17\nc\nb 17\nr 20\nm 24\ndiff\nx\nc\ni 0\nc\ns\n";
+        let mut output = Vec::new();
+        run_lines(&mut session, script.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "stopped on entry\n\
+             ip 0  rb 0  steps 0: IN [27]\n\
+             breakpoint set at 17\n\
+             breakpoint at 17\n\
+             ip 17  rb 0  steps 5: JNZ [27], 9\n\
+             changed [26] 0 -> 3, [27] 0 -> 2\n\
+             breakpoint cleared at 17\n\
+             reached 20\n\
+             ip 20  rb 0  steps 12: OUT [26]\n\
+             changed [26] 3 -> 6, [27] 2 -> 0\n\
+             \x20   24:       0      99       6       0\n\
+             1 cells changed since the start\n\
+             \x20   26:            0 -> 6            DATA 0\n\
+             unknown command \"x\"\n\
+             error: expected input at 0, but it's empty\n\
+             ip 0  rb 0  steps 14: IN [27]\n\
+             output 6\n\
+             queued 1 input values\n\
+             halted\n\
+             ip 25  rb 0  steps 17: HALT\n\
+             the program has halted\n\
+             ip 25  rb 0  steps 17: HALT\n"
+        );
+    }
+
+    #[test]
+    fn renders_panes_with_highlights() {
+        let mut session = Session::new("factorials", &FACTORIALS, &[3]);
+        session.execute(&Command::ToggleBreakpoint(9));
+        session.execute(&Command::Step(2));
+        assert_eq!(session.cursor(), 5);
+        session.move_cursor(1);
+        assert_eq!(session.cursor(), 9);
+
+        let screen = session.render(80, 12, None);
+        let rows: Vec<&str> = screen.split("\x1b[K\r\n").collect();
+        assert_eq!(rows.len(), 12);
+        assert!(rows[0].starts_with("\x1b[H\x1b[7m factorials  ip 5  rb 0  steps 2  stepped"));
+        // The instruction pointer in bold, the cursor in reverse video
+        assert!(rows[3].starts_with(&format!("{}>      5  ADD 1, 0, [26]", BOLD)));
+        assert!(rows[4].starts_with(&format!("{} *     9  MUL [26], [27], [26]", REVERSE)));
+        // Only the input written to [27] changed
+        assert!(rows[6].ends_with(&format!(
+            "|    25:      99       0{}       3{}",
+            CHANGED, RESET
+        )));
+        assert!(!rows[1].contains(CHANGED));
+        assert!(rows[8].starts_with("input:  "));
+        assert!(rows[9].starts_with("output: "));
+        assert!(rows[10].starts_with("breakpoints: 9"));
+
+        let screen = session.render(80, 12, Some("4 5"));
+        assert!(screen.ends_with(&format!("{}\x1b[K", fit("input> 4 5", 80))));
+    }
+}
@@ -0,0 +1,339 @@
+//! Static verification of program images.
+//!
+//! [`verify`] decodes every instruction that is reachable from address 0
+//! without running anything, with the same decoding as a running
+//! [`Program`], and reports what would make it fail once reached. Jumps with
+//! an immediate target are followed; jumps whose target is read from memory
+//! are flagged, since the code they lead to can't be checked.
+//!
+//! Relative mode parameters depend on the relative base at run time, so
+//! their bounds are not checked. Errors in instructions that other reachable
+//! code writes to are only warnings, since the program may patch them before
+//! they run.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::{self, Display};
+
+use crate::{Decoded, Error, Op, OpError, Param, ParamMode, Profile, Program};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Something that might be intended but can't be checked.
+    Warning,
+    /// The program fails if it reaches the instruction.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// Decoding the instruction fails with the error.
+    Invalid(OpError),
+    /// The instruction runs past the end of memory, or execution does.
+    Truncated,
+    /// A jump to a negative immediate target.
+    NegativeJumpTarget(i64),
+    /// A jump whose target is read from memory.
+    DynamicJump,
+    /// A write to a word of another reachable instruction.
+    WritesCode(usize),
+}
+
+impl Problem {
+    fn severity(&self) -> Severity {
+        match self {
+            Problem::DynamicJump | Problem::WritesCode(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Invalid(e) => write!(f, "{}", e),
+            Problem::Truncated => write!(f, "runs past the end of memory"),
+            Problem::NegativeJumpTarget(target) => write!(f, "jumps to negative target {}", target),
+            Problem::DynamicJump => write!(f, "jump target is only known at run time"),
+            Problem::WritesCode(target) => {
+                write!(f, "writes to the instruction word at {}", target)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// Address of the instruction the finding is about.
+    pub address: usize,
+    pub severity: Severity,
+    pub problem: Problem,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}: {}", self.severity, self.address, self.problem)
+    }
+}
+
+/// `decoded` with its relative parameters read as position 0, which is always
+/// in bounds, so that parsing it leaves out the bounds that depend on the
+/// relative base.
+fn without_relative(mut decoded: Decoded) -> Decoded {
+    let opcode = &mut decoded.opcode;
+    let modes = [&mut opcode.arg0, &mut opcode.arg1, &mut opcode.arg2];
+    for (mode, value) in IntoIterator::into_iter(modes).zip(decoded.params.iter_mut()) {
+        if *mode == ParamMode::Relative {
+            *mode = ParamMode::Position;
+            *value = 0;
+        }
+    }
+    decoded
+}
+
+/// Verifies the reachable code of `memory` against `profile`. Findings are
+/// sorted by address.
+pub fn verify(memory: &[i64], profile: Profile) -> Vec<Finding> {
+    let mut scratch = memory.to_vec();
+    let mut program = Program::new(&mut scratch)
+        .with_profile(profile)
+        .with_decode_cache(false);
+
+    let mut findings = Vec::new();
+    let mut report = |address, problem: Problem| {
+        findings.push(Finding {
+            address,
+            severity: problem.severity(),
+            problem,
+        })
+    };
+    // Number of words of each reachable instruction, and the position mode
+    // writes
+    let mut spans = BTreeMap::new();
+    let mut writes = Vec::new();
+
+    let mut seen = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+    while let Some(address) = queue.pop_front() {
+        if !seen.insert(address) {
+            continue;
+        }
+
+        program.next_op = address;
+        let decoded = match program.decode() {
+            Ok(decoded) => decoded,
+            Err(Error::InvalidOp(_, e)) => {
+                spans.insert(address, 1);
+                report(address, Problem::Invalid(e));
+                continue;
+            }
+            Err(_) => {
+                report(address, Problem::Truncated);
+                continue;
+            }
+        };
+        let modes = decoded.opcode.modes();
+        let count = decoded.opcode.param_count().unwrap();
+        let len = count + 1;
+        spans.insert(address, 1 + decoded.available);
+        let op = match program.parse_op(&without_relative(decoded)) {
+            Ok(op) => op,
+            Err(Error::InvalidOp(_, e)) => {
+                report(address, Problem::Invalid(e));
+                continue;
+            }
+            Err(_) => {
+                report(address, Problem::Truncated);
+                continue;
+            }
+        };
+
+        let (condition, target) = match op {
+            Op::Terminate => continue,
+            Op::JumpIfTrue(params) | Op::JumpIfFalse(params) => params,
+            Op::Add((_, _, dest))
+            | Op::Mul((_, _, dest))
+            | Op::LessThan((_, _, dest))
+            | Op::Equals((_, _, dest))
+            | Op::Input(dest) => {
+                // Overwriting its own words once it has run is harmless
+                let own = (address..address + len).contains(&dest);
+                if modes[count - 1] == ParamMode::Position && !own {
+                    writes.push((address, dest));
+                }
+                queue.push_back(address + len);
+                continue;
+            }
+            Op::Output(_) | Op::AdjustRelativeBase(_) => {
+                queue.push_back(address + len);
+                continue;
+            }
+        };
+        // A jump on an immediate condition only goes one way
+        let (may_jump, may_fall_through) = match condition {
+            Param::Immediate(condition) => {
+                let jumps = matches!(op, Op::JumpIfTrue(_)) == (condition != 0);
+                (jumps, !jumps)
+            }
+            Param::Position(_) => (true, true),
+        };
+        if may_fall_through {
+            queue.push_back(address + len);
+        }
+        if may_jump {
+            match target {
+                Param::Immediate(target) if target < 0 => {
+                    report(address, Problem::NegativeJumpTarget(target))
+                }
+                Param::Immediate(target) => queue.push_back(target as usize),
+                Param::Position(_) => report(address, Problem::DynamicJump),
+            }
+        }
+    }
+
+    let code: BTreeSet<usize> = spans
+        .iter()
+        .flat_map(|(address, len)| *address..address + len)
+        .collect();
+    for (address, target) in &writes {
+        if code.contains(target) {
+            report(*address, Problem::WritesCode(*target));
+        }
+    }
+
+    // The program may patch an instruction before it runs
+    let written: BTreeSet<usize> = writes.iter().map(|(_, target)| *target).collect();
+    for finding in &mut findings {
+        let len = spans.get(&finding.address).copied().unwrap_or(1);
+        if (finding.address..finding.address + len).any(|word| written.contains(&word)) {
+            finding.severity = Severity::Warning;
+        }
+    }
+    findings.sort_by_key(|f| f.address);
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(memory: &[i64], profile: Profile) -> Vec<(usize, Problem)> {
+        verify(memory, profile)
+            .into_iter()
+            .map(|f| (f.address, f.problem))
+            .collect()
+    }
+
+    #[test]
+    fn valid_programs_pass() {
+        // Outputs whether the input equals 8
+        let memory = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        assert_eq!(verify(&memory, Profile::Day9), vec![]);
+        // Relative parameters are left to run time, whatever their offset
+        assert_eq!(
+            verify(&[204, -3, 21101, 1, 2, -7, 99], Profile::Day9),
+            vec![]
+        );
+        assert_eq!(
+            problems(&memory, Profile::Day2),
+            vec![(
+                0,
+                Problem::Invalid(OpError::OpCodeNotInProfile(3, Profile::Day2))
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_every_reachable_failure() {
+        // Branches to an immediate write target and an output out of
+        // bounds; the word at 9 is never reached
+        let mut memory = vec![1005, 20, 7, 11101, 1, 2, 3, 4, 50, 77];
+        memory.resize(21, 0);
+        let findings = verify(&memory, Profile::Day9);
+        assert_eq!(
+            findings.iter().map(|f| f.to_string()).collect::<Vec<_>>(),
+            vec![
+                "error at 3: parameter 3 is written to but in immediate mode",
+                "error at 7: parameter 1 points outside memory at 50",
+            ]
+        );
+        assert!(findings.iter().all(|f| f.severity == Severity::Error));
+    }
+
+    #[test]
+    fn warns_about_dynamic_jumps_and_self_modification() {
+        let memory = [1101, 9, 0, 5, 105, 1, 9, 99, 0, 7];
+        let findings = verify(&memory, Profile::Day9);
+        assert_eq!(
+            findings,
+            vec![
+                Finding {
+                    address: 0,
+                    severity: Severity::Warning,
+                    problem: Problem::WritesCode(5)
+                },
+                Finding {
+                    address: 4,
+                    severity: Severity::Warning,
+                    problem: Problem::DynamicJump
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_in_patched_code_are_warnings() {
+        // Writes the halt instruction before reaching it
+        let memory = [1101, 98, 1, 4, 0];
+        let findings = verify(&memory, Profile::Day9);
+        assert_eq!(
+            findings.iter().map(|f| f.to_string()).collect::<Vec<_>>(),
+            vec![
+                "warning at 0: writes to the instruction word at 4",
+                "warning at 4: invalid opcode 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_truncated_and_undecodable_code() {
+        assert_eq!(
+            problems(&[1, 0, 0], Profile::Day9),
+            vec![(0, Problem::Truncated)]
+        );
+        assert_eq!(
+            problems(&[104, 1], Profile::Day9),
+            vec![(2, Problem::Truncated)]
+        );
+        assert_eq!(
+            problems(&[123], Profile::Day9),
+            vec![(0, Problem::Invalid(OpError::UnrecognisedOpCode(23)))]
+        );
+        assert_eq!(
+            problems(&[1105, 1, -1], Profile::Day9),
+            vec![(0, Problem::NegativeJumpTarget(-1))]
+        );
+        // Mode digits other than 0, 1 and 2
+        assert_eq!(
+            problems(&[30001, 0, 0, 0], Profile::Day9),
+            vec![(
+                0,
+                Problem::Invalid(OpError::InvalidOpCode(
+                    crate::OpCodeError::InvalidParamMode(
+                        2,
+                        crate::ParamModeError::UnrecognisedMode(3)
+                    )
+                ))
+            )]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Drives the `intcode-dap` binary with a scripted client, over standard
+//! input and output and over TCP.
+
+use std::collections::VecDeque;
+use std::fs::read_to_string;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use intcode::dap::json::{object, Value};
+use intcode::dap::{read_message, write_message};
+
+// Prints the factorial of each input until it reads a zero
+const FACTORIALS: [i64; 28] = [
+    3, 27, 1006, 27, 25, 1101, 1, 0, 26, 2, 26, 27, 26, 1001, 27, -1, 27, 1005, 27, 9, 4, 26, 1105,
+    1, 0, 99, 0, 0,
+];
+
+struct Client {
+    child: Child,
+    writer: Box<dyn Write>,
+    reader: BufReader<Box<dyn Read>>,
+    seq: i64,
+    /// Events read while waiting for something else.
+    events: VecDeque<Value>,
+}
+
+impl Client {
+    fn stdio() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_intcode-dap"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let writer = Box::new(child.stdin.take().unwrap());
+        let reader = Box::new(child.stdout.take().unwrap());
+        Client::new(child, writer, reader)
+    }
+
+    fn tcp() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_intcode-dap"))
+            .args(["--port", "0"])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stderr.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let address = line.trim().strip_prefix("listening on ").unwrap();
+        let stream = TcpStream::connect(address).unwrap();
+        let reader = Box::new(stream.try_clone().unwrap());
+        Client::new(child, Box::new(stream), reader)
+    }
+
+    fn new(child: Child, writer: Box<dyn Write>, reader: Box<dyn Read>) -> Client {
+        Client {
+            child,
+            writer,
+            reader: BufReader::new(reader),
+            seq: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    fn read(&mut self) -> Value {
+        read_message(&mut self.reader)
+            .unwrap()
+            .expect("adapter closed the stream")
+    }
+
+    /// Sends a request and returns the body of its response, or the error
+    /// message if it failed.
+    fn try_request(&mut self, command: &str, arguments: Value) -> Result<Value, String> {
+        self.seq += 1;
+        let request = object(vec![
+            ("seq", self.seq.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", arguments),
+        ]);
+        write_message(&mut self.writer, &request).unwrap();
+
+        loop {
+            let message = self.read();
+            match message.get("type").as_str() {
+                Some("response") => {
+                    assert_eq!(message.get("request_seq").as_i64(), Some(self.seq));
+                    assert_eq!(message.get("command").as_str(), Some(command));
+                    return match message.get("success").as_bool() {
+                        Some(true) => Ok(message.get("body").clone()),
+                        _ => Err(message.get("message").as_str().unwrap().to_string()),
+                    };
+                }
+                Some("event") => self.events.push_back(message),
+                _ => panic!("unexpected message {}", message),
+            }
+        }
+    }
+
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.try_request(command, arguments)
+            .unwrap_or_else(|e| panic!("{} failed: {}", command, e))
+    }
+
+    /// The body of the next event, which must be `name`.
+    fn event(&mut self, name: &str) -> Value {
+        let event = self.events.pop_front().unwrap_or_else(|| self.read());
+        assert_eq!(event.get("event").as_str(), Some(name), "{}", event);
+        event.get("body").clone()
+    }
+
+    fn stopped(&mut self, reason: &str) -> Value {
+        let body = self.event("stopped");
+        assert_eq!(body.get("reason").as_str(), Some(reason), "{}", body);
+        body
+    }
+
+    fn start(&mut self, launch: Value) {
+        let capabilities =
+            self.request("initialize", object(vec![("adapterID", "intcode".into())]));
+        assert_eq!(
+            capabilities.get("supportsDisassembleRequest").as_bool(),
+            Some(true)
+        );
+        self.request("launch", launch);
+        self.event("initialized");
+    }
+
+    fn frame(&mut self) -> Value {
+        let trace = self.request("stackTrace", object(vec![("threadId", 1.into())]));
+        trace.get("stackFrames").as_array().unwrap()[0].clone()
+    }
+
+    fn variables(&mut self, reference: i64) -> Vec<(String, String)> {
+        let body = self.request(
+            "variables",
+            object(vec![("variablesReference", reference.into())]),
+        );
+        body.get("variables")
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| {
+                (
+                    v.get("name").as_str().unwrap().to_string(),
+                    v.get("value").as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn evaluate(&mut self, expression: &str) -> String {
+        let body = self.request(
+            "evaluate",
+            object(vec![
+                ("expression", expression.into()),
+                ("context", "repl".into()),
+            ]),
+        );
+        body.get("result").as_str().unwrap().to_string()
+    }
+
+    fn finish(mut self) {
+        self.request("disconnect", object(vec![]));
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn image(words: &[i64]) -> Value {
+    words
+        .iter()
+        .map(|w| Value::from(*w))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[test]
+fn source_breakpoints_and_stepping() {
+    let mut client = Client::stdio();
+    client.start(object(vec![
+        ("image", image(&FACTORIALS)),
+        ("input", image(&[3, 0])),
+    ]));
+
+    // Line 6 of the listing is the loop's `JNZ [27], 9` at 17
+    let body = client.request(
+        "setBreakpoints",
+        object(vec![
+            ("source", object(vec![("sourceReference", 1.into())])),
+            (
+                "breakpoints",
+                vec![
+                    object(vec![("line", 6.into())]),
+                    object(vec![("line", 40.into())]),
+                ]
+                .into(),
+            ),
+        ]),
+    );
+    let breakpoints = body.get("breakpoints").as_array().unwrap();
+    assert_eq!(breakpoints[0].get("verified").as_bool(), Some(true));
+    assert_eq!(
+        breakpoints[0].get("instructionReference").as_str(),
+        Some("17")
+    );
+    assert_eq!(breakpoints[1].get("verified").as_bool(), Some(false));
+
+    client.request("configurationDone", object(vec![]));
+    client.stopped("breakpoint");
+    let frame = client.frame();
+    assert_eq!(frame.get("name").as_str(), Some("17: JNZ [27], 9"));
+    assert_eq!(frame.get("line").as_i64(), Some(6));
+    assert_eq!(
+        frame.get("instructionPointerReference").as_str(),
+        Some("17")
+    );
+
+    let registers = client.variables(1);
+    assert_eq!(registers[0], ("ip".to_string(), "17".to_string()));
+    assert_eq!(registers[3], ("input".to_string(), "[0]".to_string()));
+
+    client.request("next", object(vec![("threadId", 1.into())]));
+    client.stopped("step");
+    assert_eq!(client.frame().get("line").as_i64(), Some(4));
+    assert_eq!(client.evaluate("[26]"), "3");
+    assert_eq!(client.evaluate("ip"), "9");
+    assert_eq!(
+        client.evaluate("diff"),
+        "    26:            0 -> 3            DATA 0\n\
+         \x20   27:            0 -> 2            DATA 0\n"
+    );
+    assert!(client
+        .evaluate("dump")
+        .starts_with("     0 |    3   27           | IN [27]\n"));
+
+    client.request(
+        "setBreakpoints",
+        object(vec![
+            ("source", object(vec![("sourceReference", 1.into())])),
+            ("breakpoints", Vec::new().into()),
+        ]),
+    );
+    client.request("continue", object(vec![("threadId", 1.into())]));
+    assert_eq!(client.event("output").get("output").as_str(), Some("6\n"));
+    assert_eq!(client.event("exited").get("exitCode").as_i64(), Some(0));
+    client.event("terminated");
+    client.finish();
+}
+
+#[test]
+fn instruction_breakpoints_disassembly_and_console_input() {
+    let mut client = Client::stdio();
+    client.start(object(vec![
+        ("image", image(&FACTORIALS)),
+        ("stopOnEntry", true.into()),
+    ]));
+    let body = client.request(
+        "setInstructionBreakpoints",
+        object(vec![(
+            "breakpoints",
+            vec![
+                object(vec![("instructionReference", "20".into())]),
+                object(vec![("instructionReference", "99".into())]),
+            ]
+            .into(),
+        )]),
+    );
+    let breakpoints = body.get("breakpoints").as_array().unwrap();
+    assert_eq!(breakpoints[0].get("verified").as_bool(), Some(true));
+    assert_eq!(breakpoints[1].get("verified").as_bool(), Some(false));
+    client.request("configurationDone", object(vec![]));
+    client.stopped("entry");
+
+    let body = client.request(
+        "disassemble",
+        object(vec![
+            ("memoryReference", "0".into()),
+            ("instructionOffset", (-1).into()),
+            ("instructionCount", 3.into()),
+        ]),
+    );
+    let instructions = body.get("instructions").as_array().unwrap();
+    assert_eq!(
+        instructions[0].get("presentationHint").as_str(),
+        Some("invalid")
+    );
+    assert_eq!(instructions[1].get("instruction").as_str(), Some("IN [27]"));
+    assert_eq!(instructions[2].get("address").as_str(), Some("2"));
+    assert_eq!(
+        instructions[2].get("instructionBytes").as_str(),
+        Some("1006,27,25")
+    );
+
+    // Without input the program stops on the failing instruction
+    client.request("continue", object(vec![("threadId", 1.into())]));
+    let body = client.stopped("exception");
+    assert_eq!(
+        body.get("text").as_str(),
+        Some("expected input at 0, but it's empty")
+    );
+
+    assert_eq!(client.evaluate("input 4"), "queued 1 input values");
+    client.request("continue", object(vec![("threadId", 1.into())]));
+    client.stopped("breakpoint");
+    assert_eq!(client.evaluate("ip"), "20");
+
+    let windows = client.variables(2);
+    assert_eq!(windows, vec![("0..27".to_string(), String::new())]);
+    let cells = client.variables(1000);
+    assert_eq!(cells.len(), 28);
+    assert_eq!(cells[26], ("[26]".to_string(), "24".to_string()));
+
+    client.request("continue", object(vec![("threadId", 1.into())]));
+    assert_eq!(client.event("output").get("output").as_str(), Some("24\n"));
+    client.stopped("exception");
+    assert_eq!(
+        client.try_request("evaluate", object(vec![("expression", "[99]".into())])),
+        Err("address 99 is outside memory".to_string())
+    );
+    client.finish();
+}
+
+#[test]
+fn running_programs_can_be_paused() {
+    let mut client = Client::stdio();
+    client.start(object(vec![("image", image(&[1105, 1, 0]))]));
+    client.request("configurationDone", object(vec![]));
+    client.request("pause", object(vec![("threadId", 1.into())]));
+    client.stopped("pause");
+    assert_eq!(client.frame().get("name").as_str(), Some("0: JNZ 1, 0"));
+
+    client.request("terminate", object(vec![]));
+    client.event("terminated");
+    client.finish();
+}
+
+#[test]
+fn serves_program_files_over_tcp() {
+    let path = "tests/transpiled/compare.txt";
+    let memory: Vec<i64> = read_to_string(path)
+        .unwrap()
+        .trim()
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect();
+
+    let mut client = Client::tcp();
+    client.start(object(vec![
+        ("program", path.into()),
+        ("stopOnEntry", true.into()),
+    ]));
+    client.request("configurationDone", object(vec![]));
+    client.stopped("entry");
+
+    let frame = client.frame();
+    assert_eq!(
+        frame.get("source").get("name").as_str(),
+        Some("compare.txt.intcode")
+    );
+    let body = client.request("source", object(vec![("sourceReference", 1.into())]));
+    assert_eq!(
+        body.get("content").as_str(),
+        Some(intcode::disasm::listing(&memory).as_str())
+    );
+    client.finish();
+}
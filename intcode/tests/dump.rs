@@ -0,0 +1,36 @@
+//! Runs the `intcode-dump` binary on the transpiler's test images.
+
+use std::process::Command;
+
+fn dump(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_intcode-dump"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn dumps_one_image() {
+    let (status, text) = dump(&["tests/transpiled/undecodable.txt"]);
+    assert_eq!(status, Some(0));
+    assert!(text.starts_with("     0 | 104   5         | OUT 5\n"));
+}
+
+#[test]
+fn diffs_two_images() {
+    let image = "tests/transpiled/undecodable.txt";
+    assert_eq!(dump(&[image, image]), (Some(0), String::new()));
+
+    let (status, text) = dump(&[image, "tests/transpiled/compare.txt"]);
+    assert_eq!(status, Some(1));
+    assert!(text.starts_with("     0:          104 -> "));
+}
+
+#[test]
+fn rejects_other_arguments() {
+    assert_eq!(dump(&[]), (Some(2), String::new()));
+}
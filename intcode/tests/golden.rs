@@ -0,0 +1,22 @@
+//! Runs every manifest under `tests/golden`, see `intcode::manifest`.
+//!
+//! `GOLDEN_FILTER=<filter> cargo test --test golden` runs only the cases
+//! whose names contain the filter.
+
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let filter = env::var("GOLDEN_FILTER").unwrap_or_default();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    match intcode::manifest::run_dir(dir, &filter, io::stdout().lock()) {
+        Ok(true) => {}
+        Ok(false) => process::exit(101),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(101);
+        }
+    }
+}
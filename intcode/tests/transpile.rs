@@ -0,0 +1,76 @@
+//! Runs the checked in output of `intcode::transpile` against `Program` on the
+//! same inputs. The sources are kept up to date by a test in the crate.
+
+use intcode::{Error, Program};
+
+mod transpiled {
+    include!("transpiled/compare.rs");
+    include!("transpiled/fib.rs");
+    include!("transpiled/self_modifying.rs");
+    include!("transpiled/undecodable.rs");
+}
+
+type Transpiled =
+    fn(&mut dyn FnMut() -> Option<i64>, &mut dyn FnMut(i64)) -> Result<Vec<i64>, Error>;
+
+fn parse(image: &str) -> Vec<i64> {
+    image
+        .trim()
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect()
+}
+
+fn check(image: &str, f: Transpiled, input: &[i64]) {
+    let mut expected_memory = parse(image);
+    let (expected_result, expected_output) = {
+        let mut program = Program::new(&mut expected_memory).add_input(input);
+        let result = program.run_with_limit(1_000_000);
+        (result, program.output().to_vec())
+    };
+
+    let mut remaining = input.iter();
+    let mut output = Vec::new();
+    let result = f(&mut || remaining.next().copied(), &mut |v| output.push(v));
+
+    assert_eq!(output, expected_output, "output for input {:?}", input);
+    match result {
+        Ok(memory) => {
+            assert_eq!(expected_result, Ok(()), "result for input {:?}", input);
+            assert_eq!(memory, expected_memory, "memory for input {:?}", input);
+        }
+        Err(e) => assert_eq!(expected_result, Err(e), "result for input {:?}", input),
+    }
+}
+
+#[test]
+fn compare_matches_interpreter() {
+    let image = include_str!("transpiled/compare.txt");
+    for input in -2..20 {
+        check(image, transpiled::compare, &[input]);
+    }
+    check(image, transpiled::compare, &[]);
+}
+
+#[test]
+fn fib_matches_interpreter() {
+    let image = include_str!("transpiled/fib.txt");
+    check(image, transpiled::fib, &[1, 2, 10, 15, 0]);
+    check(image, transpiled::fib, &[0]);
+    // Runs out of input inside the loop
+    check(image, transpiled::fib, &[5, 6]);
+    // Deep enough recursion to run off the end of the stack
+    check(image, transpiled::fib, &[100, 0]);
+}
+
+#[test]
+fn self_modifying_matches_interpreter() {
+    let image = include_str!("transpiled/self_modifying.txt");
+    check(image, transpiled::self_modifying, &[]);
+}
+
+#[test]
+fn undecodable_instructions_resume_where_they_are() {
+    let image = include_str!("transpiled/undecodable.txt");
+    check(image, transpiled::undecodable, &[]);
+}
@@ -0,0 +1,76 @@
+/// Translated from a 47 word Intcode image by `intcode::transpile`.
+#[allow(unused_mut, unused_variables, unreachable_code, dead_code)]
+#[allow(clippy::all)]
+pub fn compare(
+    input: &mut dyn FnMut() -> Option<i64>,
+    output: &mut dyn FnMut(i64),
+) -> Result<Vec<i64>, intcode::Error> {
+    fn is_code(a: usize) -> bool {
+        matches!(a, 0..=18 | 22..=44 | 46..=46)
+    }
+
+    let mut m: Vec<i64> = vec![
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+        1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+        999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+    ];
+    let mut pc: usize = 0;
+    let mut rb: i64 = 0;
+    loop {
+        match pc {
+            0 => {
+                // 0: IN [21]
+                m[21] = match input() { Some(v) => v, None => { pc = 0; break; } };
+                // 2: EQ [21], 8, [20]
+                m[20] = (m[21] == 8) as i64;
+                // 6: JNZ [20], 22
+                pc = if m[20] != 0 { 22 } else { 9 };
+            }
+            9 => {
+                // 9: LT 8, [21], [20]
+                m[20] = (8 < m[21]) as i64;
+                // 13: JZ [20], 31
+                pc = if m[20] == 0 { 31 } else { 16 };
+            }
+            16 => {
+                // 16: JZ 0, 36
+                pc = if 0 == 0 { 36 } else { 19 };
+            }
+            19 => {
+                break;
+            }
+            22 => {
+                // 22: MUL [21], 125, [20]
+                m[20] = i64::wrapping_mul(m[21], 125);
+                // 26: OUT [20]
+                output(m[20]);
+                // 28: JNZ 1, 46
+                pc = if 1 != 0 { 46 } else { 31 };
+            }
+            31 => {
+                // 31: OUT 999
+                output(999);
+                // 33: JNZ 1, 46
+                pc = if 1 != 0 { 46 } else { 36 };
+            }
+            36 => {
+                // 36: ADD 1000, 1, [20]
+                m[20] = i64::wrapping_add(1000, 1);
+                // 40: OUT [20]
+                output(m[20]);
+                // 42: JNZ 1, 46
+                pc = if 1 != 0 { 46 } else { 45 };
+            }
+            45 => {
+                break;
+            }
+            46 => {
+                // 46: HALT
+                return Ok(m);
+            }
+            _ => break,
+        }
+    }
+    intcode::transpile::interpret(&mut m, pc, rb, input, output)?;
+    Ok(m)
+}
@@ -0,0 +1,258 @@
+/// Translated from a 202 word Intcode image by `intcode::transpile`.
+#[allow(unused_mut, unused_variables, unreachable_code, dead_code)]
+#[allow(clippy::all)]
+pub fn fib(
+    input: &mut dyn FnMut() -> Option<i64>,
+    output: &mut dyn FnMut(i64),
+) -> Result<Vec<i64>, intcode::Error> {
+    fn is_code(a: usize) -> bool {
+        matches!(a, 0..=135)
+    }
+
+    let mut m: Vec<i64> = vec![
+        109, 138, 203, 0, 1201, 0, 0, 137, 21008, 137, 0, 0, 21208, 0, 0, 0,
+        1206, 0, 49, 21001, 137, 0, 1, 21101, 32, 0, 0, 109, 0, 1105, 1, 50,
+        109, 0, 21001, 136, 0, 0, 204, 0, 203, 0, 1201, 0, 0, 137, 1105, 1,
+        8, 99, 21207, 1, 2, 2, 1206, 2, 64, 1201, 1, 0, 136, 2105, 1, 0,
+        21102, 1, -1, 4, 22201, 1, 4, 4, 21201, 4, 0, 3, 21101, 85, 0, 2,
+        109, 2, 1105, 1, 50, 109, -2, 21001, 136, 0, 2, 21102, 2, -1, 5, 22201,
+        1, 5, 5, 21201, 5, 0, 4, 21101, 112, 0, 3, 109, 3, 1105, 1, 50,
+        109, -3, 21001, 136, 0, 3, 22201, 2, 3, 2, 1201, 2, 0, 136, 2105, 1,
+        0, 1101, 0, 0, 136, 2105, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut pc: usize = 0;
+    let mut rb: i64 = 0;
+    loop {
+        match pc {
+            0 => {
+                // 0: ARB 138
+                rb = rb.wrapping_add(138);
+                // 2: IN [rb]
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 2; break; }
+                m[a0 as usize] = match input() { Some(v) => v, None => { pc = 2; break; } };
+                if is_code(a0 as usize) { pc = 4; break; }
+                // 4: ADD [rb], 0, [137]
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 4; break; }
+                m[137] = i64::wrapping_add(m[a0 as usize], 0);
+                pc = 8;
+            }
+            8 => {
+                // 8: EQ [137], 0, [rb]
+                let a2 = rb.wrapping_add(0);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 8; break; }
+                m[a2 as usize] = (m[137] == 0) as i64;
+                if is_code(a2 as usize) { pc = 12; break; }
+                // 12: EQ [rb], 0, [rb]
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 12; break; }
+                let a2 = rb.wrapping_add(0);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 12; break; }
+                m[a2 as usize] = (m[a0 as usize] == 0) as i64;
+                if is_code(a2 as usize) { pc = 16; break; }
+                // 16: JZ [rb], 49
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 16; break; }
+                pc = if m[a0 as usize] == 0 { 49 } else { 19 };
+            }
+            19 => {
+                // 19: ADD [137], 0, [rb+1]
+                let a2 = rb.wrapping_add(1);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 19; break; }
+                m[a2 as usize] = i64::wrapping_add(m[137], 0);
+                if is_code(a2 as usize) { pc = 23; break; }
+                // 23: ADD 32, 0, [rb]
+                let a2 = rb.wrapping_add(0);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 23; break; }
+                m[a2 as usize] = i64::wrapping_add(32, 0);
+                if is_code(a2 as usize) { pc = 27; break; }
+                // 27: ARB 0
+                rb = rb.wrapping_add(0);
+                // 29: JNZ 1, 50
+                pc = if 1 != 0 { 50 } else { 32 };
+            }
+            32 => {
+                // 32: ARB 0
+                rb = rb.wrapping_add(0);
+                // 34: ADD [136], 0, [rb]
+                let a2 = rb.wrapping_add(0);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 34; break; }
+                m[a2 as usize] = i64::wrapping_add(m[136], 0);
+                if is_code(a2 as usize) { pc = 38; break; }
+                // 38: OUT [rb]
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 38; break; }
+                output(m[a0 as usize]);
+                // 40: IN [rb]
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 40; break; }
+                m[a0 as usize] = match input() { Some(v) => v, None => { pc = 40; break; } };
+                if is_code(a0 as usize) { pc = 42; break; }
+                // 42: ADD [rb], 0, [137]
+                let a0 = rb.wrapping_add(0);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 42; break; }
+                m[137] = i64::wrapping_add(m[a0 as usize], 0);
+                // 46: JNZ 1, 8
+                pc = if 1 != 0 { 8 } else { 49 };
+            }
+            49 => {
+                // 49: HALT
+                return Ok(m);
+            }
+            50 => {
+                // 50: LT [rb+1], 2, [rb+2]
+                let a0 = rb.wrapping_add(1);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 50; break; }
+                let a2 = rb.wrapping_add(2);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 50; break; }
+                m[a2 as usize] = (m[a0 as usize] < 2) as i64;
+                if is_code(a2 as usize) { pc = 54; break; }
+                // 54: JZ [rb+2], 64
+                let a0 = rb.wrapping_add(2);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 54; break; }
+                pc = if m[a0 as usize] == 0 { 64 } else { 57 };
+            }
+            57 => {
+                // 57: ADD [rb+1], 0, [136]
+                let a0 = rb.wrapping_add(1);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 57; break; }
+                m[136] = i64::wrapping_add(m[a0 as usize], 0);
+                // 61: JNZ 1, [rb]
+                let a1 = rb.wrapping_add(0);
+                if a1 < 0 || a1 as usize >= m.len() { pc = 61; break; }
+                if 1 != 0 {
+                    if m[a1 as usize] < 0 { pc = 61; break; }
+                    pc = m[a1 as usize] as usize;
+                } else {
+                    pc = 64;
+                }
+            }
+            64 => {
+                // 64: MUL 1, -1, [rb+4]
+                let a2 = rb.wrapping_add(4);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 64; break; }
+                m[a2 as usize] = i64::wrapping_mul(1, -1);
+                if is_code(a2 as usize) { pc = 68; break; }
+                // 68: ADD [rb+1], [rb+4], [rb+4]
+                let a0 = rb.wrapping_add(1);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 68; break; }
+                let a1 = rb.wrapping_add(4);
+                if a1 < 0 || a1 as usize >= m.len() { pc = 68; break; }
+                let a2 = rb.wrapping_add(4);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 68; break; }
+                m[a2 as usize] = i64::wrapping_add(m[a0 as usize], m[a1 as usize]);
+                if is_code(a2 as usize) { pc = 72; break; }
+                // 72: ADD [rb+4], 0, [rb+3]
+                let a0 = rb.wrapping_add(4);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 72; break; }
+                let a2 = rb.wrapping_add(3);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 72; break; }
+                m[a2 as usize] = i64::wrapping_add(m[a0 as usize], 0);
+                if is_code(a2 as usize) { pc = 76; break; }
+                // 76: ADD 85, 0, [rb+2]
+                let a2 = rb.wrapping_add(2);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 76; break; }
+                m[a2 as usize] = i64::wrapping_add(85, 0);
+                if is_code(a2 as usize) { pc = 80; break; }
+                // 80: ARB 2
+                rb = rb.wrapping_add(2);
+                // 82: JNZ 1, 50
+                pc = if 1 != 0 { 50 } else { 85 };
+            }
+            85 => {
+                // 85: ARB -2
+                rb = rb.wrapping_add(-2);
+                // 87: ADD [136], 0, [rb+2]
+                let a2 = rb.wrapping_add(2);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 87; break; }
+                m[a2 as usize] = i64::wrapping_add(m[136], 0);
+                if is_code(a2 as usize) { pc = 91; break; }
+                // 91: MUL 2, -1, [rb+5]
+                let a2 = rb.wrapping_add(5);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 91; break; }
+                m[a2 as usize] = i64::wrapping_mul(2, -1);
+                if is_code(a2 as usize) { pc = 95; break; }
+                // 95: ADD [rb+1], [rb+5], [rb+5]
+                let a0 = rb.wrapping_add(1);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 95; break; }
+                let a1 = rb.wrapping_add(5);
+                if a1 < 0 || a1 as usize >= m.len() { pc = 95; break; }
+                let a2 = rb.wrapping_add(5);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 95; break; }
+                m[a2 as usize] = i64::wrapping_add(m[a0 as usize], m[a1 as usize]);
+                if is_code(a2 as usize) { pc = 99; break; }
+                // 99: ADD [rb+5], 0, [rb+4]
+                let a0 = rb.wrapping_add(5);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 99; break; }
+                let a2 = rb.wrapping_add(4);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 99; break; }
+                m[a2 as usize] = i64::wrapping_add(m[a0 as usize], 0);
+                if is_code(a2 as usize) { pc = 103; break; }
+                // 103: ADD 112, 0, [rb+3]
+                let a2 = rb.wrapping_add(3);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 103; break; }
+                m[a2 as usize] = i64::wrapping_add(112, 0);
+                if is_code(a2 as usize) { pc = 107; break; }
+                // 107: ARB 3
+                rb = rb.wrapping_add(3);
+                // 109: JNZ 1, 50
+                pc = if 1 != 0 { 50 } else { 112 };
+            }
+            112 => {
+                // 112: ARB -3
+                rb = rb.wrapping_add(-3);
+                // 114: ADD [136], 0, [rb+3]
+                let a2 = rb.wrapping_add(3);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 114; break; }
+                m[a2 as usize] = i64::wrapping_add(m[136], 0);
+                if is_code(a2 as usize) { pc = 118; break; }
+                // 118: ADD [rb+2], [rb+3], [rb+2]
+                let a0 = rb.wrapping_add(2);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 118; break; }
+                let a1 = rb.wrapping_add(3);
+                if a1 < 0 || a1 as usize >= m.len() { pc = 118; break; }
+                let a2 = rb.wrapping_add(2);
+                if a2 < 0 || a2 as usize >= m.len() { pc = 118; break; }
+                m[a2 as usize] = i64::wrapping_add(m[a0 as usize], m[a1 as usize]);
+                if is_code(a2 as usize) { pc = 122; break; }
+                // 122: ADD [rb+2], 0, [136]
+                let a0 = rb.wrapping_add(2);
+                if a0 < 0 || a0 as usize >= m.len() { pc = 122; break; }
+                m[136] = i64::wrapping_add(m[a0 as usize], 0);
+                // 126: JNZ 1, [rb]
+                let a1 = rb.wrapping_add(0);
+                if a1 < 0 || a1 as usize >= m.len() { pc = 126; break; }
+                if 1 != 0 {
+                    if m[a1 as usize] < 0 { pc = 126; break; }
+                    pc = m[a1 as usize] as usize;
+                } else {
+                    pc = 129;
+                }
+            }
+            129 => {
+                // 129: ADD 0, 0, [136]
+                m[136] = i64::wrapping_add(0, 0);
+                // 133: JNZ 1, [rb]
+                let a1 = rb.wrapping_add(0);
+                if a1 < 0 || a1 as usize >= m.len() { pc = 133; break; }
+                if 1 != 0 {
+                    if m[a1 as usize] < 0 { pc = 133; break; }
+                    pc = m[a1 as usize] as usize;
+                } else {
+                    pc = 136;
+                }
+            }
+            136 => {
+                break;
+            }
+            _ => break,
+        }
+    }
+    intcode::transpile::interpret(&mut m, pc, rb, input, output)?;
+    Ok(m)
+}
@@ -0,0 +1,30 @@
+/// Translated from a 7 word Intcode image by `intcode::transpile`.
+#[allow(unused_mut, unused_variables, unreachable_code, dead_code)]
+#[allow(clippy::all)]
+pub fn self_modifying(
+    input: &mut dyn FnMut() -> Option<i64>,
+    output: &mut dyn FnMut(i64),
+) -> Result<Vec<i64>, intcode::Error> {
+    fn is_code(a: usize) -> bool {
+        matches!(a, 0..=6)
+    }
+
+    let mut m: Vec<i64> = vec![
+        1101, 3, 1, 5, 104, 7, 99,
+    ];
+    let mut pc: usize = 0;
+    let mut rb: i64 = 0;
+    loop {
+        match pc {
+            0 => {
+                // 0: ADD 3, 1, [5]
+                m[5] = i64::wrapping_add(3, 1);
+                pc = 4;
+                break;
+            }
+            _ => break,
+        }
+    }
+    intcode::transpile::interpret(&mut m, pc, rb, input, output)?;
+    Ok(m)
+}
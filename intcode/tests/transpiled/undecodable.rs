@@ -0,0 +1,30 @@
+/// Translated from a 7 word Intcode image by `intcode::transpile`.
+#[allow(unused_mut, unused_variables, unreachable_code, dead_code)]
+#[allow(clippy::all)]
+pub fn undecodable(
+    input: &mut dyn FnMut() -> Option<i64>,
+    output: &mut dyn FnMut(i64),
+) -> Result<Vec<i64>, intcode::Error> {
+    fn is_code(a: usize) -> bool {
+        matches!(a, 0..=1)
+    }
+
+    let mut m: Vec<i64> = vec![
+        104, 5, 1, 0, 0, 100, 99,
+    ];
+    let mut pc: usize = 0;
+    let mut rb: i64 = 0;
+    loop {
+        match pc {
+            0 => {
+                // 0: OUT 5
+                output(5);
+                pc = 2;
+                break;
+            }
+            _ => break,
+        }
+    }
+    intcode::transpile::interpret(&mut m, pc, rb, input, output)?;
+    Ok(m)
+}
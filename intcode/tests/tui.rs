@@ -0,0 +1,28 @@
+//! Runs the `intcode-tui` binary without a terminal, where it reads
+//! commands line by line.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn falls_back_to_lines_without_a_terminal() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_intcode-tui"))
+        .args(["tests/transpiled/compare.txt", "8"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"s\nc\nq\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let text = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "stopped on entry");
+    assert!(lines[1].starts_with("ip 0  rb 0  steps 0: IN "));
+    assert_eq!(lines[2], "stepped");
+    assert!(!text.contains('\x1b'));
+    // Equal to 8
+    assert!(lines.contains(&"halted"));
+    assert_eq!(lines.last(), Some(&"output 1000"));
+}
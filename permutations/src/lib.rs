@@ -74,8 +74,7 @@ impl<'a, V> Iterator for UniquePermutations<'a, V> {
         let next_item: Vec<_> = self
             .stack
             .iter()
-            .map(|(items, current)| items.get(*current).cloned())
-            .flatten()
+            .filter_map(|(items, current)| items.get(*current).cloned())
             .collect();
 
         if let Some((_, current)) = self.stack.last_mut() {